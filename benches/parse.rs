@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+use macky_xml::Parser;
+
+fn attribute_heavy(records: usize) -> String {
+    let mut input = String::from("<root>");
+    for i in 0..records {
+        input.push_str(&format!(
+            r#"<record id="{}" name="record number {}" category='c{}' description="a fairly long attribute value to scan through" flag="true"/>"#,
+            i, i, i % 7
+        ));
+    }
+    input.push_str("</root>");
+    input
+}
+
+fn text_heavy(records: usize) -> String {
+    let mut input = String::from("<root>");
+    for i in 0..records {
+        input.push_str(&format!("<p>paragraph {} with a reasonable amount of running text in it, like a scraped page</p>\n", i));
+    }
+    input.push_str("</root>");
+    input
+}
+
+fn bench(name: &str, input: &str, iterations: u32) {
+    let parser = Parser::default();
+    assert!(parser.complete_element(input).is_ok(), "{} input failed to parse", name);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let _ = parser.complete_element(input);
+    }
+    let per_iteration = start.elapsed() / iterations;
+    let throughput = input.len() as f64 / per_iteration.max(Duration::from_nanos(1)).as_secs_f64() / 1e6;
+    println!("{:<16} {:>10.2?}/iter {:>8.1} MB/s", name, per_iteration, throughput);
+}
+
+fn main() {
+    bench("attribute_heavy", &attribute_heavy(2_000), 20);
+    bench("text_heavy", &text_heavy(2_000), 20);
+    #[cfg(feature = "testutil")]
+    bench("synthetic_1m", &macky_xml::testutil::Synthetic::new().depth(5).breadth(16).generate(), 2);
+}
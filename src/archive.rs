@@ -0,0 +1,298 @@
+use std::io::{self, Read, Seek};
+
+use flate2::read::GzDecoder;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::{Document, Error, Parser};
+
+const BLOCK: usize = 512;
+
+/// Iterator over the `.xml` files of a tar archive, yielding each file's path and parsed
+/// document. Other entries are skipped. Files are decoded as `complete_document_bytes` does.
+///
+/// A file that does not parse is reported and skipped; a corrupt or truncated archive ends
+/// the iteration with an `io::Error`.
+pub struct TarDocuments<'p, R> {
+    parser: &'p Parser,
+    archive: R,
+    done: bool,
+}
+
+pub fn tar_documents<R: Read>(parser: &Parser, archive: R) -> TarDocuments<'_, R> {
+    TarDocuments {
+        parser,
+        archive,
+        done: false,
+    }
+}
+
+/// `tar_documents` for a gzip-compressed `.tar.gz`.
+pub fn tar_gz_documents<R: Read>(parser: &Parser, archive: R) -> TarDocuments<'_, GzDecoder<R>> {
+    tar_documents(parser, GzDecoder::new(archive))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn field(header: &[u8]) -> &[u8] {
+    let end = header.iter().position(|&byte| byte == 0).unwrap_or(header.len());
+    &header[..end]
+}
+
+/// Octal, or base-256 with the high bit set for sizes over 8 GB.
+fn number(header: &[u8]) -> io::Result<u64> {
+    if header.first().is_some_and(|byte| byte & 0x80 != 0) {
+        return Ok(header[1..].iter().fold(0, |n, &byte| n << 8 | u64::from(byte)));
+    }
+    let text = std::str::from_utf8(field(header)).map_err(|_| invalid("malformed tar header"))?;
+    u64::from_str_radix(text.trim(), 8).map_err(|_| invalid("malformed tar header"))
+}
+
+fn path(header: &[u8]) -> String {
+    let name = String::from_utf8_lossy(field(&header[0..100]));
+    let prefix = String::from_utf8_lossy(field(&header[345..500]));
+    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+        format!("{}/{}", prefix, name)
+    } else {
+        name.into_owned()
+    }
+}
+
+/// The `path` record of a pax extended header, whose records look like `"27 path=dir/a.xml\n"`.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, record)| record))
+        .find_map(|record| record.strip_prefix("path="))
+        .map(str::to_string)
+}
+
+impl<'p, R: Read> TarDocuments<'p, R> {
+    fn read_data(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        (&mut self.archive).take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(invalid("truncated tar archive"));
+        }
+        self.skip_padding(size)?;
+        Ok(data)
+    }
+
+    fn skip_data(&mut self, size: u64) -> io::Result<()> {
+        if io::copy(&mut (&mut self.archive).take(size), &mut io::sink())? < size {
+            return Err(invalid("truncated tar archive"));
+        }
+        self.skip_padding(size)
+    }
+
+    fn skip_padding(&mut self, size: u64) -> io::Result<()> {
+        let padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        io::copy(&mut (&mut self.archive).take(padding), &mut io::sink())?;
+        Ok(())
+    }
+
+    fn next_file(&mut self) -> io::Result<Option<(String, Vec<u8>)>> {
+        let mut long_path = None;
+        loop {
+            let mut header = [0u8; BLOCK];
+            if let Err(e) = self.archive.read_exact(&mut header) {
+                return match e.kind() {
+                    io::ErrorKind::UnexpectedEof => Err(invalid("truncated tar archive")),
+                    _ => Err(e),
+                };
+            }
+            if header.iter().all(|&byte| byte == 0) {
+                return Ok(None);
+            }
+            let checksum = number(&header[148..156])?;
+            let sum: u64 = header.iter().enumerate()
+                .map(|(index, &byte)| if (148..156).contains(&index) { u64::from(b' ') } else { u64::from(byte) })
+                .sum();
+            if checksum != sum {
+                return Err(invalid("tar header checksum mismatch"));
+            }
+            let size = number(&header[124..136])?;
+            match header[156] {
+                b'0' | 0 => {
+                    let path = long_path.take().unwrap_or_else(|| path(&header));
+                    if path.to_ascii_lowercase().ends_with(".xml") {
+                        return Ok(Some((path, self.read_data(size)?)));
+                    }
+                    self.skip_data(size)?;
+                }
+                b'L' => long_path = Some(String::from_utf8_lossy(field(&self.read_data(size)?)).into_owned()),
+                b'x' => long_path = pax_path(&self.read_data(size)?).or(long_path),
+                _ => self.skip_data(size)?,
+            }
+        }
+    }
+}
+
+impl<'p, R: Read> Iterator for TarDocuments<'p, R> {
+    type Item = io::Result<(String, Result<Document, Error>)>;
+
+    fn next(&mut self) -> Option<io::Result<(String, Result<Document, Error>)>> {
+        if self.done {
+            return None;
+        }
+        let (path, data) = match self.next_file() {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        Some(Ok((path, self.parser.complete_document_bytes(&data))))
+    }
+}
+
+/// Iterator over the `.xml` files of a zip archive, stored or deflated, in the order of its
+/// central directory. Yields what `TarDocuments` does, but an unreadable entry is reported
+/// and skipped rather than ending the iteration.
+pub struct ZipDocuments<'p, R> {
+    parser: &'p Parser,
+    archive: ZipArchive<R>,
+    index: usize,
+}
+
+/// Fails if `archive` has no readable central directory.
+pub fn zip_documents<R: Read + Seek>(parser: &Parser, archive: R) -> io::Result<ZipDocuments<'_, R>> {
+    Ok(ZipDocuments {
+        parser,
+        archive: ZipArchive::new(archive).map_err(zip_error)?,
+        index: 0,
+    })
+}
+
+fn zip_error(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+impl<'p, R: Read + Seek> Iterator for ZipDocuments<'p, R> {
+    type Item = io::Result<(String, Result<Document, Error>)>;
+
+    fn next(&mut self) -> Option<io::Result<(String, Result<Document, Error>)>> {
+        while self.index < self.archive.len() {
+            let index = self.index;
+            self.index += 1;
+            let mut file = match self.archive.by_index(index) {
+                Ok(file) => file,
+                Err(e) => return Some(Err(zip_error(e))),
+            };
+            if file.is_dir() || !file.name().to_ascii_lowercase().ends_with(".xml") {
+                continue;
+            }
+            let path = file.name().to_string();
+            let mut data = vec![];
+            if let Err(e) = file.read_to_end(&mut data) {
+                return Some(Err(e));
+            }
+            return Some(Ok((path, self.parser.complete_document_bytes(&data))));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::archive::*;
+
+    fn entry(out: &mut Vec<u8>, path: &str, kind: u8, data: &[u8]) {
+        let mut header = [0u8; BLOCK];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len() + (BLOCK - data.len() % BLOCK) % BLOCK, 0);
+    }
+
+    fn tar_corpus() -> Vec<u8> {
+        let mut tar = vec![];
+        entry(&mut tar, "corpus/", b'5', b"");
+        entry(&mut tar, "corpus/a.xml", b'0', b"<?xml version=\"1.0\"?><a>1</a>");
+        entry(&mut tar, "corpus/README", b'0', b"not xml");
+        entry(&mut tar, "corpus/bad.XML", b'0', b"<?xml version=\"1.0\"?><a>");
+        let long = format!("corpus/{}.xml", "x".repeat(120));
+        entry(&mut tar, "././@LongLink", b'L', format!("{}\0", long).as_bytes());
+        entry(&mut tar, "corpus/xxxx", b'0', b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><b>\xe9</b>");
+        tar.extend_from_slice(&[0; BLOCK * 2]);
+        tar
+    }
+
+    #[test]
+    fn tar_files() {
+        let tar = tar_corpus();
+        let parser = Parser::default();
+        let files: Vec<(String, Result<Document, Error>)> =
+            tar_documents(&parser, tar.as_slice()).map(Result::unwrap).collect();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].0, "corpus/a.xml");
+        assert_eq!(files[0].1.as_ref().unwrap().root().text(), "1");
+        assert!(matches!(files[1].1, Err(Error::UnexpectedEof { .. })));
+        assert_eq!(files[2].0, format!("corpus/{}.xml", "x".repeat(120)));
+        assert_eq!(files[2].1.as_ref().unwrap().root().text(), "\u{e9}");
+
+        let truncated = &tar[..BLOCK + 10];
+        let results: Vec<_> = tar_documents(&parser, truncated).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert!(tar_documents(&parser, &b"<a/>"[..]).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn tar_gz_files() {
+        use std::io::Write;
+
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(&tar_corpus()).unwrap();
+        let gz = gz.finish().unwrap();
+        let parser = Parser::default();
+        let paths: Vec<String> = tar_gz_documents(&parser, gz.as_slice()).map(|file| file.unwrap().0).collect();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(paths[0], "corpus/a.xml");
+        assert!(tar_gz_documents(&parser, &gz[..gz.len() / 2]).any(|file| file.is_err()));
+    }
+
+    #[test]
+    fn zip_files() {
+        use std::io::{Cursor, Write};
+        use zip::write::FileOptions;
+        use zip::CompressionMethod;
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        zip.add_directory("corpus/", FileOptions::default()).unwrap();
+        zip.start_file("corpus/a.xml", FileOptions::default()).unwrap();
+        zip.write_all(b"<?xml version=\"1.0\"?><a>1</a>").unwrap();
+        zip.start_file("corpus/notes.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"<a>").unwrap();
+        zip.start_file("corpus/b.xml", FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+        zip.write_all(b"<?xml version=\"1.0\"?><b>\n<c></b>").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let parser = Parser::default();
+        let files: Vec<(String, Result<Document, Error>)> =
+            zip_documents(&parser, Cursor::new(&zip)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, "corpus/a.xml");
+        assert_eq!(files[0].1.as_ref().unwrap().root().text(), "1");
+        assert_eq!(files[1].0, "corpus/b.xml");
+        assert_eq!(files[1].1.as_ref().unwrap_err().location().map(|at| at.line), Some(2));
+        assert_eq!(zip_documents(&parser, Cursor::new(b"<a/>")).err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+    }
+}
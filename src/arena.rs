@@ -0,0 +1,900 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use std::ops::Range;
+
+use crate::foreign::Foreign;
+use crate::text::Text;
+use crate::{Element, Node};
+
+/// Handle to a node in a `Tree`. Ids stay valid while their node is in the tree, whatever else
+/// is inserted or removed, and never alias a node created after theirs was removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeData<T = String> {
+    Element { name: String, attributes: HashMap<String, String> },
+    CharData(T),
+    Comment(String),
+    ProcessingInstruction { target: String, data: String },
+    /// A `Node::Foreign`, kept whole as a leaf.
+    Foreign(Foreign),
+}
+
+#[derive(Debug, Clone)]
+pub struct ArenaNode<T = String> {
+    pub data: NodeData<T>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl<T> ArenaNode<T> {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+    pub fn name(&self) -> Option<&str> {
+        match &self.data {
+            NodeData::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Read-only handle to an element of a `Tree`, for code written against `tree::XmlElement`.
+#[derive(Debug)]
+pub struct ElementHandle<'a, T = String> {
+    tree: &'a Tree<T>,
+    id: NodeId,
+}
+
+impl<T> Clone for ElementHandle<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ElementHandle<'_, T> {}
+
+impl<'a, T: Text> ElementHandle<'a, T> {
+    pub fn tree(&self) -> &'a Tree<T> {
+        self.tree
+    }
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+    pub fn node(&self) -> &'a ArenaNode<T> {
+        self.tree.get(self.id).expect("handles are only made for live elements")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    node: Option<ArenaNode<T>>,
+    /// False while the node is only kept alive by the journal so a removal can be undone.
+    attached: bool,
+}
+
+/// A recorded mutation. Undoing applies its inverse; redoing applies it again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    SetAttribute { id: NodeId, name: String, old: Option<String>, new: Option<String> },
+    SetText { id: NodeId, old: String, new: String },
+    /// Replaced the bytes at `at..at + removed.len()` of a text node with `inserted`.
+    SpliceText { id: NodeId, at: usize, removed: String, inserted: String },
+    Insert { id: NodeId, parent: NodeId, index: usize },
+    Remove { id: NodeId, parent: NodeId, index: usize },
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::SetAttribute { id, name, old, new } => Edit::SetAttribute { id, name, old: new, new: old },
+            Edit::SetText { id, old, new } => Edit::SetText { id, old: new, new: old },
+            Edit::SpliceText { id, at, removed, inserted } => Edit::SpliceText { id, at, removed: inserted, inserted: removed },
+            Edit::Insert { id, parent, index } => Edit::Remove { id, parent, index },
+            Edit::Remove { id, parent, index } => Edit::Insert { id, parent, index },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObserverId(usize);
+
+type Observer = Box<dyn FnMut(&Edit)>;
+
+#[derive(Default)]
+struct Observers(Vec<Option<Observer>>);
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.iter().flatten().count())
+    }
+}
+
+/// Observers belong to one tree, so a cloned tree starts without any.
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+/// Why a validator refused an edit, and which node it objects to when it can say.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub node: Option<NodeId>,
+    pub message: String,
+}
+
+impl From<String> for Rejection {
+    fn from(message: String) -> Rejection {
+        Rejection { node: None, message }
+    }
+}
+
+type ValidatorFn<T> = dyn Fn(&Tree<T>) -> Result<(), Rejection>;
+
+struct Validator<T>(Rc<ValidatorFn<T>>);
+
+impl<T> Clone for Validator<T> {
+    fn clone(&self) -> Self {
+        Validator(self.0.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for Validator<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Journal {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+/// Mutable DOM storing nodes in a generational arena with parent links.
+///
+/// Edits made through `set_attribute`, `remove_attribute`, `set_text`, `insert`, `append` and
+/// `remove` are recorded when the journal is enabled; direct changes through `get_mut` are not.
+///
+/// Character data is stored as `T`, a `String` unless another `Text` type is chosen with
+/// `with_storage`, such as a `Rope` for editors splicing large text nodes.
+#[derive(Debug, Clone)]
+pub struct Tree<T = String> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    root: NodeId,
+    journal: Option<Journal>,
+    observers: Observers,
+    validator: Option<Validator<T>>,
+    rejection: Option<Rejection>,
+}
+
+impl Tree {
+    pub fn new(root_name: &str) -> Tree {
+        Tree::with_storage(root_name)
+    }
+
+    pub fn from_element(element: &Element) -> Tree {
+        Tree::from_element_with_storage(element)
+    }
+}
+
+impl<T: Text> Tree<T> {
+    pub fn with_storage(root_name: &str) -> Tree<T> {
+        let mut tree = Tree {
+            slots: vec![],
+            free: vec![],
+            root: NodeId { index: 0, generation: 0 },
+            journal: None,
+            observers: Observers::default(),
+            validator: None,
+            rejection: None,
+        };
+        tree.root = tree.allocate(NodeData::Element { name: root_name.to_string(), attributes: HashMap::new() }, None);
+        tree
+    }
+
+    pub fn from_element_with_storage(element: &Element) -> Tree<T> {
+        let mut tree = Tree::with_storage(&element.name);
+        let root = tree.root;
+        if let Some(ArenaNode { data: NodeData::Element { attributes, .. }, .. }) = tree.get_mut(root) {
+            *attributes = element.attributes.clone();
+        }
+        tree.append_children(root, &element.children);
+        tree
+    }
+
+    fn append_children(&mut self, parent: NodeId, children: &[Node]) {
+        for child in children {
+            match child {
+                Node::CharData(data) => {
+                    self.append(parent, NodeData::CharData(T::from_text(data)));
+                }
+                Node::Comment(data) => {
+                    self.append(parent, NodeData::Comment(data.clone()));
+                }
+                Node::ProcessingInstruction { target, data } => {
+                    self.append(parent, NodeData::ProcessingInstruction { target: target.clone(), data: data.clone() });
+                }
+                Node::Element(element) => {
+                    let data = NodeData::Element { name: element.name.clone(), attributes: element.attributes.clone() };
+                    if let Some(id) = self.append(parent, data) {
+                        self.append_children(id, &element.children);
+                    }
+                }
+                Node::Foreign(foreign) => {
+                    self.append(parent, NodeData::Foreign(foreign.clone()));
+                }
+            }
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn to_element(&self) -> Element {
+        self.element(self.root).unwrap_or_else(|| Element {
+            name: String::new(),
+            attributes: HashMap::new(),
+            children: vec![],
+        })
+    }
+
+    /// Copies the subtree at `id` out as an owned `Element`, if `id` is a live element.
+    pub fn element(&self, id: NodeId) -> Option<Element> {
+        match self.node(id)? {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// A handle to the element `id`, if it is a live element.
+    pub fn handle(&self, id: NodeId) -> Option<ElementHandle<'_, T>> {
+        match self.get(id)?.data {
+            NodeData::Element { .. } => Some(ElementHandle { tree: self, id }),
+            _ => None,
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<Node> {
+        let node = self.get(id)?;
+        Some(match &node.data {
+            NodeData::CharData(data) => Node::CharData(data.to_text()),
+            NodeData::Comment(data) => Node::Comment(data.clone()),
+            NodeData::ProcessingInstruction { target, data } => Node::ProcessingInstruction { target: target.clone(), data: data.clone() },
+            NodeData::Foreign(foreign) => Node::Foreign(foreign.clone()),
+            NodeData::Element { name, attributes } => Node::Element(Element {
+                name: name.clone(),
+                attributes: attributes.clone(),
+                children: node.children.iter().filter_map(|child| self.node(*child)).collect(),
+            }),
+        })
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&ArenaNode<T>> {
+        let slot = self.slots.get(id.index)?;
+        if slot.generation == id.generation && slot.attached {
+            slot.node.as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut ArenaNode<T>> {
+        let slot = self.slots.get_mut(id.index)?;
+        if slot.generation == id.generation && slot.attached {
+            slot.node.as_mut()
+        } else {
+            None
+        }
+    }
+
+    fn slot_mut(&mut self, id: NodeId) -> Option<&mut ArenaNode<T>> {
+        let slot = self.slots.get_mut(id.index)?;
+        if slot.generation == id.generation {
+            slot.node.as_mut()
+        } else {
+            None
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id)?.parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.get(id).map_or(&[], |node| &node.children)
+    }
+
+    fn allocate(&mut self, data: NodeData<T>, parent: Option<NodeId>) -> NodeId {
+        let node = ArenaNode { data, parent, children: vec![] };
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.node = Some(node);
+                slot.attached = true;
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node), attached: true });
+                NodeId { index: self.slots.len() - 1, generation: 0 }
+            }
+        }
+    }
+
+    pub fn append(&mut self, parent: NodeId, data: NodeData<T>) -> Option<NodeId> {
+        let index = self.get(parent)?.children.len();
+        self.insert(parent, index, data)
+    }
+
+    /// Inserts a new node as the `index`th child of `parent`. Character data cannot have children.
+    pub fn insert(&mut self, parent: NodeId, index: usize, data: NodeData<T>) -> Option<NodeId> {
+        let node = self.get(parent)?;
+        if !matches!(node.data, NodeData::Element { .. }) || index > node.children.len() {
+            return None;
+        }
+        let id = self.allocate(data, Some(parent));
+        self.get_mut(parent)?.children.insert(index, id);
+        if self.commit(Edit::Insert { id, parent, index }) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Removes `id` and its whole subtree; every id in it stops resolving (until an undo, when
+    /// the journal is enabled). The root cannot be removed.
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        let (parent, index) = match self.detach(id) {
+            Some(position) => position,
+            None => return false,
+        };
+        if !self.commit(Edit::Remove { id, parent, index }) {
+            return false;
+        }
+        if self.journal.is_none() {
+            self.free_subtree(id);
+        }
+        true
+    }
+
+    /// Sets an attribute, returning the previous value (`Some(None)` if there was none).
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) -> Option<Option<String>> {
+        let old = self.write_attribute(id, name, Some(value.to_string()))?;
+        if self.commit(Edit::SetAttribute { id, name: name.to_string(), old: old.clone(), new: Some(value.to_string()) }) {
+            Some(old)
+        } else {
+            None
+        }
+    }
+
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> Option<String> {
+        let old = self.write_attribute(id, name, None)??;
+        if self.commit(Edit::SetAttribute { id, name: name.to_string(), old: Some(old.clone()), new: None }) {
+            Some(old)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces the contents of a character data or comment node, returning the previous text.
+    pub fn set_text(&mut self, id: NodeId, text: &str) -> Option<String> {
+        let old = self.write_text(id, text)?;
+        if self.commit(Edit::SetText { id, old: old.clone(), new: text.to_string() }) {
+            Some(old)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces a byte range of a character data or comment node, returning the removed text.
+    /// Only the changed piece is copied, here and into the journal, so large text nodes stay
+    /// cheap to edit with a `Rope`.
+    pub fn splice_text(&mut self, id: NodeId, range: Range<usize>, replacement: &str) -> Option<String> {
+        let at = range.start;
+        let removed = self.write_splice(id, range, replacement)?;
+        if self.commit(Edit::SpliceText { id, at, removed: removed.clone(), inserted: replacement.to_string() }) {
+            Some(removed)
+        } else {
+            None
+        }
+    }
+
+    /// Checks the whole tree after every edit made through the editing methods. An edit the
+    /// validator rejects is rolled back, the method reports failure (`None`/`false`) and the
+    /// message is available from `last_rejection`. This is where a schema check such as
+    /// `dtd::Dtd::check` plugs in.
+    pub fn set_validator<E: Into<Rejection>>(&mut self, validator: impl Fn(&Tree<T>) -> Result<(), E> + 'static) {
+        self.validator = Some(Validator(Rc::new(move |tree: &Tree<T>| validator(tree).map_err(Into::into))));
+    }
+
+    pub fn clear_validator(&mut self) {
+        self.validator = None;
+    }
+
+    pub fn last_rejection(&self) -> Option<&str> {
+        self.rejection.as_ref().map(|rejection| rejection.message.as_str())
+    }
+
+    /// The node the last rejected edit was refused over, if the validator named one. It may
+    /// be the node the edit inserted, which no longer resolves.
+    pub fn last_rejected_node(&self) -> Option<NodeId> {
+        self.rejection.as_ref()?.node
+    }
+
+    /// Validates an edit that has already been applied, then records it or rolls it back.
+    fn commit(&mut self, edit: Edit) -> bool {
+        if let Some(Validator(validator)) = self.validator.clone() {
+            if let Err(rejection) = validator(self) {
+                self.replay(&edit.inverse());
+                if let Edit::Insert { id, .. } = edit {
+                    self.free_subtree(id);
+                }
+                self.rejection = Some(rejection);
+                return false;
+            }
+        }
+        self.rejection = None;
+        self.record(edit);
+        true
+    }
+
+    /// Registers a callback run after every recorded kind of edit, including those made by
+    /// `undo` (reported as the inverse edit) and `redo`.
+    pub fn observe(&mut self, observer: impl FnMut(&Edit) + 'static) -> ObserverId {
+        self.observers.0.push(Some(Box::new(observer)));
+        ObserverId(self.observers.0.len() - 1)
+    }
+
+    pub fn unobserve(&mut self, id: ObserverId) -> bool {
+        self.observers.0.get_mut(id.0).and_then(Option::take).is_some()
+    }
+
+    fn notify(&mut self, edit: &Edit) {
+        for observer in self.observers.0.iter_mut().flatten() {
+            observer(edit);
+        }
+    }
+
+    /// Starts recording edits so they can be undone and redone.
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Journal::default());
+        }
+    }
+
+    /// Stops recording and forgets the history, releasing nodes kept only for undo.
+    pub fn disable_journal(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            self.release(journal.undo.into_iter().chain(journal.redo));
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.journal.as_ref().is_some_and(|journal| !journal.undo.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.journal.as_ref().is_some_and(|journal| !journal.redo.is_empty())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let edit = match self.journal.as_mut().and_then(|journal| journal.undo.pop()) {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let inverse = edit.inverse();
+        self.replay(&inverse);
+        self.notify(&inverse);
+        if let Some(journal) = &mut self.journal {
+            journal.redo.push(edit);
+        }
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let edit = match self.journal.as_mut().and_then(|journal| journal.redo.pop()) {
+            Some(edit) => edit,
+            None => return false,
+        };
+        self.replay(&edit);
+        self.notify(&edit);
+        if let Some(journal) = &mut self.journal {
+            journal.undo.push(edit);
+        }
+        true
+    }
+
+    fn record(&mut self, edit: Edit) {
+        self.notify(&edit);
+        if let Some(journal) = &mut self.journal {
+            let redo = std::mem::take(&mut journal.redo);
+            journal.undo.push(edit);
+            self.release(redo);
+        }
+    }
+
+    /// Frees nodes that only a discarded history entry could have brought back.
+    fn release(&mut self, edits: impl IntoIterator<Item=Edit>) {
+        for edit in edits {
+            if let Edit::Insert { id, .. } | Edit::Remove { id, .. } = edit {
+                let detached = self.slots.get(id.index)
+                    .is_some_and(|slot| slot.generation == id.generation && slot.node.is_some() && !slot.attached);
+                if detached {
+                    self.free_subtree(id);
+                }
+            }
+        }
+    }
+
+    fn replay(&mut self, edit: &Edit) {
+        match edit {
+            Edit::SetAttribute { id, name, new, .. } => {
+                self.write_attribute(*id, name, new.clone());
+            }
+            Edit::SetText { id, new, .. } => {
+                self.write_text(*id, new);
+            }
+            Edit::SpliceText { id, at, removed, inserted } => {
+                self.write_splice(*id, *at..at + removed.len(), inserted);
+            }
+            Edit::Insert { id, parent, index } => self.attach(*id, *parent, *index),
+            Edit::Remove { id, .. } => {
+                self.detach(*id);
+            }
+        }
+    }
+
+    fn write_attribute(&mut self, id: NodeId, name: &str, value: Option<String>) -> Option<Option<String>> {
+        match &mut self.get_mut(id)?.data {
+            NodeData::Element { attributes, .. } => Some(match value {
+                Some(value) => attributes.insert(name.to_string(), value),
+                None => attributes.remove(name),
+            }),
+            _ => None,
+        }
+    }
+
+    fn write_text(&mut self, id: NodeId, text: &str) -> Option<String> {
+        match &mut self.get_mut(id)?.data {
+            NodeData::CharData(data) => Some(std::mem::replace(data, T::from_text(text)).to_text()),
+            NodeData::Comment(data) => Some(std::mem::replace(data, text.to_string())),
+            _ => None,
+        }
+    }
+
+    fn write_splice(&mut self, id: NodeId, range: Range<usize>, replacement: &str) -> Option<String> {
+        match &mut self.get_mut(id)?.data {
+            NodeData::CharData(data) => data.splice(range, replacement),
+            NodeData::Comment(data) => data.splice(range, replacement),
+            _ => None,
+        }
+    }
+
+    /// Unlinks a non-root node from its parent and hides its subtree, returning where it was.
+    fn detach(&mut self, id: NodeId) -> Option<(NodeId, usize)> {
+        let parent = self.parent(id)?;
+        let siblings = &mut self.get_mut(parent)?.children;
+        let index = siblings.iter().position(|x| *x == id)?;
+        siblings.remove(index);
+        self.set_attached(id, false);
+        Some((parent, index))
+    }
+
+    fn attach(&mut self, id: NodeId, parent: NodeId, index: usize) {
+        self.set_attached(id, true);
+        if let Some(node) = self.get_mut(parent) {
+            let index = index.min(node.children.len());
+            node.children.insert(index, id);
+        }
+    }
+
+    fn set_attached(&mut self, id: NodeId, attached: bool) {
+        let children = match self.slot_mut(id) {
+            Some(node) => node.children.clone(),
+            None => return,
+        };
+        self.slots[id.index].attached = attached;
+        for child in children {
+            self.set_attached(child, attached);
+        }
+    }
+
+    fn free_subtree(&mut self, id: NodeId) {
+        let children = match self.slot_mut(id) {
+            Some(node) => node.children.clone(),
+            None => return,
+        };
+        for child in children {
+            self.free_subtree(child);
+        }
+        let slot = &mut self.slots[id.index];
+        slot.node = None;
+        slot.attached = false;
+        // A slot whose generation would wrap is retired, so that no old id matches it again.
+        if let Some(generation) = slot.generation.checked_add(1) {
+            slot.generation = generation;
+            self.free.push(id.index);
+        }
+    }
+}
+
+/// Per-node data kept beside a `Tree` rather than in it, for analysis passes such as schema
+/// types, computed styles or validation results. Ids of removed nodes never alias new ones, so
+/// a stale entry is never mistaken for another node's; `prune` drops them.
+#[derive(Debug, Clone)]
+pub struct Annotations<V> {
+    values: HashMap<NodeId, V>,
+}
+
+impl<V> Default for Annotations<V> {
+    fn default() -> Annotations<V> {
+        Annotations { values: HashMap::new() }
+    }
+}
+
+impl<V> Annotations<V> {
+    pub fn new() -> Annotations<V> {
+        Annotations::default()
+    }
+
+    /// Runs `annotate` on every live node of `tree` in document order, keeping the values it returns.
+    pub fn compute<T: Text>(tree: &Tree<T>, mut annotate: impl FnMut(&Tree<T>, NodeId) -> Option<V>) -> Annotations<V> {
+        let mut annotations = Annotations::new();
+        let mut stack = vec![tree.root()];
+        while let Some(id) = stack.pop() {
+            if let Some(value) = annotate(tree, id) {
+                annotations.insert(id, value);
+            }
+            stack.extend(tree.children(id).iter().rev());
+        }
+        annotations
+    }
+
+    pub fn insert(&mut self, id: NodeId, value: V) -> Option<V> {
+        self.values.insert(id, value)
+    }
+    pub fn get(&self, id: NodeId) -> Option<&V> {
+        self.values.get(&id)
+    }
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut V> {
+        self.values.get_mut(&id)
+    }
+    pub fn remove(&mut self, id: NodeId) -> Option<V> {
+        self.values.remove(&id)
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item=(NodeId, &V)> {
+        self.values.iter().map(|(id, value)| (*id, value))
+    }
+
+    /// Drops the values of nodes that are no longer in `tree`. Nodes detached by a journaled
+    /// removal count as gone, so their values do not come back if the removal is undone.
+    pub fn prune<T: Text>(&mut self, tree: &Tree<T>) {
+        self.values.retain(|id, _| tree.contains(*id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::arena::*;
+    use crate::text::Rope;
+
+    #[test]
+    fn ids_survive_mutation() {
+        let element = Parser::default().complete_element("<list><a>1</a><b>2</b><c>3</c></list>").unwrap();
+        let mut tree = Tree::from_element(&element);
+        let root = tree.root();
+        let (a, b, c) = (tree.children(root)[0], tree.children(root)[1], tree.children(root)[2]);
+        let text_b = tree.children(b)[0];
+        let first = tree.insert(root, 0, NodeData::CharData("start".to_string())).unwrap();
+        assert!(tree.remove(b));
+        assert!(!tree.contains(b));
+        assert!(!tree.contains(text_b));
+        let reused = tree.append(root, NodeData::Element { name: "d".to_string(), attributes: Default::default() }).unwrap();
+        assert!(tree.get(b).is_none());
+        assert_eq!(tree.get(reused).unwrap().name(), Some("d"));
+        assert_eq!(tree.get(a).unwrap().name(), Some("a"));
+        assert_eq!(tree.get(c).unwrap().parent(), Some(root));
+        assert_eq!(tree.children(root), &[first, a, c, reused]);
+        if let NodeData::Element { attributes, .. } = &mut tree.get_mut(c).unwrap().data {
+            attributes.insert("x".to_string(), "y".to_string());
+        }
+        let element = tree.to_element();
+        assert_eq!(element.children.len(), 4);
+        assert_eq!(element.children().elem_name("c")[0].attributes["x"], "y");
+        assert!(!tree.remove(root));
+    }
+
+    #[test]
+    fn document_tree() {
+        let mut document = Parser::default().complete_document(r#"<?xml version="1.0" encoding="UTF-8"?><list><a/></list>"#).unwrap();
+        let mut tree = document.to_tree();
+        let a = tree.children(tree.root())[0];
+        tree.insert(tree.root(), 0, NodeData::CharData("x".to_string())).unwrap();
+        tree.set_attribute(a, "id", "1").unwrap();
+        assert_eq!(tree.get(a).unwrap().name(), Some("a"));
+        document.set_tree(&tree);
+        assert_eq!(document.encoding(), Some("UTF-8"));
+        assert_eq!(document.root().text(), "x");
+        assert_eq!(document.root().children().elem_name("a")[0].attribute("id"), Some("1"));
+    }
+
+    #[test]
+    fn exhausted_slots_retire() {
+        let mut tree = Tree::from_element(&Element::new("r").unwrap());
+        let a = tree.append(tree.root(), NodeData::CharData("a".to_string())).unwrap();
+        let stale = NodeId { index: a.index, generation: u32::MAX };
+        tree.slots[a.index].generation = u32::MAX;
+        tree.slots[0].node.as_mut().unwrap().children[0] = stale;
+        assert!(tree.remove(stale));
+        let b = tree.append(tree.root(), NodeData::CharData("b".to_string())).unwrap();
+        assert_ne!(b.index, a.index);
+        assert!(!tree.contains(stale));
+    }
+
+    #[test]
+    fn undo_redo() {
+        let element = Parser::default().complete_element(r#"<doc v="1"><p>hello</p><q/></doc>"#).unwrap();
+        let mut tree = Tree::from_element(&element);
+        let root = tree.root();
+        let p = tree.children(root)[0];
+        let q = tree.children(root)[1];
+        let text = tree.children(p)[0];
+        tree.enable_journal();
+        assert_eq!(tree.set_attribute(root, "v", "2"), Some(Some("1".to_string())));
+        assert_eq!(tree.set_text(text, "bye"), Some("hello".to_string()));
+        assert!(tree.remove(p));
+        let r = tree.append(root, NodeData::CharData("tail".to_string())).unwrap();
+        assert!(!tree.contains(text));
+
+        assert!(tree.undo());
+        assert!(!tree.contains(r));
+        assert!(tree.undo());
+        assert!(tree.contains(p));
+        assert_eq!(tree.children(root), &[p, q]);
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(!tree.undo());
+        assert_eq!(tree.to_element().attributes["v"], "1");
+        assert_eq!(tree.to_element().text(), "hello");
+
+        assert!(tree.redo());
+        assert!(tree.redo());
+        assert!(tree.redo());
+        assert!(!tree.contains(p));
+        assert_eq!(tree.to_element().attributes["v"], "2");
+        assert!(tree.can_redo());
+        tree.remove_attribute(root, "v");
+        assert!(!tree.can_redo());
+        assert!(!tree.contains(r));
+        assert!(tree.undo());
+        assert_eq!(tree.to_element().attributes["v"], "2");
+        tree.disable_journal();
+        assert!(!tree.undo());
+    }
+
+    #[test]
+    fn observers() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut tree = Tree::new("root");
+        let root = tree.root();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = seen.clone();
+        let observer = tree.observe(move |edit| log.borrow_mut().push(edit.clone()));
+        tree.enable_journal();
+        let child = tree.append(root, NodeData::CharData("x".to_string())).unwrap();
+        tree.set_attribute(root, "a", "1");
+        tree.undo();
+        assert_eq!(*seen.borrow(), vec![
+            Edit::Insert { id: child, parent: root, index: 0 },
+            Edit::SetAttribute { id: root, name: "a".to_string(), old: None, new: Some("1".to_string()) },
+            Edit::SetAttribute { id: root, name: "a".to_string(), old: Some("1".to_string()), new: None },
+        ]);
+        assert!(tree.unobserve(observer));
+        tree.remove(child);
+        assert_eq!(seen.borrow().len(), 3);
+    }
+
+    #[test]
+    fn validation_on_edit() {
+        let mut tree = Tree::new("list");
+        let root = tree.root();
+        tree.enable_journal();
+        tree.set_validator(|tree| {
+            let root = tree.root();
+            if tree.children(root).len() > 2 {
+                return Err("list holds at most two items".to_string());
+            }
+            match tree.get(root).map(|node| &node.data) {
+                Some(NodeData::Element { attributes, .. }) if attributes.get("size").is_some_and(|x| x.parse::<u32>().is_err()) => {
+                    Err("size must be a number".to_string())
+                }
+                _ => Ok(()),
+            }
+        });
+        let item = || NodeData::Element { name: "item".to_string(), attributes: Default::default() };
+        let first = tree.append(root, item()).unwrap();
+        tree.append(root, item()).unwrap();
+        assert!(tree.append(root, item()).is_none());
+        assert_eq!(tree.last_rejection(), Some("list holds at most two items"));
+        assert_eq!(tree.children(root).len(), 2);
+        assert!(tree.set_attribute(root, "size", "big").is_none());
+        assert!(tree.to_element().attributes.is_empty());
+        assert_eq!(tree.set_attribute(root, "size", "2"), Some(None));
+        assert_eq!(tree.last_rejection(), None);
+        assert!(tree.remove(first));
+        assert!(tree.undo());
+        assert_eq!(tree.children(root).len(), 2);
+    }
+
+    #[test]
+    fn rope_storage() {
+        let body = "x".repeat(5000);
+        let element = Parser::default().complete_element(&format!("<doc><p>{}</p></doc>", body)).unwrap();
+        let mut tree = Tree::<Rope>::from_element_with_storage(&element);
+        let text = tree.children(tree.children(tree.root())[0])[0];
+        tree.enable_journal();
+        assert_eq!(tree.splice_text(text, 4000..4003, "yz"), Some("xxx".to_string()));
+        let snapshot = tree.clone();
+        assert_eq!(tree.splice_text(text, 0..0, "<"), Some(String::new()));
+        assert_eq!(tree.splice_text(text, 0..6000, ""), None);
+        assert_eq!(tree.to_element().text().len(), 5000);
+        assert!(tree.undo());
+        assert_eq!(tree.to_element(), snapshot.to_element());
+        assert!(tree.undo());
+        assert_eq!(tree.to_element(), element);
+        assert_eq!(Tree::from_element(&element).to_element(), element);
+    }
+
+    #[test]
+    fn annotation_side_table() {
+        let element = Parser::default().complete_element("<a><b><c/></b><d>t</d></a>").unwrap();
+        let mut tree = Tree::from_element(&element);
+        let depths = Annotations::compute(&tree, |tree, id| {
+            let mut depth = 0;
+            let mut at = id;
+            while let Some(parent) = tree.parent(at) {
+                depth += 1;
+                at = parent;
+            }
+            tree.get(id)?.name().map(|_| depth)
+        });
+        let root = tree.root();
+        let (b, d) = (tree.children(root)[0], tree.children(root)[1]);
+        let c = tree.children(b)[0];
+        assert_eq!(depths.len(), 4);
+        assert_eq!((depths.get(root), depths.get(c), depths.get(tree.children(d)[0])), (Some(&0), Some(&2), None));
+
+        let mut depths = depths;
+        tree.remove(b);
+        let e = tree.append(root, NodeData::Element { name: "e".to_string(), attributes: Default::default() }).unwrap();
+        assert_eq!(depths.get(e), None);
+        depths.prune(&tree);
+        assert_eq!(depths.len(), 2);
+        assert_eq!(depths.get(c), None);
+    }
+}
@@ -0,0 +1,465 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::foreign::Foreign;
+use crate::tokens::{tokens, tokens_from, TokenKind, Tokens};
+use crate::typed::{DeError, ExpectError, Expected};
+use crate::{comment, identifier, normalize_attribute_value, processing_instruction, reference, Element, Error, Location, Node, Parser, WhitespacePolicy};
+
+/// An element whose names, attribute values and text borrow from the input wherever nothing
+/// had to be decoded or normalized, so well-behaved messages parse without copying strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BorrowedElement<'a> {
+    pub name: Cow<'a, str>,
+    /// In document order.
+    pub attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    pub children: Vec<BorrowedNode<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BorrowedNode<'a> {
+    CharData(Cow<'a, str>),
+    Element(BorrowedElement<'a>),
+    Comment(&'a str),
+    ProcessingInstruction { target: &'a str, data: &'a str },
+    Foreign(BorrowedForeign<'a>),
+}
+
+/// An element a `Parser::foreign` handler parsed, kept as the slice of input it spans.
+#[derive(Clone)]
+pub struct BorrowedForeign<'a> {
+    pub name: Cow<'a, str>,
+    /// The whole element as written, start tag to end tag.
+    pub source: &'a str,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl<'a> BorrowedForeign<'a> {
+    /// What the foreign parser produced, if it is a `T`.
+    pub fn value<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+    pub fn to_foreign(&self) -> Foreign {
+        Foreign { name: self.name.to_string(), source: self.source.to_string(), value: self.value.clone() }
+    }
+}
+
+impl fmt::Debug for BorrowedForeign<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowedForeign").field("name", &self.name).field("source", &self.source).finish_non_exhaustive()
+    }
+}
+
+/// Equal when written the same way; the parsed values are not compared.
+impl PartialEq for BorrowedForeign<'_> {
+    fn eq(&self, other: &BorrowedForeign<'_>) -> bool {
+        self.name == other.name && self.source == other.source
+    }
+}
+
+impl Eq for BorrowedForeign<'_> {}
+
+impl<'a> BorrowedElement<'a> {
+    pub fn attribute(&self, name: &str) -> Option<&Cow<'a, str>> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+    pub fn child_elements(&self) -> impl Iterator<Item=&BorrowedElement<'a>> {
+        self.children.iter().filter_map(|node| match node {
+            BorrowedNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+    /// The element's text: borrowed when it is a single run of character data, as in
+    /// `<name>value</name>`.
+    pub fn text(&self) -> Cow<'a, str> {
+        let mut text: Option<Cow<'a, str>> = None;
+        self.collect_text(&mut text);
+        text.unwrap_or_default()
+    }
+    fn collect_text(&self, text: &mut Option<Cow<'a, str>>) {
+        for node in &self.children {
+            match node {
+                BorrowedNode::CharData(data) => match text {
+                    None => *text = Some(data.clone()),
+                    Some(text) => text.to_mut().push_str(data),
+                },
+                BorrowedNode::Element(element) => element.collect_text(text),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn to_element(&self) -> Element {
+        Element {
+            name: self.name.to_string(),
+            attributes: self.attributes.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            children: self.children.iter().map(|node| match node {
+                BorrowedNode::CharData(data) => Node::CharData(data.to_string()),
+                BorrowedNode::Element(element) => Node::Element(element.to_element()),
+                BorrowedNode::Comment(data) => Node::Comment(data.to_string()),
+                BorrowedNode::ProcessingInstruction { target, data } => {
+                    Node::ProcessingInstruction { target: target.to_string(), data: data.to_string() }
+                }
+                BorrowedNode::Foreign(foreign) => Node::Foreign(foreign.to_foreign()),
+            }).collect(),
+        }
+    }
+
+    fn apply(&mut self, policy: WhitespacePolicy) {
+        if policy == WhitespacePolicy::Preserve {
+            return;
+        }
+        self.children.retain(|node| !matches!(node, BorrowedNode::CharData(data) if data.trim().is_empty()));
+        for node in &mut self.children {
+            match node {
+                BorrowedNode::CharData(data) if policy == WhitespacePolicy::Trim => *data = match std::mem::take(data) {
+                    Cow::Borrowed(data) => Cow::Borrowed(data.trim()),
+                    Cow::Owned(data) => Cow::Owned(data.trim().to_string()),
+                },
+                BorrowedNode::Element(element) => element.apply(policy),
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Builder<'p, 'a> {
+    parser: &'p Parser,
+    input: &'a str,
+    tokens: Peekable<Tokens<'a>>,
+    /// Where the last token read starts.
+    offset: usize,
+}
+
+impl<'p, 'a> Builder<'p, 'a> {
+    fn next(&mut self) -> Option<(TokenKind, &'a str)> {
+        let input = self.input;
+        let (kind, range) = self.tokens.next()?;
+        self.offset = range.start;
+        Some((kind, &input[range]))
+    }
+
+    /// The next token that is not whitespace.
+    fn significant(&mut self) -> Option<(TokenKind, &'a str)> {
+        self.tokens.next_if(|(kind, _)| *kind == TokenKind::Whitespace);
+        self.next()
+    }
+
+    /// A name token, normalized, if `identifier` accepts all of it as the tree parser would.
+    fn name(&self, token: &'a str) -> Option<Cow<'a, str>> {
+        match identifier(token) {
+            Ok(("", _)) => Some((self.parser.normalize_name)(token)),
+            _ => None,
+        }
+    }
+
+    fn normalized(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+        match (self.parser.normalize_text, text) {
+            (None, text) => text,
+            (Some(normalize), Cow::Borrowed(text)) => normalize(text),
+            (Some(normalize), Cow::Owned(text)) => Cow::Owned(normalize(&text).into_owned()),
+        }
+    }
+
+    /// `normalized`, then cut to `limit` as the parser's `over_limit` says.
+    fn limited(&self, text: Cow<'a, str>, limit: Option<usize>) -> Option<Cow<'a, str>> {
+        let text = self.normalized(text);
+        let keep = self.parser.limit(&text, limit)?;
+        Some(match text {
+            Cow::Borrowed(text) => Cow::Borrowed(&text[..keep]),
+            Cow::Owned(mut text) => {
+                text.truncate(keep);
+                Cow::Owned(text)
+            }
+        })
+    }
+
+    /// The rest of a start tag after `<`; the flag tells whether the element is already complete.
+    fn start_tag(&mut self) -> Option<(BorrowedElement<'a>, bool)> {
+        let name = match self.next()? {
+            (TokenKind::ElementName, name) => self.name(name)?,
+            _ => return None,
+        };
+        let mut attributes: Vec<(Cow<'a, str>, Cow<'a, str>)> = vec![];
+        loop {
+            match self.significant()? {
+                (TokenKind::AttributeName, key) => {
+                    let key = self.name(key)?;
+                    let value = match (self.significant()?, self.significant()?) {
+                        ((TokenKind::Equals, _), (TokenKind::AttributeValue, quoted)) => &quoted[1..quoted.len() - 1],
+                        _ => return None,
+                    };
+                    let value = if value.contains(['&', '\t', '\n', '\r']) {
+                        Cow::Owned(normalize_attribute_value(value))
+                    } else {
+                        Cow::Borrowed(value)
+                    };
+                    if attributes.iter().any(|(x, _)| *x == key) {
+                        return None;
+                    }
+                    attributes.push((key, self.limited(value, self.parser.max_attribute_len)?));
+                }
+                (TokenKind::TagClose, _) => {
+                    let void = self.parser.allow_no_close.iter().any(|x| *x == name);
+                    return Some((BorrowedElement { name, attributes, children: vec![] }, void));
+                }
+                (TokenKind::EmptyTagClose, _) => return Some((BorrowedElement { name, attributes, children: vec![] }, true)),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Character data from adjacent text, whitespace and references, borrowed when it is one piece.
+    fn text(&mut self, first: &'a str, first_kind: TokenKind) -> Option<Cow<'a, str>> {
+        let decode = |kind, piece: &'a str| match kind {
+            TokenKind::Reference => reference(piece).map(|(_, ch)| Cow::Owned(ch.to_string())).unwrap_or(Cow::Borrowed(piece)),
+            _ => Cow::Borrowed(piece),
+        };
+        let mut text = decode(first_kind, first);
+        let lenient = self.parser.lenient;
+        while let Some((kind, piece)) = self.tokens.next_if(|(kind, range)| match kind {
+            TokenKind::Text | TokenKind::Whitespace | TokenKind::Reference => true,
+            TokenKind::Error => lenient && range.len() == 1,
+            _ => false,
+        }).map(|(kind, range)| (kind, &self.input[range])) {
+            text.to_mut().push_str(&decode(kind, piece));
+        }
+        self.limited(text, self.parser.max_text_len)
+    }
+
+    /// The normalized name of the element whose `<` was just read, if it is registered in
+    /// `Parser::foreign`.
+    fn foreign_name(&mut self) -> Option<Cow<'a, str>> {
+        if self.parser.foreign.is_empty() {
+            return None;
+        }
+        let input = self.input;
+        let name = match self.tokens.peek()? {
+            (TokenKind::ElementName, range) => &input[range.clone()],
+            _ => return None,
+        };
+        let name = self.name(name)?;
+        self.parser.foreign.iter().any(|(foreign, _)| *foreign == name).then_some(name)
+    }
+
+    /// The element named `name` starting at the `<` just read, parsed by its handler; the
+    /// tokens resume after it.
+    fn foreign(&mut self, name: Cow<'a, str>) -> Option<BorrowedForeign<'a>> {
+        let (_, handler) = self.parser.foreign.iter().find(|(foreign, _)| *foreign == name)?;
+        let start = self.offset;
+        let rest = &self.input[start..];
+        let (len, value) = handler(rest).filter(|&(len, _)| len > 0 && rest.is_char_boundary(len))?;
+        self.tokens = tokens_from(self.input, start + len).peekable();
+        Some(BorrowedForeign { name, source: &rest[..len], value })
+    }
+
+    fn element(&mut self) -> Option<BorrowedElement<'a>> {
+        let (mut element, complete) = self.start_tag()?;
+        if complete {
+            return Some(element);
+        }
+        loop {
+            let (kind, piece) = self.next()?;
+            let node = match kind {
+                TokenKind::TagOpen => match self.foreign_name() {
+                    Some(name) => BorrowedNode::Foreign(self.foreign(name)?),
+                    None => BorrowedNode::Element(self.element()?),
+                },
+                TokenKind::EndTagOpen => {
+                    let name = match self.significant()? {
+                        (TokenKind::ElementName, name) => self.name(name)?,
+                        _ => return None,
+                    };
+                    return match self.significant()? {
+                        (TokenKind::TagClose, _) if name == element.name => Some(element),
+                        _ => None,
+                    };
+                }
+                TokenKind::Text | TokenKind::Whitespace | TokenKind::Reference => BorrowedNode::CharData(self.text(piece, kind)?),
+                TokenKind::Error if self.parser.lenient && piece == "<" => BorrowedNode::CharData(self.text(piece, kind)?),
+                TokenKind::CData => BorrowedNode::CharData(self.limited(Cow::Borrowed(&piece[9..piece.len() - 3]), self.parser.max_text_len)?),
+                TokenKind::Comment => BorrowedNode::Comment(comment(piece).ok()?.1),
+                TokenKind::ProcessingInstruction => {
+                    let (target, data) = processing_instruction(piece).ok()?.1;
+                    BorrowedNode::ProcessingInstruction { target, data }
+                }
+                _ => return None,
+            };
+            element.children.push(node);
+        }
+    }
+}
+
+/// Parses `input` as one element like `complete_element`, borrowing every name, attribute
+/// value and text run that needs no decoding. Fails with the same `Error` as `complete_element`.
+pub fn parse_borrowed<'a>(parser: &Parser, input: &'a str) -> Result<BorrowedElement<'a>, Error> {
+    parser.check_input_len(input).map_err(|_| Error::TooLarge { what: "input", at: Location::new(input, 0) })?;
+    let mut builder = Builder { parser, input, tokens: tokens(input).peekable(), offset: 0 };
+    let element = match builder.next() {
+        Some((TokenKind::TagOpen, _)) => builder.element(),
+        _ => None,
+    };
+    let mut element = element.ok_or_else(|| {
+        let failure = nom::error::Error::new(&input[builder.offset..], nom::error::ErrorKind::Verify);
+        parser.diagnose(input, nom::Err::Error(failure))
+    })?;
+    if builder.significant().is_some() {
+        return Err(Error::TrailingContent { at: Location::new(input, builder.offset) });
+    }
+    element.apply(parser.whitespace);
+    Ok(element)
+}
+
+/// Types that can be built from a `BorrowedElement`, keeping borrowed strings where they can.
+pub trait FromBorrowed<'a>: Sized {
+    fn from_borrowed(element: &BorrowedElement<'a>) -> Result<Self, DeError>;
+}
+
+/// Types that can be read from a borrowed attribute value or text.
+pub trait FromBorrowedValue<'a>: Sized {
+    fn from_borrowed_value(value: Cow<'a, str>) -> Result<Self, DeError>;
+}
+
+/// Fails for a value that had entities to decode, since it cannot point into the input.
+impl<'a> FromBorrowedValue<'a> for &'a str {
+    fn from_borrowed_value(value: Cow<'a, str>) -> Result<&'a str, DeError> {
+        match value {
+            Cow::Borrowed(value) => Ok(value),
+            Cow::Owned(value) => Err(DeError::new(format!("{:?} was decoded and cannot be borrowed; use Cow or String", value))),
+        }
+    }
+}
+
+impl<'a> FromBorrowedValue<'a> for Cow<'a, str> {
+    fn from_borrowed_value(value: Cow<'a, str>) -> Result<Cow<'a, str>, DeError> {
+        Ok(value)
+    }
+}
+
+impl<'a> FromBorrowedValue<'a> for String {
+    fn from_borrowed_value(value: Cow<'a, str>) -> Result<String, DeError> {
+        Ok(value.into_owned())
+    }
+}
+
+macro_rules! from_borrowed_via_from_str {
+    ($($t:ty),*) => {
+        $(impl<'a> FromBorrowedValue<'a> for $t {
+            fn from_borrowed_value(value: Cow<'a, str>) -> Result<$t, DeError> {
+                <$t as FromStr>::from_str(value.trim())
+                    .map_err(|_| ExpectError::found(Expected::Type(stringify!($t).to_string()), &value).into())
+            }
+        })*
+    };
+}
+
+from_borrowed_via_from_str!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// Any value type maps from an element through its text.
+impl<'a, T: FromBorrowedValue<'a>> FromBorrowed<'a> for T {
+    fn from_borrowed(element: &BorrowedElement<'a>) -> Result<T, DeError> {
+        T::from_borrowed_value(element.text())
+    }
+}
+
+pub fn from_str_borrowed<'a, T: FromBorrowed<'a>>(parser: &Parser, input: &'a str) -> Result<T, DeError> {
+    let element = parse_borrowed(parser, input).map_err(|e| DeError::new(e.to_string()))?;
+    T::from_borrowed(&element).map_err(|e| e.within(&element.name))
+}
+
+pub fn attr<'a, T: FromBorrowedValue<'a>>(element: &BorrowedElement<'a>, name: &str) -> Result<T, DeError> {
+    let value = element.attribute(name).ok_or_else(|| DeError::from(ExpectError::missing(Expected::Attribute(name.to_string()))))?;
+    T::from_borrowed_value(value.clone()).map_err(|e| e.within(&format!("@{}", name)))
+}
+
+/// The only child element called `name`.
+pub fn child<'a, T: FromBorrowed<'a>>(element: &BorrowedElement<'a>, name: &str) -> Result<T, DeError> {
+    let mut found = element.child_elements().filter(|x| x.name == name);
+    match (found.next(), found.next()) {
+        (Some(only), None) => T::from_borrowed(only).map_err(|e| e.within(name)),
+        (None, _) => Err(ExpectError::missing(Expected::Element(name.to_string())).into()),
+        (Some(_), Some(_)) => Err(ExpectError::found_other(Expected::OneElement(name.to_string()), &(2 + found.count()).to_string()).into()),
+    }
+}
+
+pub fn children<'a, T: FromBorrowed<'a>>(element: &BorrowedElement<'a>, name: &str) -> Result<Vec<T>, DeError> {
+    element.child_elements()
+        .filter(|x| x.name == name)
+        .enumerate()
+        .map(|(index, x)| T::from_borrowed(x).map_err(|e| e.within(&format!("{}[{}]", name, index + 1))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::*;
+    use crate::borrowed::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Message<'a> {
+        id: &'a str,
+        kind: Cow<'a, str>,
+        body: &'a str,
+        parts: Vec<Cow<'a, str>>,
+        size: u32,
+    }
+
+    impl<'a> FromBorrowed<'a> for Message<'a> {
+        fn from_borrowed(element: &BorrowedElement<'a>) -> Result<Message<'a>, DeError> {
+            Ok(Message {
+                id: attr(element, "id")?,
+                kind: attr(element, "kind")?,
+                body: child(element, "body")?,
+                parts: children(element, "part")?,
+                size: child(element, "size")?,
+            })
+        }
+    }
+
+    #[test]
+    fn zero_copy_mapping() {
+        let parser = Parser::default();
+        let input = r#"<msg id="42" kind="a&amp;b"><body>hello</body><part>x</part><part>1 &lt; 2</part><size> 7 </size></msg>"#;
+        let message: Message = from_str_borrowed(&parser, input).unwrap();
+        assert_eq!(message.id, "42");
+        assert!(matches!(message.kind, Cow::Owned(ref kind) if kind == "a&b"));
+        assert!(matches!(message.parts[0], Cow::Borrowed("x")));
+        assert_eq!(message.parts[1], "1 < 2");
+        assert_eq!((message.body, message.size), ("hello", 7));
+        let input = r#"<msg id="&#52;2" kind="k"><body/><size>1</size></msg>"#;
+        assert_eq!(from_str_borrowed::<Message>(&parser, input).unwrap_err().to_string(), "msg/@id: \"42\" was decoded and cannot be borrowed; use Cow or String");
+    }
+
+    #[test]
+    fn borrowed_matches_owned() {
+        let lenient = Parser { lenient: true, allow_no_close: vec!["br".to_string()], ..Default::default() };
+        let trim = Parser { whitespace: WhitespacePolicy::Trim, ..Default::default() };
+        let inputs = [
+            "<a x = 'y\tz' b=\"&#10;\"> t &amp; &bogus <!--c--><![CDATA[<z>]]><?p d ?><B/></a >\n",
+            "<p>1 < 2<br>ok</p>",
+            "<a>\n  <b> x </b>\n</a>",
+            "<a></b>",
+            "<a x='1' x='2'/>",
+            " <a/>",
+            "<a/><b/>",
+            "<a><1b/></a>",
+            "<a><-x/></a>",
+            "<a .x='1'/>",
+            "<a 2='1'/>",
+            "<a><b></1b></a>",
+            "<a>x <1 y</a>",
+        ];
+        for parser in [Parser::default(), lenient, trim] {
+            for input in inputs {
+                let borrowed = parse_borrowed(&parser, input).map(|element| element.to_element());
+                assert_eq!(borrowed, parser.complete_element(input), "{}", input);
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{Element, InvalidName, NameToken, Node};
+
+/// Builds an element tree top-down, like `Writer` writes one: `start` opens a child of the
+/// innermost open element and `end` closes it.
+///
+/// With `indent`, each element whose content is only elements, comments and processing
+/// instructions gets a newline and indentation text node before each child and before its end
+/// tag, so even compact serialization reads well. Elements holding text are left alone, since
+/// added whitespace would change their content.
+#[derive(Debug, Clone)]
+pub struct TreeBuilder {
+    stack: Vec<Element>,
+    indent: Option<String>,
+    error: Option<InvalidName>,
+}
+
+impl TreeBuilder {
+    pub fn new(root: &str) -> TreeBuilder {
+        let mut builder = TreeBuilder { stack: vec![], indent: None, error: None };
+        builder.open(root);
+        builder
+    }
+
+    /// Indents nested elements by `unit` per level, e.g. `"  "` or `"\t"`.
+    pub fn indent(mut self, unit: &str) -> TreeBuilder {
+        self.indent = Some(unit.to_string());
+        self
+    }
+
+    fn open(&mut self, name: &str) {
+        let element = Element::new(name).unwrap_or_else(|e| {
+            self.error.get_or_insert(e);
+            Element { name: name.to_string(), attributes: Default::default(), children: vec![] }
+        });
+        self.stack.push(element);
+    }
+
+    fn push(&mut self, node: Node) {
+        if let Some(top) = self.stack.last_mut() {
+            top.children.push(node);
+        }
+    }
+
+    pub fn start(mut self, name: &str) -> TreeBuilder {
+        self.open(name);
+        self
+    }
+
+    /// Sets an attribute on the innermost open element.
+    pub fn attr(mut self, name: &str, value: &str) -> TreeBuilder {
+        if let Err(e) = NameToken::new(name) {
+            self.error.get_or_insert(e);
+        }
+        if let Some(top) = self.stack.last_mut() {
+            top.set_attribute(name, value);
+        }
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> TreeBuilder {
+        self.push(Node::CharData(text.to_string()));
+        self
+    }
+
+    pub fn comment(mut self, text: &str) -> TreeBuilder {
+        self.push(Node::Comment(text.to_string()));
+        self
+    }
+
+    /// `start(name).text(text).end()`.
+    pub fn leaf(self, name: &str, text: &str) -> TreeBuilder {
+        self.start(name).text(text).end()
+    }
+
+    /// Adds an element built elsewhere, as it is.
+    pub fn element(mut self, element: Element) -> TreeBuilder {
+        self.push(Node::Element(element));
+        self
+    }
+
+    /// Closes the innermost open element; the root is only closed by `finish`.
+    pub fn end(mut self) -> TreeBuilder {
+        if self.stack.len() > 1 {
+            let element = self.close();
+            self.push(Node::Element(element));
+        }
+        self
+    }
+
+    fn close(&mut self) -> Element {
+        let depth = self.stack.len() - 1;
+        let mut element = self.stack.pop().expect("the root stays open until finish");
+        let structural = element.children.iter().all(|node| !matches!(node, Node::CharData(_)));
+        if let (Some(unit), true, false) = (&self.indent, structural, element.children.is_empty()) {
+            let children = std::mem::take(&mut element.children);
+            for child in children {
+                element.children.push(Node::CharData(format!("\n{}", unit.repeat(depth + 1))));
+                element.children.push(child);
+            }
+            element.children.push(Node::CharData(format!("\n{}", unit.repeat(depth))));
+        }
+        element
+    }
+
+    /// Closes every open element and returns the root, or the first invalid name used.
+    pub fn finish(mut self) -> Result<Element, InvalidName> {
+        while self.stack.len() > 1 {
+            self = self.end();
+        }
+        let root = self.close();
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(root),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::builder::*;
+
+    #[test]
+    fn indenting_builder() {
+        let built = TreeBuilder::new("config")
+            .indent("  ")
+            .start("server").attr("host", "a")
+            .leaf("port", "80")
+            .comment("tls later")
+            .end()
+            .start("note").text("mixed ").leaf("b", "x").end()
+            .start("empty")
+            .finish()
+            .unwrap();
+        let parser = Parser { whitespace: WhitespacePolicy::Preserve, ..Default::default() };
+        let expected = "<config>\n  <server host=\"a\">\n    <port>80</port>\n    <!--tls later-->\n  </server>\n  <note>mixed <b>x</b></note>\n  <empty/>\n</config>";
+        assert_eq!(built, parser.complete_element(expected).unwrap());
+        assert!(TreeBuilder::new("a").start("two words").finish().is_err());
+        assert_eq!(TreeBuilder::new("a").attr("x", "1").finish().unwrap().attribute("x"), Some("1"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use crate::json::own_text;
+use crate::{Element, InvalidName, Node};
+
+/// Why a YAML or TOML value has no element form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertError {
+    pub message: String,
+}
+
+impl ConvertError {
+    pub(crate) fn new(message: impl Into<String>) -> ConvertError {
+        ConvertError { message: message.into() }
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<InvalidName> for ConvertError {
+    fn from(e: InvalidName) -> Self {
+        ConvertError::new(e.to_string())
+    }
+}
+
+/// A value as `to_element` reads it, whatever the format.
+pub(crate) enum Shape<'v, V> {
+    Null,
+    /// A string, or a number, boolean or date as written.
+    Text(String),
+    List(Vec<&'v V>),
+    Map(Vec<(String, &'v V)>),
+}
+
+/// The value type of a configuration format, which elements map to as `json` maps them:
+/// attributes under `@name` keys, child elements under their names (a list when a name
+/// repeats, in order of first appearance) and the element's own text under `#text`. An
+/// element with nothing but text is a plain string.
+pub(crate) trait ConfigValue: Sized {
+    /// `None` for a format without null, where `xsi:nil` stays an attribute.
+    fn null() -> Option<Self>;
+    fn string(text: String) -> Self;
+    fn list(values: Vec<Self>) -> Self;
+    fn map(entries: Vec<(String, Self)>) -> Self;
+    fn shape(&self) -> Result<Shape<'_, Self>, ConvertError>;
+}
+
+/// `element` as a value; `map`: whether it is a map even with only text.
+pub(crate) fn from_element<V: ConfigValue>(element: &Element, map: bool) -> V {
+    if element.is_nil() {
+        if let Some(null) = V::null() {
+            return null;
+        }
+    }
+    let mut entries: Vec<(String, V)> = element.attributes_sorted().into_iter()
+        .map(|(name, value)| (format!("@{}", name), V::string(value.to_string())))
+        .collect();
+    let mut groups: Vec<(&str, Vec<V>)> = vec![];
+    for child in element.children.iter().filter_map(Node::as_element) {
+        let value = from_element(child, false);
+        match groups.iter_mut().find(|(name, _)| *name == child.name) {
+            Some((_, group)) => group.push(value),
+            None => groups.push((&child.name, vec![value])),
+        }
+    }
+    for (name, mut group) in groups {
+        let value = match group.len() {
+            1 => group.remove(0),
+            _ => V::list(group),
+        };
+        entries.push((name.to_string(), value));
+    }
+    let text = own_text(element);
+    if entries.is_empty() && !map {
+        return V::string(text);
+    }
+    if !text.is_empty() {
+        entries.push(("#text".to_string(), V::string(text)));
+    }
+    V::map(entries)
+}
+
+/// The element named `name` that `value` maps back to: `@` keys become attributes, `#text`
+/// its text and every other key a child element, one per list item. Null becomes `xsi:nil`.
+pub(crate) fn to_element<V: ConfigValue>(value: &V, name: &str) -> Result<Element, ConvertError> {
+    let mut element = Element::new(name)?;
+    match value.shape()? {
+        Shape::Null => {
+            element.set_attribute("xsi:nil", "true");
+        }
+        Shape::Text(text) => {
+            if !text.is_empty() {
+                element.push_child(Node::CharData(text));
+            }
+        }
+        Shape::List(_) => return Err(ConvertError::new(format!("a list cannot be the single element {}", name))),
+        Shape::Map(entries) => {
+            for (key, value) in entries {
+                let text = || match value.shape()? {
+                    Shape::Text(text) => Ok(text),
+                    Shape::Null => Ok(String::new()),
+                    _ => Err(ConvertError::new(format!("{} of {} is not a string", key, name))),
+                };
+                if let Some(attribute) = key.strip_prefix('@') {
+                    element = element.try_with_attribute(attribute, &text()?)?;
+                } else if key == "#text" {
+                    element.push_child(Node::CharData(text()?));
+                } else if let Shape::List(values) = value.shape()? {
+                    for value in values {
+                        element.push_child(Node::Element(to_element(value, &key)?));
+                    }
+                } else {
+                    element.push_child(Node::Element(to_element(value, &key)?));
+                }
+            }
+        }
+    }
+    Ok(element)
+}
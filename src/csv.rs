@@ -0,0 +1,93 @@
+use std::io;
+
+use crate::{Element, Node};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Attr(String),
+    ChildText(String),
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub header: String,
+    pub selector: Selector,
+}
+
+impl Column {
+    pub fn attr(name: &str) -> Column {
+        Column {
+            header: name.to_string(),
+            selector: Selector::Attr(name.to_string()),
+        }
+    }
+    pub fn child_text(name: &str) -> Column {
+        Column {
+            header: name.to_string(),
+            selector: Selector::ChildText(name.to_string()),
+        }
+    }
+    pub fn text(header: &str) -> Column {
+        Column {
+            header: header.to_string(),
+            selector: Selector::Text,
+        }
+    }
+    pub fn with_header(mut self, header: &str) -> Column {
+        self.header = header.to_string();
+        self
+    }
+
+    fn value(&self, element: &Element) -> String {
+        match &self.selector {
+            Selector::Attr(name) => element.attributes.get(name).cloned().unwrap_or_default(),
+            Selector::ChildText(name) => element.children.iter()
+                .filter_map(Node::as_element)
+                .find(|x| x.is_named(name.as_str()))
+                .map(Element::text)
+                .unwrap_or_default(),
+            Selector::Text => element.text(),
+        }
+    }
+}
+
+fn write_record<W: io::Write>(w: &mut W, fields: impl Iterator<Item=String>) -> io::Result<()> {
+    for (index, field) in fields.enumerate() {
+        if index > 0 {
+            w.write_all(b",")?;
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(w, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            w.write_all(field.as_bytes())?;
+        }
+    }
+    w.write_all(b"\r\n")
+}
+
+/// Writes a header row followed by one RFC 4180 record per element.
+pub fn write_csv<W: io::Write>(mut w: W, elements: &[&Element], columns: &[Column]) -> io::Result<()> {
+    write_record(&mut w, columns.iter().map(|column| column.header.clone()))?;
+    for element in elements {
+        write_record(&mut w, columns.iter().map(|column| column.value(element)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::csv::*;
+
+    #[test]
+    fn csv_export() {
+        let res = Parser::default()
+            .complete_element(r#"<report><row id="1"><name>Ann, Jr.</name></row><row id="2"><name>Bo "B"</name></row></report>"#)
+            .unwrap();
+        let mut out = vec![];
+        let columns = [Column::attr("id"), Column::child_text("name").with_header("Name"), Column::attr("missing")];
+        write_csv(&mut out, &vec![&res].elem_name("row"), &columns).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "id,Name,missing\r\n1,\"Ann, Jr.\",\r\n2,\"Bo \"\"B\"\"\",\r\n");
+    }
+}
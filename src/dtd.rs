@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arena::{NodeData, NodeId, Rejection, Tree};
+use crate::identifier;
+use crate::text::Text;
+
+/// A declaration `Dtd::parse` could not read, at a byte offset into its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtdError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DtdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for DtdError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repeat {
+    Once,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Name(String),
+    Sequence(Vec<Particle>),
+    Choice(Vec<Particle>),
+}
+
+/// One term of an element content model, such as `(head, body?)+`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Particle {
+    kind: Kind,
+    repeat: Repeat,
+}
+
+impl Particle {
+    /// Every position in `names` where a match starting at `start` can end.
+    fn ends(&self, names: &[&str], start: usize) -> Vec<usize> {
+        let mut ends = self.once(names, start);
+        if matches!(self.repeat, Repeat::Optional | Repeat::ZeroOrMore) {
+            push_new(&mut ends, start);
+        }
+        if matches!(self.repeat, Repeat::ZeroOrMore | Repeat::OneOrMore) {
+            let mut i = 0;
+            while i < ends.len() {
+                for end in self.once(names, ends[i]) {
+                    push_new(&mut ends, end);
+                }
+                i += 1;
+            }
+        }
+        ends
+    }
+
+    fn once(&self, names: &[&str], start: usize) -> Vec<usize> {
+        match &self.kind {
+            Kind::Name(name) if names.get(start) == Some(&name.as_str()) => vec![start + 1],
+            Kind::Name(_) => vec![],
+            Kind::Choice(options) => {
+                let mut ends = vec![];
+                for end in options.iter().flat_map(|option| option.ends(names, start)) {
+                    push_new(&mut ends, end);
+                }
+                ends
+            }
+            Kind::Sequence(items) => items.iter().fold(vec![start], |starts, item| {
+                let mut ends = vec![];
+                for end in starts.into_iter().flat_map(|start| item.ends(names, start)) {
+                    push_new(&mut ends, end);
+                }
+                ends
+            }),
+        }
+    }
+}
+
+fn push_new(ends: &mut Vec<usize>, end: usize) {
+    if !ends.contains(&end) {
+        ends.push(end);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Content {
+    Empty,
+    Any,
+    /// `(#PCDATA|a|b)*`: text mixed with the listed elements.
+    Mixed(Vec<String>),
+    Children(Particle),
+}
+
+/// The element and attribute-list declarations of a DTD, to check a `Tree` against as it is
+/// edited: `tree.set_validator(move |tree| dtd.check(tree))`. Entity and notation
+/// declarations are skipped and parameter entities are not expanded; of the attribute
+/// declarations only `#REQUIRED` is enforced.
+#[derive(Debug, Clone, Default)]
+pub struct Dtd {
+    elements: HashMap<String, Content>,
+    required: HashMap<String, Vec<String>>,
+}
+
+impl Dtd {
+    /// Reads an external DTD, or the internal subset of a doctype without its brackets.
+    pub fn parse(text: &str) -> Result<Dtd, DtdError> {
+        let mut cursor = Cursor { text, at: 0 };
+        let mut dtd = Dtd::default();
+        loop {
+            cursor.skip_space();
+            let start = cursor.at;
+            if cursor.rest().is_empty() {
+                return Ok(dtd);
+            } else if cursor.eat("<!--") {
+                cursor.skip_past("-->")?;
+            } else if cursor.eat("<?") {
+                cursor.skip_past("?>")?;
+            } else if cursor.eat("<!ELEMENT") {
+                cursor.space()?;
+                let name = cursor.name()?;
+                cursor.space()?;
+                let content = cursor.content()?;
+                cursor.skip_space();
+                cursor.expect(">")?;
+                if dtd.elements.insert(name.clone(), content).is_some() {
+                    return Err(DtdError { offset: start, message: format!("element {} declared twice", name) });
+                }
+            } else if cursor.eat("<!ATTLIST") {
+                cursor.space()?;
+                let element = cursor.name()?;
+                loop {
+                    cursor.skip_space();
+                    if cursor.eat(">") {
+                        break;
+                    }
+                    let name = cursor.name()?;
+                    cursor.space()?;
+                    cursor.attribute_type()?;
+                    cursor.space()?;
+                    if cursor.default()? {
+                        dtd.required.entry(element.clone()).or_default().push(name);
+                    }
+                }
+            } else if cursor.eat("<!") {
+                cursor.skip_declaration()?;
+            } else if cursor.eat("%") {
+                cursor.skip_past(";")?;
+            } else {
+                return Err(cursor.error("expected a markup declaration"));
+            }
+        }
+    }
+
+    /// Checks every element of `tree`: it must be declared, have its required attributes and
+    /// match its content model. Names the first element in document order that does not.
+    pub fn check<T: Text>(&self, tree: &Tree<T>) -> Result<(), Rejection> {
+        let mut stack = vec![tree.root()];
+        while let Some(id) = stack.pop() {
+            self.check_element(tree, id).map_err(|message| Rejection { node: Some(id), message })?;
+            stack.extend(tree.children(id).iter().rev());
+        }
+        Ok(())
+    }
+
+    fn check_element<T: Text>(&self, tree: &Tree<T>, id: NodeId) -> Result<(), String> {
+        let (name, attributes) = match tree.get(id).map(|node| &node.data) {
+            Some(NodeData::Element { name, attributes }) => (name, attributes),
+            _ => return Ok(()),
+        };
+        let content = self.elements.get(name).ok_or_else(|| format!("element {} is not declared", name))?;
+        if let Some(missing) = self.required.get(name).into_iter().flatten().find(|x| !attributes.contains_key(*x)) {
+            return Err(format!("{} is missing required attribute {}", name, missing));
+        }
+        let mut children = vec![];
+        let mut text = false;
+        for &child in tree.children(id) {
+            match tree.get(child).map(|node| &node.data) {
+                Some(NodeData::Element { name, .. }) => children.push(name.as_str()),
+                Some(NodeData::Foreign(foreign)) => children.push(foreign.name()),
+                Some(NodeData::CharData(data)) => text |= !data.to_text().trim().is_empty(),
+                _ => {}
+            }
+        }
+        let fits = match content {
+            Content::Empty => tree.children(id).is_empty(),
+            Content::Any => true,
+            Content::Mixed(allowed) => children.iter().all(|child| allowed.iter().any(|x| x == child)),
+            Content::Children(particle) => !text && particle.ends(&children, 0).contains(&children.len()),
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(format!("content of {} does not match its declaration", name))
+        }
+    }
+}
+
+struct Cursor<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.at..]
+    }
+
+    fn error(&self, message: &str) -> DtdError {
+        DtdError { offset: self.at, message: message.to_string() }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.at += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), DtdError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", token)))
+        }
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    /// Whitespace the grammar requires.
+    fn space(&mut self) -> Result<(), DtdError> {
+        let at = self.at;
+        self.skip_space();
+        if self.at == at {
+            return Err(self.error("expected whitespace"));
+        }
+        Ok(())
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), DtdError> {
+        match self.rest().find(end) {
+            Some(found) => {
+                self.at += found + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing {}", end))),
+        }
+    }
+
+    /// The rest of a declaration up to its `>`, passing over quoted literals.
+    fn skip_declaration(&mut self) -> Result<(), DtdError> {
+        let mut quote = None;
+        for (i, ch) in self.rest().char_indices() {
+            match (quote, ch) {
+                (Some(q), ch) if ch == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => quote = Some(ch),
+                (None, '>') => {
+                    self.at += i + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated declaration"))
+    }
+
+    fn name(&mut self) -> Result<String, DtdError> {
+        match identifier(self.rest()) {
+            Ok((rest, name)) => {
+                self.at = self.text.len() - rest.len();
+                Ok(name.to_string())
+            }
+            Err(_) => Err(self.error("expected a name")),
+        }
+    }
+
+    fn quoted(&mut self) -> Result<(), DtdError> {
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error("expected a quoted value")),
+        };
+        self.at += 1;
+        self.skip_past(&quote.to_string())
+    }
+
+    fn repeat(&mut self) -> Repeat {
+        if self.eat("?") {
+            Repeat::Optional
+        } else if self.eat("*") {
+            Repeat::ZeroOrMore
+        } else if self.eat("+") {
+            Repeat::OneOrMore
+        } else {
+            Repeat::Once
+        }
+    }
+
+    fn content(&mut self) -> Result<Content, DtdError> {
+        if self.eat("EMPTY") {
+            return Ok(Content::Empty);
+        }
+        if self.eat("ANY") {
+            return Ok(Content::Any);
+        }
+        self.expect("(")?;
+        self.skip_space();
+        if !self.eat("#PCDATA") {
+            return Ok(Content::Children(self.group()?));
+        }
+        let mut names = vec![];
+        loop {
+            self.skip_space();
+            if self.eat(")") {
+                break;
+            }
+            self.expect("|")?;
+            self.skip_space();
+            names.push(self.name()?);
+        }
+        if !self.eat("*") && !names.is_empty() {
+            return Err(self.error("expected * after mixed content"));
+        }
+        Ok(Content::Mixed(names))
+    }
+
+    /// A choice or sequence after its `(`, with any repetition after the `)`.
+    fn group(&mut self) -> Result<Particle, DtdError> {
+        let mut items = vec![self.particle()?];
+        let mut separator = None;
+        loop {
+            self.skip_space();
+            if self.eat(")") {
+                break;
+            }
+            let at = self.at;
+            let next = if self.eat("|") { '|' } else { self.expect(",").map(|_| ',')? };
+            if separator.is_some_and(|separator| separator != next) {
+                return Err(DtdError { offset: at, message: "mixed , and | in one group".to_string() });
+            }
+            separator = Some(next);
+            items.push(self.particle()?);
+        }
+        let kind = match separator {
+            Some('|') => Kind::Choice(items),
+            _ => Kind::Sequence(items),
+        };
+        Ok(Particle { kind, repeat: self.repeat() })
+    }
+
+    fn particle(&mut self) -> Result<Particle, DtdError> {
+        self.skip_space();
+        if self.eat("(") {
+            return self.group();
+        }
+        let name = self.name()?;
+        Ok(Particle { kind: Kind::Name(name), repeat: self.repeat() })
+    }
+
+    fn attribute_type(&mut self) -> Result<(), DtdError> {
+        if self.eat("NOTATION") {
+            self.space()?;
+        }
+        if self.eat("(") {
+            return self.skip_past(")");
+        }
+        self.name().map(|_| ())
+    }
+
+    /// Reads an attribute default, telling whether it is `#REQUIRED`.
+    fn default(&mut self) -> Result<bool, DtdError> {
+        if self.eat("#REQUIRED") {
+            return Ok(true);
+        }
+        if !self.eat("#IMPLIED") {
+            if self.eat("#FIXED") {
+                self.space()?;
+            }
+            self.quoted()?;
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::arena::*;
+    use crate::dtd::*;
+
+    const DTD: &str = r#"
+        <!-- a note list -->
+        <!ELEMENT notes (title?, (note | group)+)>
+        <!ELEMENT title (#PCDATA)>
+        <!ELEMENT group (note*)>
+        <!ELEMENT note (#PCDATA | b)*>
+        <!ELEMENT b (#PCDATA)>
+        <!ELEMENT br EMPTY>
+        <!ATTLIST note id ID #REQUIRED
+                       kind (todo | done) "todo"
+                       lang CDATA #IMPLIED>
+        <!ENTITY sig "-- me">
+    "#;
+
+    #[test]
+    fn dtd_validation() {
+        let dtd = Dtd::parse(DTD).unwrap();
+        let element = Parser::default()
+            .complete_element("<notes>\n  <title>t</title>\n  <note id='1'>a <b>b</b></note>\n  <group/>\n</notes>")
+            .unwrap();
+        let mut tree = Tree::from_element(&element);
+        assert_eq!(dtd.check(&tree), Ok(()));
+        let root = tree.root();
+        let title = tree.children(root)[0];
+        let note = tree.children(root)[1];
+        let group = tree.children(root)[2];
+        tree.set_validator(move |tree| dtd.check(tree));
+
+        assert!(tree.remove_attribute(note, "id").is_none());
+        assert_eq!(tree.last_rejection(), Some("note is missing required attribute id"));
+        assert_eq!(tree.last_rejected_node(), Some(note));
+        assert!(tree.insert(root, 0, NodeData::Element { name: "title".to_string(), attributes: Default::default() }).is_none());
+        assert_eq!(tree.last_rejection(), Some("content of notes does not match its declaration"));
+        assert_eq!(tree.last_rejected_node(), Some(root));
+        assert!(tree.append(note, NodeData::Element { name: "br".to_string(), attributes: Default::default() }).is_none());
+        assert_eq!(tree.last_rejected_node(), Some(note));
+        assert!(tree.append(root, NodeData::CharData("loose".to_string())).is_none());
+        assert!(tree.remove(title));
+        assert!(tree.append(note, NodeData::CharData(" more".to_string())).is_some());
+        assert_eq!(tree.last_rejected_node(), None);
+        assert!(tree.remove(group));
+        assert!(!tree.remove(note));
+        assert_eq!(tree.last_rejected_node(), Some(root));
+    }
+
+    #[test]
+    fn dtd_syntax() {
+        assert!(Dtd::parse("<!ELEMENT a (b, (c | d)*, e+)?> <?pi x?> %ext; <!NOTATION n SYSTEM 'x>y'>").is_ok());
+        let error = |text: &str| Dtd::parse(text).unwrap_err();
+        assert_eq!(error("<!ELEMENT a (b | c, d)>"), DtdError { offset: 18, message: "mixed , and | in one group".to_string() });
+        assert_eq!(error("<!ELEMENT a (#PCDATA | b)>").message, "expected * after mixed content");
+        assert_eq!(error("<!ELEMENT a EMPTY><!ELEMENT a ANY>").message, "element a declared twice");
+        assert_eq!(error("<!ATTLIST a b CDATA>").message, "expected whitespace");
+        assert_eq!(error("<!-- open").offset, 4);
+        assert_eq!(error("text").message, "expected a markup declaration");
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+/// The character encodings documents can be read from and written back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1: each byte is the code point of the same value.
+    Latin1,
+    /// Latin-1 with printable characters in 0x80-0x9F; the five unassigned bytes there map to the
+    /// C1 control of the same value, as browsers do, so every byte round-trips.
+    Windows1252,
+    Ascii,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingError {
+    /// Byte offset into the input when decoding, into the text when encoding.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Characters for bytes 0x80-0x9F in windows-1252.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl Encoding {
+    /// Looks up the value of an `encoding` declaration, ignoring case. A bare `UTF-16` is
+    /// big-endian, as it is without a byte order mark.
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        Some(match label.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-16" | "utf-16be" => Encoding::Utf16Be,
+            "utf-16le" => Encoding::Utf16Le,
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" => Encoding::Latin1,
+            "windows-1252" | "cp1252" => Encoding::Windows1252,
+            "us-ascii" | "ascii" => Encoding::Ascii,
+            _ => return None,
+        })
+    }
+
+    pub fn byte_order_mark(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xef\xbb\xbf",
+            Encoding::Utf16Le => b"\xff\xfe",
+            Encoding::Utf16Be => b"\xfe\xff",
+            Encoding::Latin1 | Encoding::Windows1252 | Encoding::Ascii => b"",
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<String, EncodingError> {
+        let error = |offset, message: &str| EncodingError { offset, message: message.to_string() };
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|e| error(e.utf8_error().valid_up_to(), "invalid UTF-8")),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if bytes.len() % 2 != 0 {
+                    return Err(error(bytes.len() - 1, "truncated UTF-16"));
+                }
+                let units = bytes.chunks(2).map(|pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                let mut text = String::with_capacity(bytes.len() / 2);
+                let mut offset = 0;
+                for ch in char::decode_utf16(units) {
+                    let ch = ch.map_err(|_| error(offset, "unpaired UTF-16 surrogate"))?;
+                    offset += ch.len_utf16() * 2;
+                    text.push(ch);
+                }
+                Ok(text)
+            }
+            Encoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            Encoding::Windows1252 => Ok(bytes.iter().map(|&byte| match byte {
+                0x80..=0x9f => WINDOWS_1252[usize::from(byte - 0x80)],
+                _ => char::from(byte),
+            }).collect()),
+            Encoding::Ascii => match bytes.iter().position(|byte| !byte.is_ascii()) {
+                Some(offset) => Err(error(offset, "non-ASCII byte")),
+                None => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            },
+        }
+    }
+
+    /// Fails on the first character the encoding cannot represent.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, EncodingError> {
+        let single = |ch: char| -> Option<u8> {
+            let code = u32::from(ch);
+            match self {
+                Encoding::Ascii if code < 0x80 => Some(code as u8),
+                Encoding::Latin1 if code < 0x100 => Some(code as u8),
+                Encoding::Windows1252 if code < 0x80 || (0xa0..0x100).contains(&code) => Some(code as u8),
+                Encoding::Windows1252 => WINDOWS_1252.iter().position(|&x| x == ch).map(|i| 0x80 + i as u8),
+                _ => None,
+            }
+        };
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 | Encoding::Windows1252 | Encoding::Ascii => text.char_indices()
+                .map(|(offset, ch)| single(ch).ok_or_else(|| EncodingError {
+                    offset,
+                    message: format!("{:?} cannot be encoded as {:?}", ch, self),
+                }))
+                .collect(),
+        }
+    }
+}
+
+/// Finds the encoding of a document from its byte order mark, else from the layout of its
+/// first characters, else from its XML declaration, defaulting to UTF-8. Returns it with
+/// whether there was a byte order mark.
+pub fn detect(bytes: &[u8]) -> Result<(Encoding, bool), EncodingError> {
+    for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if bytes.starts_with(encoding.byte_order_mark()) {
+            return Ok((encoding, true));
+        }
+    }
+    if bytes.starts_with(b"<\0?\0") {
+        return Ok((Encoding::Utf16Le, false));
+    }
+    if bytes.starts_with(b"\0<\0?") {
+        return Ok((Encoding::Utf16Be, false));
+    }
+    // The declaration is ASCII in every encoding left.
+    let declaration = match (bytes.starts_with(b"<?xml"), bytes.windows(2).position(|pair| pair == b"?>")) {
+        (true, Some(end)) => String::from_utf8_lossy(&bytes[..end]),
+        _ => return Ok((Encoding::Utf8, false)),
+    };
+    let label = declaration.find("encoding").and_then(|at| {
+        let rest = declaration[at + "encoding".len()..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|&quote| quote == '"' || quote == '\'')?;
+        rest[1..].split(quote).next()
+    });
+    match label {
+        None => Ok((Encoding::Utf8, false)),
+        Some(label) => Encoding::for_label(label).map(|encoding| (encoding, false)).ok_or_else(|| EncodingError {
+            offset: 0,
+            message: format!("unsupported encoding {:?}", label),
+        }),
+    }
+}
+
+/// Decodes a whole document, dropping any byte order mark.
+pub fn decode(bytes: &[u8]) -> Result<(String, Encoding, bool), EncodingError> {
+    let (encoding, bom) = detect(bytes)?;
+    let skip = if bom { encoding.byte_order_mark().len() } else { 0 };
+    let text = encoding.decode(&bytes[skip..]).map_err(|e| EncodingError { offset: e.offset + skip, ..e })?;
+    Ok((text, encoding, bom))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::encoding::*;
+
+    #[test]
+    fn codecs() {
+        let text = "<a>caf\u{e9} \u{20ac}5</a>";
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Windows1252] {
+            assert_eq!(encoding.decode(&encoding.encode(text).unwrap()).unwrap(), text, "{:?}", encoding);
+        }
+        assert_eq!(Encoding::Windows1252.encode("\u{20ac}\u{81}").unwrap(), b"\x80\x81");
+        assert_eq!(Encoding::Latin1.encode(text).unwrap_err().offset, 9);
+        assert_eq!(Encoding::Ascii.decode(b"a\xe9").unwrap_err().offset, 1);
+        assert_eq!(detect(b"<?xml version='1.0' encoding = 'Latin1'?><a/>").unwrap(), (Encoding::Latin1, false));
+        assert_eq!(detect(b"\xfe\xff\0<").unwrap(), (Encoding::Utf16Be, true));
+        assert_eq!(detect(b"<a/>").unwrap(), (Encoding::Utf8, false));
+        assert!(detect(b"<?xml version=\"1.0\" encoding=\"EBCDIC\"?>").is_err());
+        assert!(Parser::default().complete_document_bytes(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><p>\xe9</p>").is_err());
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn byte_round_trip() {
+        let parser = Parser::default();
+        let latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><p lang=\"fr\">caf\xe9</p>";
+        let document = parser.complete_document_bytes(latin1).unwrap();
+        assert_eq!(document.root().text(), "caf\u{e9}");
+        assert_eq!(document.source_encoding(), Some((Encoding::Latin1, false)));
+        assert_eq!(document.to_bytes().unwrap(), latin1);
+
+        let mut utf16 = Encoding::Utf16Le.byte_order_mark().to_vec();
+        utf16.extend(Encoding::Utf16Le.encode("<?xml version=\"1.0\" encoding=\"UTF-16\"?><p>\u{1f600}</p>").unwrap());
+        let document = parser.complete_document_bytes(&utf16).unwrap();
+        assert_eq!(document.to_bytes().unwrap(), utf16);
+
+        let mut edited = document.with_encoding("us-ascii");
+        assert_eq!(edited.source_encoding(), None);
+        assert!(edited.to_bytes().is_err());
+        edited.root_mut().children.clear();
+        assert_eq!(edited.to_bytes().unwrap(), b"<?xml version=\"1.0\" encoding=\"us-ascii\"?><p/>");
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Element, Node, Parser};
+
+/// Where `expand` gets included files and variables from.
+pub trait Resolver {
+    /// Loads `file` as referenced from the document at `from`, `None` being the top-level
+    /// document, and returns its resolved location, used to report errors and detect cycles,
+    /// and its contents.
+    fn load(&self, file: &str, from: Option<&str>) -> io::Result<(String, String)>;
+
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+/// Loads includes from disk, relative to the including file or else to `base`, and reads
+/// variables from the process environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileResolver {
+    pub base: PathBuf,
+}
+
+impl Resolver for FileResolver {
+    fn load(&self, file: &str, from: Option<&str>) -> io::Result<(String, String)> {
+        let dir = from.and_then(|from| Path::new(from).parent()).unwrap_or(&self.base);
+        let path = dir.join(file);
+        let contents = std::fs::read_to_string(&path)?;
+        Ok((path.to_string_lossy().into_owned(), contents))
+    }
+}
+
+/// Files and variables held in memory, for tests and embedded defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryResolver {
+    pub files: HashMap<String, String>,
+    pub vars: HashMap<String, String>,
+}
+
+impl Resolver for MemoryResolver {
+    fn load(&self, file: &str, _from: Option<&str>) -> io::Result<(String, String)> {
+        match self.files.get(file) {
+            Some(contents) => Ok((file.to_string(), contents.clone())),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no file {}", file))),
+        }
+    }
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandError {
+    /// The included file the problem is in, `None` for the top-level document.
+    pub file: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}: {}", file, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+struct Expander<'p, 'r, R: ?Sized> {
+    parser: &'p Parser,
+    resolver: &'r R,
+    /// Files being expanded, outermost first.
+    stack: Vec<String>,
+}
+
+impl<'p, 'r, R: Resolver + ?Sized> Expander<'p, 'r, R> {
+    fn error(&self, message: String) -> ExpandError {
+        ExpandError { file: self.stack.last().cloned(), message }
+    }
+
+    /// Replaces `${NAME}` and `${NAME:-default}` with variables; `$${` stands for a literal `${`.
+    fn substitute(&self, value: &str) -> Result<String, ExpandError> {
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                out.push_str(&rest[..start - 1]);
+                out.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            out.push_str(&rest[..start]);
+            let end = rest[start..].find('}')
+                .ok_or_else(|| self.error(format!("unterminated variable in {:?}", value)))?;
+            let reference = &rest[start + 2..start + end];
+            let (name, default) = match reference.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (reference, None),
+            };
+            match self.resolver.var(name).or_else(|| default.map(str::to_string)) {
+                Some(value) => out.push_str(&value),
+                None => return Err(self.error(format!("undefined variable {}", name))),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn include(&mut self, include: &Element) -> Result<Element, ExpandError> {
+        let file = include.attribute("file").ok_or_else(|| self.error("include without a file attribute".to_string()))?;
+        let file = self.substitute(file)?;
+        let (location, contents) = self.resolver.load(&file, self.stack.last().map(String::as_str))
+            .map_err(|e| self.error(format!("cannot include {}: {}", file, e)))?;
+        if self.stack.contains(&location) {
+            return Err(self.error(format!("{} includes itself", location)));
+        }
+        let root = self.parser.complete_document(&contents).map(|document| document.root)
+            .or_else(|_| self.parser.complete_element(&contents));
+        self.stack.push(location);
+        let mut root = root.map_err(|e| self.error(format!("malformed document: {}", e)))?;
+        self.element(&mut root)?;
+        self.stack.pop();
+        Ok(root)
+    }
+
+    fn element(&mut self, element: &mut Element) -> Result<(), ExpandError> {
+        for value in element.attributes.values_mut() {
+            *value = self.substitute(value)?;
+        }
+        for node in &mut element.children {
+            match node {
+                Node::CharData(data) => *data = self.substitute(data)?,
+                Node::Element(child) if child.name == "include" => *child = self.include(child)?,
+                Node::Element(child) => self.element(child)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opt-in pass for config documents: expands `${NAME}` references in attribute values and text
+/// and replaces every `<include file="..."/>` with the root element of that file, expanded the
+/// same way. `from` is the location of `element`'s own document, if it has one.
+pub fn expand<R: Resolver + ?Sized>(parser: &Parser, element: &mut Element, resolver: &R, from: Option<&str>) -> Result<(), ExpandError> {
+    let mut expander = Expander { parser, resolver, stack: from.map(str::to_string).into_iter().collect() };
+    if element.name == "include" {
+        *element = expander.include(element)?;
+        return Ok(());
+    }
+    expander.element(element)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::expand::*;
+
+    #[test]
+    fn config_expansion() {
+        let parser = Parser::default();
+        let mut resolver = MemoryResolver::default();
+        resolver.vars.insert("HOST".to_string(), "db.local".to_string());
+        resolver.files.insert("db.xml".to_string(), r#"<?xml version="1.0"?><db host="${HOST}" port="${PORT:-5432}"/>"#.to_string());
+        resolver.files.insert("loop.xml".to_string(), r#"<a><include file="loop.xml"/></a>"#.to_string());
+
+        let mut config = parser.complete_element(r#"<config><include file="db.xml"/><price>$${HOST}</price></config>"#).unwrap();
+        expand(&parser, &mut config, &resolver, None).unwrap();
+        let db = config.children()[0].as_element().unwrap();
+        assert_eq!((db.name(), db.attribute("host"), db.attribute("port")), ("db", Some("db.local"), Some("5432")));
+        assert_eq!(config.text(), "${HOST}");
+
+        let mut looped = parser.complete_element(r#"<config><include file="loop.xml"/></config>"#).unwrap();
+        let error = expand(&parser, &mut looped, &resolver, None).unwrap_err();
+        assert_eq!(error.to_string(), "loop.xml: loop.xml includes itself");
+        let mut undefined = parser.complete_element("<config>${USER_NAME_UNSET}</config>").unwrap();
+        assert_eq!(expand(&parser, &mut undefined, &resolver, None).unwrap_err().message, "undefined variable USER_NAME_UNSET");
+    }
+}
@@ -0,0 +1,138 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::tokens::{tokens, TokenKind};
+
+/// Takes over parsing an element registered in `Parser::foreign`: given the input from the
+/// element's `<`, returns how many bytes the element spans and what they hold, or `None` if
+/// they are malformed, which fails the parse.
+pub type ForeignParser = fn(&str) -> Option<(usize, Arc<dyn Any + Send + Sync>)>;
+
+/// An element a `ForeignParser` parsed, kept as its source and the parser's result.
+#[derive(Clone)]
+pub struct Foreign {
+    pub(crate) name: String,
+    pub(crate) source: String,
+    pub(crate) value: Arc<dyn Any + Send + Sync>,
+}
+
+impl Foreign {
+    /// The element name, as normalized by the parser.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The whole element as written, start tag to end tag.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    /// What the foreign parser produced, if it is a `T`.
+    pub fn value<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Foreign").field("name", &self.name).field("source", &self.source).finish_non_exhaustive()
+    }
+}
+
+/// Equal when written the same way; the parsed values are not compared.
+impl PartialEq for Foreign {
+    fn eq(&self, other: &Foreign) -> bool {
+        self.name == other.name && self.source == other.source
+    }
+}
+
+impl Eq for Foreign {}
+
+/// Length of the element starting at the beginning of `input`, found by matching tags without
+/// building anything, for foreign parsers that only need the raw markup.
+pub fn element_len(input: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut closing = false;
+    for (kind, span) in tokens(input) {
+        match kind {
+            TokenKind::TagOpen => depth += 1,
+            TokenKind::EndTagOpen => {
+                depth = depth.checked_sub(1)?;
+                closing = true;
+            }
+            TokenKind::EmptyTagClose | TokenKind::TagClose => {
+                if kind == TokenKind::EmptyTagClose {
+                    depth = depth.checked_sub(1)?;
+                }
+                if depth == 0 && (closing || kind == TokenKind::EmptyTagClose) {
+                    return Some(span.end);
+                }
+                closing = false;
+            }
+            TokenKind::Error => return None,
+            _ if depth == 0 && !closing => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::Arc;
+
+    use crate::*;
+    use crate::foreign::*;
+
+    /// Stands in for a MathML parser: keeps the text of every `<mi>`.
+    fn identifiers(input: &str) -> Option<(usize, Arc<dyn Any + Send + Sync>)> {
+        let len = element_len(input)?;
+        let names: Vec<String> = input[..len].split("<mi>").skip(1)
+            .map(|rest| rest.split('<').next().unwrap_or_default().to_string())
+            .collect();
+        Some((len, Arc::new(names)))
+    }
+
+    #[test]
+    fn foreign_subtrees() {
+        let parser = Parser { foreign: vec![("math".to_string(), identifiers)], ..Default::default() };
+        let p = parser.complete_element("<p>area <MATH display='inline'><mi>r</mi><mo>&pi;</mo><mi>x</mi></MATH>!</p>").unwrap();
+        let foreign = match &p.children[1] {
+            Node::Foreign(foreign) => foreign,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(foreign.name(), "math");
+        assert_eq!(foreign.source(), "<MATH display='inline'><mi>r</mi><mo>&pi;</mo><mi>x</mi></MATH>");
+        assert_eq!(foreign.value::<Vec<String>>(), Some(&vec!["r".to_string(), "x".to_string()]));
+        assert_eq!(p.text(), "area !");
+        assert!(parser.complete_element("<p><math><mi></p>").is_err());
+        assert_eq!(element_len("<a><b/><a>x</a></a>tail"), Some(19));
+    }
+
+    #[test]
+    fn foreign_everywhere() {
+        let parser = Parser { foreign: vec![("math".to_string(), identifiers)], ..Default::default() };
+        let input = "<r><rec><math><mi>x</mi></math></rec></r>";
+        let tree = parser.complete_element(input).unwrap();
+        let record = reader::Reader::new(&parser, input).split_on("rec").next().unwrap().unwrap();
+        assert_eq!(&record, tree.children[0].as_element().unwrap());
+        assert!(matches!(&record.children[0], Node::Foreign(foreign) if foreign.source() == "<math><mi>x</mi></math>"));
+        assert_eq!(arena::Tree::from_element(&tree).to_element(), tree);
+        let last = reader::Reader::new(&parser, "<r><math><mi></r>").last().unwrap();
+        assert_eq!(last.unwrap_err().message, "malformed foreign element");
+
+        for input in [input, "<p>area <MATH><mi>r</mi><mo>&pi;</mo></MATH>! <b/></p>", "<r><math><mi></r>", "<r><math/><math></r>"] {
+            let borrowed = borrowed::parse_borrowed(&parser, input).map(|element| element.to_element());
+            assert_eq!(borrowed, parser.complete_element(input), "{}", input);
+        }
+        let borrowed = borrowed::parse_borrowed(&parser, "<r>x<math><mi>y</mi></math></r>").unwrap();
+        match &borrowed.children[1] {
+            borrowed::BorrowedNode::Foreign(foreign) => {
+                assert_eq!((foreign.name.as_ref(), foreign.source), ("math", "<math><mi>y</mi></math>"));
+                assert_eq!(foreign.value::<Vec<String>>(), Some(&vec!["y".to_string()]));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(borrowed.text(), "x");
+    }
+}
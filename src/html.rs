@@ -0,0 +1,692 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "write")]
+use crate::writer::{escape_attribute, escape_text, is_forbidden};
+#[cfg(feature = "write")]
+use crate::{Document, NameToken};
+use crate::encoding::{self, Encoding, EncodingError};
+use crate::{doctype_body, Element, Error, Node, Parser, QuerySupport};
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure",
+    "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav",
+    "ol", "p", "pre", "section", "table", "tr", "ul",
+];
+
+const SKIPPED_ELEMENTS: &[&str] = &["head", "script", "style", "template"];
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+pub fn is_block_element(name: &str) -> bool {
+    BLOCK_ELEMENTS.iter().any(|x| x.eq_ignore_ascii_case(name))
+}
+
+/// Elements that never have content, such as `<br>`, usually listed in `allow_no_close`.
+pub fn is_void_element(name: &str) -> bool {
+    VOID_ELEMENTS.iter().any(|x| x.eq_ignore_ascii_case(name))
+}
+
+struct PlainText {
+    out: String,
+}
+
+impl PlainText {
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn newline(&mut self) {
+        while self.out.ends_with(' ') {
+            self.out.pop();
+        }
+        self.out.push('\n');
+    }
+
+    fn block_boundary(&mut self) {
+        if !self.at_line_start() {
+            self.newline();
+        }
+    }
+
+    fn text(&mut self, data: &str) {
+        for (index, word) in data.split_whitespace().enumerate() {
+            let separated = index > 0 || data.starts_with(char::is_whitespace);
+            if separated && !self.at_line_start() && !self.out.ends_with(' ') {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if data.ends_with(char::is_whitespace) && !data.trim().is_empty() {
+            self.out.push(' ');
+        }
+    }
+
+    fn element(&mut self, element: &Element) {
+        if SKIPPED_ELEMENTS.iter().any(|x| element.is_named(*x)) {
+            return;
+        }
+        if element.is_named("br") {
+            self.newline();
+            return;
+        }
+        let block = is_block_element(&element.name);
+        if block {
+            self.block_boundary();
+        }
+        for child in &element.children {
+            match child {
+                Node::CharData(data) => self.text(data),
+                Node::Element(child) => self.element(child),
+                Node::Comment(_) | Node::ProcessingInstruction { .. } | Node::Foreign(_) => {}
+            }
+        }
+        if block {
+            self.block_boundary();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub href: String,
+    pub text: String,
+    pub rel: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub src: String,
+    pub alt: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormField {
+    pub name: String,
+    /// The `type` of an `<input>`, or `select`/`textarea` for those elements.
+    pub kind: String,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form {
+    pub action: Option<String>,
+    pub method: String,
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MicrodataValue {
+    Text(String),
+    Item(MicrodataItem),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MicrodataItem {
+    pub item_type: Option<String>,
+    pub id: Option<String>,
+    pub properties: HashMap<String, Vec<MicrodataValue>>,
+}
+
+impl MicrodataItem {
+    fn from_scope(element: &Element) -> MicrodataItem {
+        let mut item = MicrodataItem {
+            item_type: element.attributes.get("itemtype").cloned(),
+            id: element.attributes.get("itemid").cloned(),
+            properties: HashMap::new(),
+        };
+        item.collect(element);
+        item
+    }
+
+    fn collect(&mut self, element: &Element) {
+        for child in element.children.iter().filter_map(Node::as_element) {
+            if let Some(names) = child.attributes.get("itemprop") {
+                let value = microdata_value(child);
+                for name in names.split_whitespace() {
+                    self.properties.entry(name.to_string()).or_default().push(value.clone());
+                }
+            }
+            if !child.attributes.contains_key("itemscope") {
+                self.collect(child);
+            }
+        }
+    }
+
+    /// The first text value of a property, the common case for scalar properties like `name`.
+    pub fn text(&self, property: &str) -> Option<&str> {
+        self.properties.get(property)?.iter().find_map(|x| match x {
+            MicrodataValue::Text(text) => Some(text.as_str()),
+            MicrodataValue::Item(_) => None,
+        })
+    }
+}
+
+fn microdata_value(element: &Element) -> MicrodataValue {
+    if element.attributes.contains_key("itemscope") {
+        return MicrodataValue::Item(MicrodataItem::from_scope(element));
+    }
+    let attribute = match element.name.to_ascii_lowercase().as_str() {
+        "meta" => "content",
+        "a" | "area" | "link" => "href",
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => "src",
+        "object" => "data",
+        "time" => "datetime",
+        "data" | "meter" => "value",
+        _ => return MicrodataValue::Text(element.text().trim().to_string()),
+    };
+    MicrodataValue::Text(element.attributes.get(attribute).cloned().unwrap_or_default())
+}
+
+fn scheme_len(url: &str) -> Option<usize> {
+    let colon = url.find(':')?;
+    let scheme = &url[..colon];
+    let valid = scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && scheme.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '+' || ch == '-' || ch == '.');
+    if valid {
+        Some(colon + 1)
+    } else {
+        None
+    }
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let parts: Vec<&str> = path.split('/').collect();
+    for (index, part) in parts.iter().enumerate() {
+        let last = index == parts.len() - 1;
+        match *part {
+            "." => if last { segments.push("") },
+            ".." => {
+                if segments.len() > 1 {
+                    segments.pop();
+                }
+                if last {
+                    segments.push("");
+                }
+            }
+            part => segments.push(part),
+        }
+    }
+    segments.join("/")
+}
+
+/// Resolves `reference` against `base` following the RFC 3986 merge rules for the common cases.
+pub fn resolve_url(base: &str, reference: &str) -> String {
+    let reference = reference.trim();
+    if scheme_len(reference).is_some() {
+        return reference.to_string();
+    }
+    let scheme_end = match scheme_len(base) {
+        Some(end) => end,
+        None => return reference.to_string(),
+    };
+    let base = base.split('#').next().unwrap_or(base);
+    if let Some(rest) = reference.strip_prefix("//") {
+        return format!("{}//{}", &base[..scheme_end], rest);
+    }
+    let authority_end = match base[scheme_end..].strip_prefix("//") {
+        Some(rest) => scheme_end + 2 + rest.find(['/', '?']).unwrap_or(rest.len()),
+        None => scheme_end,
+    };
+    let (origin, base_path) = base.split_at(authority_end);
+    if reference.is_empty() {
+        return base.to_string();
+    }
+    if reference.starts_with('#') {
+        return format!("{}{}", base, reference);
+    }
+    if reference.starts_with('?') {
+        let path = base_path.split('?').next().unwrap_or(base_path);
+        return format!("{}{}{}", origin, path, reference);
+    }
+    let (reference_path, suffix) = match reference.find(['?', '#']) {
+        Some(index) => reference.split_at(index),
+        None => (reference, ""),
+    };
+    let path = if reference_path.starts_with('/') {
+        reference_path.to_string()
+    } else {
+        let base_path = base_path.split('?').next().unwrap_or(base_path);
+        let directory = match base_path.rfind('/') {
+            Some(index) => &base_path[..=index],
+            None => "/",
+        };
+        format!("{}{}", directory, reference_path)
+    };
+    format!("{}{}{}", origin, remove_dot_segments(&path), suffix)
+}
+
+fn resolve(base: Option<&str>, url: &str) -> String {
+    match base {
+        Some(base) => resolve_url(base, url),
+        None => url.to_string(),
+    }
+}
+
+impl Element {
+    pub fn links(&self, base: Option<&str>) -> Vec<Link> {
+        vec![self].elem_where(|x| x.is_named("a") || x.is_named("area"))
+            .into_iter()
+            .filter_map(|x| Some(Link {
+                href: resolve(base, x.attributes.get("href")?),
+                text: x.to_plain_text(),
+                rel: x.attributes.get("rel").cloned(),
+            }))
+            .collect()
+    }
+
+    pub fn images(&self, base: Option<&str>) -> Vec<Image> {
+        self.select_iter("img")
+            .filter_map(|x| Some(Image {
+                src: resolve(base, x.attributes.get("src")?),
+                alt: x.attributes.get("alt").cloned(),
+            }))
+            .collect()
+    }
+
+    pub fn forms(&self, base: Option<&str>) -> Vec<Form> {
+        self.select_iter("form")
+            .map(|form| Form {
+                action: form.attributes.get("action").map(|action| resolve(base, action)),
+                method: form.attributes.get("method").map_or("get".to_string(), |x| x.to_ascii_lowercase()),
+                fields: form.children()
+                    .elem_where(|x| x.is_named("input") || x.is_named("select") || x.is_named("textarea"))
+                    .into_iter()
+                    .filter_map(|x| Some(FormField {
+                        name: x.attributes.get("name")?.clone(),
+                        kind: if x.is_named("input") {
+                            x.attributes.get("type").map_or("text".to_string(), |x| x.to_ascii_lowercase())
+                        } else {
+                            x.name.clone()
+                        },
+                        value: if x.is_named("input") {
+                            x.attributes.get("value").cloned()
+                        } else {
+                            Some(x.text())
+                        },
+                    }))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// `<meta>` contents keyed by their `property` (Open Graph style) or `name` attribute.
+    pub fn meta_tags(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        for meta in self.select_iter("meta") {
+            let key = meta.attributes.get("property").or_else(|| meta.attributes.get("name"));
+            if let (Some(key), Some(content)) = (key, meta.attributes.get("content")) {
+                map.entry(key.to_ascii_lowercase()).or_insert_with(|| content.clone());
+            }
+        }
+        map
+    }
+
+    /// Top-level schema.org style microdata items, i.e. `itemscope` elements that are not themselves
+    /// properties, in document order. Such an item nested inside another is listed as well.
+    pub fn microdata_items(&self) -> Vec<MicrodataItem> {
+        fn visit<'a>(element: &'a Element, scopes: &mut Vec<&'a Element>) {
+            if element.attributes.contains_key("itemscope") && !element.attributes.contains_key("itemprop") {
+                scopes.push(element);
+            }
+            for child in element.children.iter().filter_map(Node::as_element) {
+                visit(child, scopes);
+            }
+        }
+        let mut scopes = vec![];
+        visit(self, &mut scopes);
+        scopes.into_iter().map(MicrodataItem::from_scope).collect()
+    }
+
+    /// Readable text for HTML content: block elements and `<br>` start new lines, and runs of
+    /// whitespace inside a line collapse to a single space.
+    pub fn to_plain_text(&self) -> String {
+        let mut text = PlainText { out: String::new() };
+        text.element(self);
+        let lines: Vec<&str> = text.out.lines().map(str::trim_end).collect();
+        lines.join("\n").trim().to_string()
+    }
+}
+
+/// How `parse_auto` parsed a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Strict, with the default `Parser`.
+    Xml,
+    /// Lenient, with void elements allowed to go unclosed.
+    Html,
+}
+
+/// Skips the XML declaration, comments, processing instructions and doctype before the root,
+/// returning the rest and the doctype's body.
+fn skip_prolog(mut text: &str) -> (&str, Option<&str>) {
+    let mut doctype = None;
+    loop {
+        text = text.trim_start();
+        let skipped = if text.starts_with("<?") {
+            text.find("?>").map(|end| &text[end + 2..])
+        } else if text.starts_with("<!--") {
+            text.find("-->").map(|end| &text[end + 3..])
+        } else if text.get(..9).is_some_and(|x| x.eq_ignore_ascii_case("<!doctype")) {
+            doctype_body(&text[9..]).ok().map(|(rest, body)| {
+                doctype = Some(body);
+                rest
+            })
+        } else {
+            None
+        };
+        match skipped {
+            Some(rest) => text = rest,
+            None => return (text, doctype),
+        }
+    }
+}
+
+/// Picks the mode for a decoded document: from the MIME type of `content_type` when it names
+/// HTML or XML, else from an XML declaration, else from the doctype (XHTML doctypes mean XML),
+/// else from whether the root is `<html>`.
+pub fn sniff_mode(text: &str, content_type: Option<&str>) -> Mode {
+    let essence = content_type.and_then(|x| x.split(';').next()).map(|x| x.trim().to_ascii_lowercase());
+    match essence.as_deref() {
+        Some("text/html") => return Mode::Html,
+        Some(x) if x == "text/xml" || x == "application/xml" || x.ends_with("+xml") => return Mode::Xml,
+        _ => {}
+    }
+    if text.trim_start().starts_with("<?xml") {
+        return Mode::Xml;
+    }
+    match skip_prolog(text) {
+        (_, Some(doctype)) => {
+            let doctype = doctype.trim().to_ascii_lowercase();
+            if doctype.starts_with("html") && !doctype.contains("xhtml") { Mode::Html } else { Mode::Xml }
+        }
+        (root, None) if root.get(..5).is_some_and(|x| x.eq_ignore_ascii_case("<html")) => Mode::Html,
+        _ => Mode::Xml,
+    }
+}
+
+/// Decodes and parses a fetched document, as a crawler gets it, in the mode `sniff_mode`
+/// picks. The encoding comes from a byte order mark, else the `charset` of `content_type`, else
+/// as `encoding::detect` finds it. Errors are located in the decoded text.
+pub fn parse_auto(bytes: &[u8], content_type: Option<&str>) -> Result<(Mode, Element), Error> {
+    let charset = content_type.into_iter()
+        .flat_map(|x| x.split(';').skip(1))
+        .find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches(['"', '\'']))
+        })
+        .and_then(Encoding::for_label);
+    let text = match (encoding::detect(bytes), charset) {
+        (Ok((encoding, true)), _) => {
+            let skip = encoding.byte_order_mark().len();
+            encoding.decode(&bytes[skip..]).map_err(|e| EncodingError { offset: e.offset + skip, ..e })
+        }
+        (_, Some(encoding)) => encoding.decode(bytes),
+        (detected, None) => detected.and_then(|(encoding, _)| encoding.decode(bytes)),
+    }.map_err(Error::Encoding)?;
+    let mode = sniff_mode(&text, content_type);
+    let parser = match mode {
+        Mode::Xml => Parser::default(),
+        Mode::Html => Parser {
+            allow_no_close: VOID_ELEMENTS.iter().map(|x| x.to_string()).collect(),
+            lenient: true,
+            ..Default::default()
+        },
+    };
+    let (root, _) = skip_prolog(&text);
+    let root = parser.complete_element(root).map_err(|e| e.within(&text, text.len() - root.len()))?;
+    Ok((mode, root))
+}
+
+/// Something in an HTML tree with no well-formed XML equivalent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unconvertible {
+    /// Where it is, as given by `Element::breadcrumb`.
+    pub path: String,
+    pub message: String,
+}
+
+#[cfg(feature = "write")]
+struct StrictXml<'d> {
+    root: &'d Element,
+    out: String,
+    problems: Vec<Unconvertible>,
+}
+
+#[cfg(feature = "write")]
+impl StrictXml<'_> {
+    fn problem(&mut self, element: &Element, message: String) {
+        let path = element.breadcrumb(self.root).expect("element is below the root");
+        self.problems.push(Unconvertible { path, message });
+    }
+
+    fn element(&mut self, element: &Element) {
+        let name = element.name.to_ascii_lowercase();
+        if NameToken::new(&name).is_err() {
+            self.problem(element, format!("invalid element name {:?}", element.name));
+        }
+        let mut attributes: Vec<(String, &str)> = element.attributes.iter()
+            .map(|(key, value)| (key.to_ascii_lowercase(), value.as_str()))
+            .collect();
+        attributes.sort();
+        if std::ptr::eq(element, self.root) && name == "html" && !attributes.iter().any(|(key, _)| key == "xmlns") {
+            attributes.insert(0, ("xmlns".to_string(), "http://www.w3.org/1999/xhtml"));
+        }
+        self.out.push('<');
+        self.out.push_str(&name);
+        for (index, (key, value)) in attributes.iter().enumerate() {
+            if index > 0 && attributes[index - 1].0 == *key {
+                self.problem(element, format!("attribute {} given more than once, differing in case", key));
+                continue;
+            }
+            if NameToken::new(key).is_err() {
+                self.problem(element, format!("invalid attribute name {:?}", key));
+            }
+            if value.contains(is_forbidden) {
+                self.problem(element, format!("attribute {} holds a character XML forbids", key));
+            }
+            self.out.push_str(&format!(" {}=\"{}\"", key, escape_attribute(value)));
+        }
+        if is_void_element(&name) {
+            if !element.children.is_empty() {
+                self.problem(element, format!("void element {} has content", name));
+            }
+            self.out.push_str("/>");
+            return;
+        }
+        self.out.push('>');
+        for child in &element.children {
+            match child {
+                Node::CharData(data) => {
+                    if data.contains(is_forbidden) {
+                        self.problem(element, "text holds a character XML forbids".to_string());
+                    }
+                    self.out.push_str(&escape_text(data));
+                }
+                Node::Element(child) => self.element(child),
+                Node::Comment(text) => {
+                    if text.contains("--") || text.ends_with('-') {
+                        self.problem(element, format!("comment {:?} contains \"--\" or ends with \"-\"", text));
+                    }
+                    self.out.push_str(&format!("<!--{}-->", text));
+                }
+                Node::ProcessingInstruction { target, data } => {
+                    if NameToken::new(target).is_err() || target.eq_ignore_ascii_case("xml") || data.contains("?>") {
+                        self.problem(element, format!("malformed processing instruction {:?} {:?}", target, data));
+                    }
+                    self.out.push_str(&if data.is_empty() { format!("<?{}?>", target) } else { format!("<?{} {}?>", target, data) });
+                }
+                Node::Foreign(foreign) => self.out.push_str(foreign.source()),
+            }
+        }
+        self.out.push_str(&format!("</{}>", name));
+    }
+}
+
+#[cfg(feature = "write")]
+impl Document {
+    /// Writes a leniently parsed HTML document as well-formed XHTML: names lowercased, void
+    /// elements self-closing, other empty elements with an end tag as browsers expect, attribute
+    /// values quoted and escaped, and the XHTML namespace on an `<html>` root. Fails with
+    /// everything that has no XML form, such as a void element with content, attributes
+    /// differing only in case or `--` in a comment.
+    pub fn to_strict_xml(&self) -> Result<String, Vec<Unconvertible>> {
+        let mut strict = StrictXml {
+            root: &self.root,
+            out: format!("<?xml version=\"1.{}\"?>", self.version),
+            problems: vec![],
+        };
+        strict.element(&self.root);
+        if strict.problems.is_empty() {
+            Ok(strict.out)
+        } else {
+            Err(strict.problems)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::html::*;
+
+    #[test]
+    fn plain_text() {
+        let parser = Parser {
+            allow_no_close: vec!["br".to_string()],
+            ..Default::default()
+        };
+        let res = parser
+            .complete_element("<div><p>Hello\n   <b>big</b>!</p><ul><li>one</li><li>two</li></ul>line<br>break<script>x()</script></div>")
+            .unwrap();
+        assert_eq!(res.to_plain_text(), "Hello big!\none\ntwo\nline\nbreak");
+    }
+
+    #[test]
+    fn url_resolution() {
+        let base = "https://example.com/a/b/page.html?x=1#top";
+        assert_eq!(resolve_url(base, "c.png"), "https://example.com/a/b/c.png");
+        assert_eq!(resolve_url(base, "../c.png"), "https://example.com/a/c.png");
+        assert_eq!(resolve_url(base, "/root"), "https://example.com/root");
+        assert_eq!(resolve_url(base, "//cdn.example.com/x"), "https://cdn.example.com/x");
+        assert_eq!(resolve_url(base, "?y=2"), "https://example.com/a/b/page.html?y=2");
+        assert_eq!(resolve_url(base, "#f"), "https://example.com/a/b/page.html?x=1#f");
+        assert_eq!(resolve_url(base, "mailto:me@example.com"), "mailto:me@example.com");
+        assert_eq!(resolve_url("https://example.com", "x/./y/../z"), "https://example.com/x/z");
+    }
+
+    #[test]
+    fn links_images_forms() {
+        let parser = Parser {
+            allow_no_close: vec!["img".to_string(), "input".to_string()],
+            ..Default::default()
+        };
+        let res = parser.complete_element(r#"<body>
+            <a href="/about" rel="nofollow">About <b>us</b></a><a name="anchor">no href</a>
+            <img src="logo.png" alt="Logo"><img>
+            <form action="search" method="POST"><p><input name="q"><input type="checkbox" name="all" value="1"></p><textarea name="notes">hi</textarea></form>
+        </body>"#).unwrap();
+        let base = Some("https://example.com/docs/index.html");
+        assert_eq!(res.links(base), vec![Link {
+            href: "https://example.com/about".to_string(),
+            text: "About us".to_string(),
+            rel: Some("nofollow".to_string()),
+        }]);
+        assert_eq!(res.images(None), vec![Image { src: "logo.png".to_string(), alt: Some("Logo".to_string()) }]);
+        let forms = res.forms(base);
+        assert_eq!(forms.len(), 1);
+        assert_eq!(forms[0].action.as_deref(), Some("https://example.com/docs/search"));
+        assert_eq!(forms[0].method, "post");
+        let fields: Vec<(&str, &str)> = forms[0].fields.iter().map(|x| (x.name.as_str(), x.kind.as_str())).collect();
+        assert_eq!(fields, vec![("q", "text"), ("all", "checkbox"), ("notes", "textarea")]);
+    }
+
+    #[test]
+    fn meta_and_microdata() {
+        let parser = Parser {
+            allow_no_close: vec!["meta".to_string(), "img".to_string()],
+            ..Default::default()
+        };
+        let res = parser.complete_element(r#"<html><head>
+            <meta property="og:title" content="A Page"><meta name="Description" content="About things">
+        </head><body>
+            <div itemscope="" itemtype="https://schema.org/Product">
+                <span itemprop="name">Widget</span><img itemprop="image" src="w.png">
+                <div itemprop="offers" itemscope="" itemtype="https://schema.org/Offer"><meta itemprop="price" content="9.99"></div>
+            </div>
+        </body></html>"#).unwrap();
+        let meta = res.meta_tags();
+        assert_eq!(meta["og:title"], "A Page");
+        assert_eq!(meta["description"], "About things");
+        let items = res.microdata_items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].item_type.as_deref(), Some("https://schema.org/Product"));
+        assert_eq!(items[0].text("name"), Some("Widget"));
+        assert_eq!(items[0].text("image"), Some("w.png"));
+        match &items[0].properties["offers"][0] {
+            MicrodataValue::Item(offer) => assert_eq!(offer.text("price"), Some("9.99")),
+            other => panic!("expected nested item, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_microdata_items() {
+        let res = Parser::default().complete_element(r#"<body>
+            <article itemscope="" itemtype="https://schema.org/BlogPosting">
+                <h1 itemprop="headline">Post</h1>
+                <div itemscope="" itemtype="https://schema.org/Person"><span itemprop="name">Ann</span></div>
+            </article>
+        </body>"#).unwrap();
+        let items = res.microdata_items();
+        let types: Vec<Option<&str>> = items.iter().map(|x| x.item_type.as_deref()).collect();
+        assert_eq!(types, [Some("https://schema.org/BlogPosting"), Some("https://schema.org/Person")]);
+        assert_eq!(items[0].text("headline"), Some("Post"));
+        assert_eq!(items[0].text("name"), None);
+        assert_eq!(items[1].text("name"), Some("Ann"));
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn strict_xml() {
+        let parser = Parser {
+            allow_no_close: vec!["br".to_string(), "img".to_string(), "meta".to_string()],
+            lenient: true,
+            ..Default::default()
+        };
+        let document = parser.complete_document(
+            r#"<?xml version="1.0"?><HTML><head><META charset='utf-8'></head><body CLASS="x"><p>1 < 2 &amp; 3<br>4</p><div></div><IMG src="a.png"></body></HTML>"#,
+        ).unwrap();
+        assert_eq!(
+            document.to_strict_xml().unwrap(),
+            r#"<?xml version="1.0"?><html xmlns="http://www.w3.org/1999/xhtml"><head><meta charset="utf-8"/></head><body class="x"><p>1 &lt; 2 &amp; 3<br/>4</p><div></div><img src="a.png"/></body></html>"#,
+        );
+
+        let parser = Parser { normalize_name: keep_name, ..Default::default() };
+        let mut root = parser.complete_element(r#"<div><p Lang="en" lang="fr">x</p><br>y</br></div>"#).unwrap();
+        root.children.push(Node::Comment("a--b".to_string()));
+        let problems: Vec<(String, String)> = Document::new(root).to_strict_xml().unwrap_err()
+            .into_iter().map(|x| (x.path, x.message)).collect();
+        assert_eq!(problems, [
+            ("div > p".to_string(), "attribute lang given more than once, differing in case".to_string()),
+            ("div > br".to_string(), "void element br has content".to_string()),
+            ("div".to_string(), "comment \"a--b\" contains \"--\" or ends with \"-\"".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn auto_mode() {
+        let page = b"<!DOCTYPE html>\n<html><body><p>caf\xe9<br>1 < 2</p></body></html>";
+        let (mode, root) = parse_auto(page, Some("text/html; charset=\"windows-1252\"")).unwrap();
+        assert_eq!((mode, root.to_plain_text().as_str()), (Mode::Html, "caf\u{e9}\n1 < 2"));
+        let (mode, root) = parse_auto(b"\xef\xbb\xbf<?xml version=\"1.0\"?><!-- c --><feed><entry/></feed>", None).unwrap();
+        assert_eq!((mode, root.name()), (Mode::Xml, "feed"));
+        assert_eq!(parse_auto(b"<HTML><img src='a.png'></HTML>", Some("application/octet-stream")).unwrap().0, Mode::Html);
+        let error = parse_auto(b"<!-- c -->\n<html><br></html>", Some("application/atom+xml")).unwrap_err();
+        assert!(matches!(error, Error::MismatchedCloseTag { at: Location { offset: 21, line: 2, column: 11 }, .. }), "{:?}", error);
+        assert!(matches!(parse_auto(b"<a>\xff</a>", Some("text/xml; charset=utf-8")), Err(Error::Encoding(_))));
+        assert_eq!(sniff_mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"x\"><html/>", None), Mode::Xml);
+    }
+}
@@ -0,0 +1,588 @@
+#[cfg(feature = "write")]
+use std::io::{self, Write as _};
+use std::ops::Range;
+
+use crate::reader::{Event, Reader};
+use crate::tokens::{tokens, TokenKind};
+#[cfg(feature = "write")]
+use crate::writer::Writer;
+use crate::{Element, Node, Parser};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Span {
+    range: Range<usize>,
+    /// One per element child, in order.
+    children: Vec<Span>,
+}
+
+impl Span {
+    fn shift(&mut self, map: &impl Fn(usize) -> usize) {
+        self.range = map(self.range.start)..map(self.range.end);
+        for child in &mut self.children {
+            child.shift(map);
+        }
+    }
+}
+
+/// One entry of an editor's symbol tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// The element's `id` attribute, or its `title` when it has no `id`.
+    pub label: Option<String>,
+    pub span: Range<usize>,
+    pub children: Vec<Symbol>,
+}
+
+fn symbol(element: &Element, span: &Span) -> Symbol {
+    Symbol {
+        name: element.name.clone(),
+        label: element.attribute("id").or_else(|| element.attribute("title")).map(str::to_string),
+        span: span.range.clone(),
+        children: element.children.iter()
+            .filter_map(Node::as_element)
+            .zip(&span.children)
+            .map(|(child, span)| symbol(child, span))
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldKind {
+    Element,
+    Comment,
+    CData,
+}
+
+fn element_folds(span: &Span, out: &mut Vec<(FoldKind, Range<usize>)>) {
+    out.push((FoldKind::Element, span.range.clone()));
+    for child in &span.children {
+        element_folds(child, out);
+    }
+}
+
+/// What the text at a cursor position is, for auto-completion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorKind {
+    /// In or right after an element name, including just after `<` or `</`.
+    TagName,
+    /// Inside a start tag where an attribute name goes.
+    AttributeName,
+    /// After the `=` or inside the quotes of `attribute`'s value.
+    AttributeValue { attribute: String },
+    Text,
+    /// In a comment, processing instruction, doctype or tag punctuation.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorContext {
+    pub kind: CursorKind,
+    /// Path of the innermost element containing the cursor.
+    pub path: Vec<usize>,
+}
+
+/// Classifies the cursor at `offset` from the tokens of `source`. A cursor between two tokens
+/// belongs to the one before it when typing there would extend it, as with a name.
+fn cursor_kind(source: &str, offset: usize) -> CursorKind {
+    let mut in_tag = false;
+    let mut attribute = "";
+    for (kind, range) in tokens(source) {
+        let extends = matches!(kind, TokenKind::TagOpen | TokenKind::EndTagOpen | TokenKind::ElementName
+            | TokenKind::AttributeName | TokenKind::Equals | TokenKind::Whitespace | TokenKind::Text);
+        if range.start >= offset {
+            break;
+        }
+        if offset < range.end || (offset == range.end && extends) {
+            let value = || CursorKind::AttributeValue { attribute: attribute.to_string() };
+            return match kind {
+                TokenKind::TagOpen | TokenKind::EndTagOpen | TokenKind::ElementName => CursorKind::TagName,
+                TokenKind::AttributeName => CursorKind::AttributeName,
+                TokenKind::Equals | TokenKind::AttributeValue => value(),
+                TokenKind::Error if in_tag && source[range].starts_with(['"', '\'']) => value(),
+                TokenKind::Whitespace if in_tag => CursorKind::AttributeName,
+                TokenKind::Whitespace | TokenKind::Text | TokenKind::Reference | TokenKind::CData => CursorKind::Text,
+                _ => CursorKind::Other,
+            };
+        }
+        match kind {
+            TokenKind::TagOpen | TokenKind::EndTagOpen => in_tag = true,
+            TokenKind::AttributeName => attribute = &source[range],
+            TokenKind::ElementName | TokenKind::Equals | TokenKind::AttributeValue | TokenKind::Whitespace => {}
+            _ => in_tag = false,
+        }
+    }
+    if in_tag { CursorKind::AttributeName } else { CursorKind::Text }
+}
+
+/// The range between the end of the start tag and the start of the end tag of the element
+/// spanning `span`, or `None` for an empty-element tag.
+#[cfg(feature = "write")]
+fn content_range(source: &str, span: &Span) -> Option<Range<usize>> {
+    let text = &source[span.range.clone()];
+    let (kind, close) = tokens(text)
+        .find(|(kind, _)| matches!(kind, TokenKind::TagClose | TokenKind::EmptyTagClose))?;
+    if matches!(kind, TokenKind::EmptyTagClose) {
+        return None;
+    }
+    let end = text.rfind("</")?;
+    Some(span.range.start + close.end..span.range.start + end)
+}
+
+/// Splits children into the element children and the runs of other nodes around them, one
+/// more run than elements.
+#[cfg(feature = "write")]
+fn runs(children: &[Node]) -> (Vec<&Element>, Vec<&[Node]>) {
+    let mut elements = vec![];
+    let mut runs = vec![];
+    let mut start = 0;
+    for (index, node) in children.iter().enumerate() {
+        if let Node::Element(element) = node {
+            runs.push(&children[start..index]);
+            elements.push(element);
+            start = index + 1;
+        }
+    }
+    runs.push(&children[start..]);
+    (elements, runs)
+}
+
+/// For each of `new`, the index of the element of `old` it is paired with by a longest common
+/// subsequence of names.
+#[cfg(feature = "write")]
+fn pair_by_name(old: &[&Element], new: &[&Element]) -> Vec<Option<usize>> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i].name == new[j].name {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = vec![None; new.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i].name == new[j].name {
+            pairs[j] = Some(i);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(feature = "write")]
+struct WriteThrough<'s> {
+    source: &'s str,
+    out: Vec<u8>,
+}
+
+#[cfg(feature = "write")]
+impl WriteThrough<'_> {
+    fn copy(&mut self, range: Range<usize>) {
+        self.out.extend_from_slice(self.source[range].as_bytes());
+    }
+
+    fn element(&mut self, original: &Element, span: &Span, edited: &Element) -> io::Result<()> {
+        if original == edited {
+            self.copy(span.range.clone());
+            return Ok(());
+        }
+        let content = match content_range(self.source, span) {
+            Some(content) if !edited.children.is_empty() => content,
+            _ => return Writer::new(&mut self.out).write_element(edited),
+        };
+        if original.name == edited.name && original.attributes == edited.attributes {
+            self.copy(span.range.start..content.start);
+        } else {
+            let mut writer = Writer::new(&mut self.out);
+            writer.start(&edited.name, edited.attributes_sorted())?;
+            writer.flush()?;
+        }
+        self.children(original, span, content.clone(), edited)?;
+        if original.name == edited.name {
+            self.copy(content.end..span.range.end);
+        } else {
+            write!(self.out, "</{}>", edited.name)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the source between children wherever the nodes there are unchanged. Whitespace the
+    /// parser dropped is carried over to inserted and surviving elements from a neighbouring gap.
+    fn children(&mut self, original: &Element, span: &Span, content: Range<usize>, edited: &Element) -> io::Result<()> {
+        let (old_elements, old_runs) = runs(&original.children);
+        let (new_elements, new_runs) = runs(&edited.children);
+        let n = old_elements.len();
+        let gaps: Vec<Range<usize>> = (0..=n).map(|i| {
+            let start = if i == 0 { content.start } else { span.children[i - 1].range.end };
+            let end = if i == n { content.end } else { span.children[i].range.start };
+            start..end
+        }).collect();
+        let pairs = pair_by_name(&old_elements, &new_elements);
+        let m = new_elements.len();
+        for (j, run) in new_runs.iter().enumerate() {
+            let left = if j == 0 { Some(0) } else { pairs[j - 1].map(|i| i + 1) };
+            let right = if j == m { Some(n) } else { pairs[j] };
+            let blank = |i: &usize| old_runs[*i].is_empty();
+            match left {
+                Some(gap) if left == right && old_runs[gap] == *run => self.copy(gaps[gap].clone()),
+                _ if run.is_empty() => {
+                    let gap = if j == m {
+                        Some(n).filter(blank)
+                    } else {
+                        right.into_iter().chain(left).filter(|i| *i < n).chain(0..n).find(blank)
+                    };
+                    if let Some(gap) = gap {
+                        self.copy(gaps[gap].clone());
+                    }
+                }
+                _ => {
+                    let mut writer = Writer::new(&mut self.out);
+                    for node in run.iter() {
+                        match node {
+                            Node::CharData(data) => writer.text(data)?,
+                            Node::Comment(text) => writer.comment(text)?,
+                            Node::ProcessingInstruction { target, data } => writer.processing_instruction(target, data)?,
+                            Node::Foreign(foreign) => writer.raw(foreign.source())?,
+                            Node::Element(_) => unreachable!("runs hold no elements"),
+                        }
+                    }
+                }
+            }
+            if let Some(element) = new_elements.get(j) {
+                match pairs[j] {
+                    Some(i) => self.element(old_elements[i], &span.children[i], element)?,
+                    None => Writer::new(&mut self.out).write_element(element)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A parsed element together with its source text and the byte range every element came
+/// from, so that an edit only re-parses the smallest element enclosing it, as a language
+/// server needs on every keystroke.
+///
+/// Paths address elements by their position among element children: `[1, 0]` is the first
+/// element child of the root's second element child.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedTree {
+    source: String,
+    root: Element,
+    spans: Span,
+}
+
+/// Parses `input` as one element with spans, like `complete_element`. Offsets in the result are
+/// relative to `input`.
+fn parse_spanned(parser: &Parser, input: &str) -> Option<(Element, Span)> {
+    let mut reader = Reader::new(parser, input);
+    let mut stack: Vec<(Element, usize, Vec<Span>)> = vec![];
+    let mut done = None;
+    loop {
+        let start = reader.offset();
+        let event = match reader.next() {
+            Some(event) => event.ok()?,
+            None => break,
+        };
+        match (event, stack.last_mut()) {
+            (Event::Text(data), None) if done.is_some() && data.trim().is_empty() => {}
+            (_, None) if done.is_some() => return None,
+            (Event::Start { name, attributes }, _) => {
+                let element = Element { name, attributes: attributes.into_iter().collect(), children: vec![] };
+                stack.push((element, start, vec![]));
+            }
+            (Event::End { .. }, Some(_)) => {
+                let (element, start, children) = stack.pop()?;
+                let span = Span { range: start..reader.offset(), children };
+                match stack.last_mut() {
+                    Some((parent, _, spans)) => {
+                        parent.children.push(Node::Element(element));
+                        spans.push(span);
+                    }
+                    None => done = Some((element, span)),
+                }
+            }
+            (Event::Text(data), Some((parent, _, _))) | (Event::CData(data), Some((parent, _, _))) => {
+                parent.children.push(Node::CharData(data));
+            }
+            (Event::Comment(data), Some((parent, _, _))) => parent.children.push(Node::Comment(data)),
+            (Event::ProcessingInstruction { target, data }, Some((parent, _, _))) => {
+                parent.children.push(Node::ProcessingInstruction { target, data });
+            }
+            (Event::Foreign(foreign), Some((parent, _, _))) => parent.children.push(Node::Foreign(foreign)),
+            _ => return None,
+        }
+    }
+    let (mut element, span) = done?;
+    parser.whitespace.apply(&mut element);
+    Some((element, span))
+}
+
+impl SpannedTree {
+    pub fn parse(parser: &Parser, source: String) -> Option<SpannedTree> {
+        let (root, spans) = parse_spanned(parser, &source)?;
+        Some(SpannedTree { source, root, spans })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn root(&self) -> &Element {
+        &self.root
+    }
+    pub fn into_root(self) -> Element {
+        self.root
+    }
+
+    /// Byte range of the element at `path`, from its `<` to the end of its closing tag.
+    pub fn span(&self, path: &[usize]) -> Option<Range<usize>> {
+        let mut span = &self.spans;
+        for &index in path {
+            span = span.children.get(index)?;
+        }
+        Some(span.range.clone())
+    }
+
+    /// Path of the innermost element whose span contains `offset`.
+    pub fn path_at(&self, offset: usize) -> Option<Vec<usize>> {
+        if !self.spans.range.contains(&offset) {
+            return None;
+        }
+        let mut path = vec![];
+        let mut span = &self.spans;
+        while let Some(index) = span.children.iter().position(|child| child.range.contains(&offset)) {
+            path.push(index);
+            span = &span.children[index];
+        }
+        Some(path)
+    }
+
+    /// What is being typed at `offset` and where. The kind comes from the current source; the
+    /// path comes from the tree, which stays at the last edit that parsed.
+    pub fn context_at(&self, offset: usize) -> Option<CursorContext> {
+        if offset > self.source.len() {
+            return None;
+        }
+        Some(CursorContext {
+            kind: cursor_kind(&self.source, offset),
+            path: self.path_at(offset).unwrap_or_default(),
+        })
+    }
+
+    /// Byte ranges of the elements, comments and CDATA sections that span more than one line,
+    /// ordered by start, for code folding.
+    pub fn folding_ranges(&self) -> Vec<(FoldKind, Range<usize>)> {
+        let mut folds = vec![];
+        element_folds(&self.spans, &mut folds);
+        folds.extend(tokens(&self.source).filter_map(|(kind, range)| match kind {
+            TokenKind::Comment => Some((FoldKind::Comment, range)),
+            TokenKind::CData => Some((FoldKind::CData, range)),
+            _ => None,
+        }));
+        folds.retain(|(_, range)| self.source[range.clone()].contains('\n'));
+        folds.sort_by_key(|(_, range)| range.start);
+        folds
+    }
+
+    /// Every element as a nested `Symbol`, rooted at the root element.
+    pub fn outline(&self) -> Symbol {
+        symbol(&self.root, &self.spans)
+    }
+
+    /// Serializes `edited`, typically a modified clone of the root, by copying the original
+    /// source for every subtree, tag and gap between children that is unchanged and writing
+    /// only what changed, so a version-controlled file gets a minimal diff. Elements are paired
+    /// with the originals by name and position among their siblings.
+    #[cfg(feature = "write")]
+    pub fn write_through(&self, edited: &Element) -> io::Result<String> {
+        let mut through = WriteThrough { source: &self.source, out: vec![] };
+        through.copy(0..self.spans.range.start);
+        through.element(&self.root, &self.spans, edited)?;
+        through.copy(self.spans.range.end..self.source.len());
+        Ok(String::from_utf8(through.out).expect("source and writer output are UTF-8"))
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and re-parses the innermost element
+    /// that strictly encloses the edit, falling back to its ancestors when that element alone
+    /// no longer parses. Returns the path of the re-parsed element, or `None`, leaving the
+    /// tree untouched, when the range is invalid or the edited source does not parse.
+    pub fn edit(&mut self, parser: &Parser, range: Range<usize>, replacement: &str) -> Option<Vec<usize>> {
+        if range.start > range.end || self.source.get(range.clone()).is_none() {
+            return None;
+        }
+        let mut source = String::with_capacity(self.source.len() - range.len() + replacement.len());
+        source.push_str(&self.source[..range.start]);
+        source.push_str(replacement);
+        source.push_str(&self.source[range.end..]);
+        let new_end = range.start + replacement.len();
+        let map = |offset: usize| if offset >= range.end { offset - range.end + new_end } else { offset };
+
+        let mut path = vec![];
+        let mut span = &self.spans;
+        while let Some(index) = span.children.iter()
+            .position(|child| child.range.start < range.start && range.end < child.range.end) {
+            path.push(index);
+            span = &span.children[index];
+        }
+        loop {
+            if path.is_empty() {
+                let (root, spans) = parse_spanned(parser, &source)?;
+                *self = SpannedTree { source, root, spans };
+                return Some(path);
+            }
+            let old = self.span(&path)?;
+            let new = old.start..map(old.end);
+            if let Some((element, mut spans)) = parse_spanned(parser, &source[new.clone()]) {
+                spans.shift(&|offset| offset + new.start);
+                self.spans.shift(&map);
+                let (mut target, mut target_span) = (&mut self.root, &mut self.spans);
+                for &index in &path {
+                    target = target.children.iter_mut().filter_map(|node| match node {
+                        Node::Element(element) => Some(element),
+                        _ => None,
+                    }).nth(index).expect("spans mirror the element children");
+                    target_span = &mut target_span.children[index];
+                }
+                *target = element;
+                *target_span = spans;
+                self.source = source;
+                return Some(path);
+            }
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::incremental::*;
+
+    #[test]
+    fn spans() {
+        let parser = Parser::default();
+        let tree = SpannedTree::parse(&parser, "<a>x<b>1</b><c><d/></c></a>\n".to_string()).unwrap();
+        assert_eq!(tree.span(&[]), Some(0..27));
+        assert_eq!(tree.span(&[1, 0]), Some(15..19));
+        assert_eq!(tree.span(&[2]), None);
+        assert_eq!(tree.path_at(16), Some(vec![1, 0]));
+        assert_eq!(tree.path_at(3), Some(vec![]));
+        assert_eq!(tree.path_at(27), None);
+        assert!(SpannedTree::parse(&parser, "<a/><b/>".to_string()).is_none());
+    }
+
+    #[test]
+    fn outline() {
+        let source = "<book title='B'>\n  <chapter id='c1'><p/></chapter>\n  <chapter title='Two' id='c2'/>\n</book>";
+        let tree = SpannedTree::parse(&Parser::default(), source.to_string()).unwrap();
+        let symbol = |name: &str, label: Option<&str>, span: Range<usize>, children| Symbol {
+            name: name.to_string(),
+            label: label.map(str::to_string),
+            span,
+            children,
+        };
+        assert_eq!(tree.outline(), symbol("book", Some("B"), 0..91, vec![
+            symbol("chapter", Some("c1"), 19..50, vec![symbol("p", None, 36..40, vec![])]),
+            symbol("chapter", Some("c2"), 53..83, vec![]),
+        ]));
+    }
+
+    #[test]
+    fn cursor_context() {
+        let source = "<a>t <b x='1' y=\"2\"/><!--c--></a >";
+        let tree = SpannedTree::parse(&Parser::default(), source.to_string()).unwrap();
+        let kind = |offset| tree.context_at(offset).unwrap().kind;
+        let value = |name: &str| CursorKind::AttributeValue { attribute: name.to_string() };
+        assert_eq!(kind(0), CursorKind::Text);
+        assert_eq!(kind(2), CursorKind::TagName);
+        assert_eq!(kind(3), CursorKind::Text);
+        assert_eq!(kind(6), CursorKind::TagName);
+        assert_eq!(kind(7), CursorKind::TagName);
+        assert_eq!(kind(8), CursorKind::AttributeName);
+        assert_eq!(kind(10), value("x"));
+        assert_eq!(kind(11), value("x"));
+        assert_eq!(kind(13), CursorKind::AttributeName);
+        assert_eq!(kind(17), value("y"));
+        assert_eq!(kind(20), CursorKind::Other);
+        assert_eq!(kind(23), CursorKind::Other);
+        assert_eq!(kind(31), CursorKind::TagName);
+        assert_eq!(kind(34), CursorKind::Text);
+        assert_eq!(tree.context_at(7).unwrap().path, vec![0]);
+        assert_eq!(tree.context_at(3).unwrap().path, Vec::<usize>::new());
+        assert_eq!(tree.context_at(35), None);
+        assert_eq!(cursor_kind("<a x='unfinished", 10), value("x"));
+        assert_eq!(cursor_kind("<a ", 3), CursorKind::AttributeName);
+    }
+
+    #[test]
+    fn folding_ranges() {
+        let source = "<a>\n<b>x</b>\n<!--\nc--><!--d--><c><![CDATA[\n]]></c>\n</a>";
+        let tree = SpannedTree::parse(&Parser::default(), source.to_string()).unwrap();
+        assert_eq!(tree.folding_ranges(), vec![
+            (FoldKind::Element, 0..55),
+            (FoldKind::Comment, 13..22),
+            (FoldKind::Element, 30..50),
+            (FoldKind::CData, 33..46),
+        ]);
+    }
+
+    #[test]
+    fn incremental_edits() {
+        let parser = Parser::default();
+        let mut tree = SpannedTree::parse(&parser, "<a>x<b>1</b><c><d/></c><e/></a>".to_string()).unwrap();
+        type Case = (Range<usize>, &'static str, Option<Vec<usize>>);
+        let edits: &[Case] = &[
+            (7..8, "1234", Some(vec![0])),
+            (18..22, "<d k='v'>t</d><f/>", Some(vec![1])),
+            (3..4, "", Some(vec![])),
+            (6..10, "12</b><b>34", Some(vec![])),
+            (6..7, "</oops>", None),
+            (0..1, "<<", None),
+            (1..1000, "", None),
+        ];
+        for (range, replacement, expected) in edits {
+            let before = tree.clone();
+            let path = tree.edit(&parser, range.clone(), replacement);
+            assert_eq!(&path, expected, "{:?} {:?}", range, replacement);
+            if path.is_none() {
+                assert_eq!(tree, before);
+            }
+            assert_eq!(tree, SpannedTree::parse(&parser, tree.source().to_string()).unwrap());
+        }
+        assert_eq!(tree.source(), "<a><b>12</b><b>34</b><c><d k='v'>t</d><f/></c><e/></a>");
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn write_through() {
+        let parser = Parser::default();
+        let source = "<config>\n  <!-- servers -->\n  <server host='a'  port=\"1\"/>\n  <server host='b'>\n    <alias>x &amp; y</alias>\n    <alias>w</alias>\n  </server>\n</config>\n";
+        let tree = SpannedTree::parse(&parser, source.to_string()).unwrap();
+        assert_eq!(tree.write_through(tree.root()).unwrap(), source);
+
+        let mut edited = tree.root().clone();
+        let mut servers: Vec<&mut Element> = edited.children.iter_mut().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }).collect();
+        let second = servers.pop().unwrap();
+        servers[0].set_attribute("port", "2");
+        second.children.retain(|node| node.as_element().map_or(true, |alias| alias.text() != "w"));
+        if let Some(Node::Element(alias)) = second.children.first_mut() {
+            alias.children = vec![Node::CharData("<z>".to_string())];
+        }
+        edited.children.push(Node::Element(parser.complete_element("<server host='c'/>").unwrap()));
+        assert_eq!(
+            tree.write_through(&edited).unwrap(),
+            "<config>\n  <!-- servers -->\n  <server host=\"a\" port=\"2\"/>\n  <server host='b'>\n    <alias>&lt;z&gt;</alias>\n  </server>\n  <server host=\"c\"/>\n</config>\n",
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use crate::reader::{Event, ReadError, Reader};
+use crate::{Element, NameMatch, Parser};
+
+const MAGIC: &str = "macky_xml-index 1";
+/// Bytes hashed at each end of the source to tell whether an index still belongs to it.
+const SAMPLE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub id: Option<String>,
+    /// Byte range from the element's `<` to the end of its closing tag.
+    pub span: Range<usize>,
+}
+
+/// Sidecar index of where every element of a large document starts and ends, by name and
+/// `id`, so repeated queries can seek straight to the matching subtrees instead of parsing
+/// the whole file each run. Build it once, save it with `write_to`, and check it with
+/// `matches_source` after `read_from`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    len: u64,
+    fingerprint: u64,
+    entries: Vec<Entry>,
+    ids: HashMap<String, usize>,
+}
+
+/// Length and FNV-1a hash of the first and last `SAMPLE` bytes: cheap enough to check on
+/// every run, and any edit that changes the length or touches either end is caught.
+fn fingerprint<R: Read + Seek>(mut source: R) -> io::Result<(u64, u64)> {
+    let len = source.seek(SeekFrom::End(0))?;
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut sample = |source: &mut R, from: u64| -> io::Result<()> {
+        source.seek(SeekFrom::Start(from))?;
+        let mut buffer = vec![];
+        source.take(SAMPLE).read_to_end(&mut buffer)?;
+        for byte in buffer {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    };
+    sample(&mut source, 0)?;
+    sample(&mut source, len.saturating_sub(SAMPLE))?;
+    Ok((len, hash))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Index {
+    fn from_entries(len: u64, fingerprint: u64, entries: Vec<Entry>) -> Index {
+        let mut ids = HashMap::new();
+        for (position, entry) in entries.iter().enumerate() {
+            if let Some(id) = &entry.id {
+                ids.entry(id.clone()).or_insert(position);
+            }
+        }
+        Index { len, fingerprint, entries, ids }
+    }
+
+    pub fn build(parser: &Parser, input: &str) -> Result<Index, ReadError> {
+        let mut entries = vec![];
+        let mut open = vec![];
+        let mut reader = Reader::new(parser, input);
+        loop {
+            let start = reader.offset();
+            match reader.next().transpose()? {
+                Some(Event::Start { name, attributes }) => {
+                    let id = attributes.into_iter().find(|(key, _)| key == "id").map(|(_, value)| value);
+                    open.push(entries.len());
+                    entries.push(Entry { name, id, span: start..start });
+                }
+                Some(Event::End { .. }) => {
+                    if let Some(position) = open.pop() {
+                        entries[position].span.end = reader.offset();
+                    }
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        let (len, fingerprint) = fingerprint(io::Cursor::new(input)).expect("reading from memory cannot fail");
+        Ok(Index::from_entries(len, fingerprint, entries))
+    }
+
+    /// Whether `source` still looks like the document this index was built from.
+    pub fn matches_source<R: Read + Seek>(&self, source: R) -> io::Result<bool> {
+        Ok(fingerprint(source)? == (self.len, self.fingerprint))
+    }
+
+    /// Every element, in document order.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    pub fn by_name<'n>(&self, name: impl Into<NameMatch<'n>>) -> Vec<&Entry> {
+        let name = name.into();
+        self.entries.iter().filter(|entry| name.matches(&entry.name)).collect()
+    }
+    /// The first element with this `id`.
+    pub fn by_id(&self, id: &str) -> Option<&Entry> {
+        self.ids.get(id).map(|&position| &self.entries[position])
+    }
+
+    /// Reads and parses only the bytes of `entry`. `None` means they no longer hold an element,
+    /// which happens when the index is stale.
+    pub fn load<R: Read + Seek>(&self, parser: &Parser, mut source: R, entry: &Entry) -> io::Result<Option<Element>> {
+        source.seek(SeekFrom::Start(entry.span.start as u64))?;
+        let mut buffer = vec![];
+        source.take(entry.span.len() as u64).read_to_end(&mut buffer)?;
+        Ok(String::from_utf8(buffer).ok().and_then(|text| parser.complete_element(&text).ok()))
+    }
+
+    /// Saves the index as text: a header line, then one `start end name` line per element with
+    /// ` =id` appended when it has one.
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{} {} {}", MAGIC, self.len, self.fingerprint)?;
+        for entry in &self.entries {
+            write!(w, "{} {} {}", entry.span.start, entry.span.end, entry.name)?;
+            if let Some(id) = &entry.id {
+                write!(w, " ={}", escape(id))?;
+            }
+            writeln!(w)?;
+        }
+        w.flush()
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Index> {
+        let mut lines = r.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let (len, fingerprint) = header.strip_prefix(MAGIC)
+            .and_then(|rest| {
+                let mut fields = rest.split_whitespace().map(str::parse::<u64>);
+                Some((fields.next()?.ok()?, fields.next()?.ok()?))
+            })
+            .ok_or_else(|| invalid("not a macky_xml index"))?;
+        let mut entries = vec![];
+        for line in lines {
+            let line = line?;
+            let mut fields = line.splitn(4, ' ');
+            let mut offset = || fields.next().and_then(|field| field.parse::<usize>().ok());
+            let (start, end) = match (offset(), offset()) {
+                (Some(start), Some(end)) if start <= end => (start, end),
+                _ => return Err(invalid("malformed index entry")),
+            };
+            let name = fields.next().filter(|name| !name.is_empty()).ok_or_else(|| invalid("malformed index entry"))?;
+            let id = match fields.next() {
+                Some(id) => Some(unescape(id.strip_prefix('=').ok_or_else(|| invalid("malformed index entry"))?)),
+                None => None,
+            };
+            entries.push(Entry { name: name.to_string(), id, span: start..end });
+        }
+        Ok(Index::from_entries(len, fingerprint, entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::*;
+    use crate::index::*;
+
+    #[test]
+    fn sidecar_index() {
+        let parser = Parser::default();
+        let input = "<log>\n<e id=\"a b\\\n\">1</e><e><x/></e>\n<f id=\"z\"/></log>";
+        let index = Index::build(&parser, input).unwrap();
+        assert_eq!(index.entries().len(), 5);
+        assert_eq!(index.by_name("e").len(), 2);
+        assert_eq!(index.by_id("z").unwrap().span, 37..48);
+
+        let mut saved = vec![];
+        index.write_to(&mut saved).unwrap();
+        let loaded = Index::read_from(Cursor::new(&saved)).unwrap();
+        assert_eq!(loaded, index);
+        assert!(loaded.matches_source(Cursor::new(input)).unwrap());
+        // Attribute value normalization has already turned the newline into a space.
+        let entry = loaded.by_id("a b\\ ").unwrap();
+        let element = loaded.load(&parser, Cursor::new(input), entry).unwrap().unwrap();
+        assert_eq!(element.text(), "1");
+        let second = loaded.by_name("e")[1];
+        assert_eq!(loaded.load(&parser, Cursor::new(input), second).unwrap().unwrap().children().len(), 1);
+
+        assert!(!loaded.matches_source(Cursor::new(input.replace("1", "2"))).unwrap());
+        assert!(Index::read_from(Cursor::new("0 1 a\n")).is_err());
+        assert!(Index::build(&parser, "<a></b>").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::ops::Range;
+
+use crate::tokens::{tokens, TokenKind, Tokens};
+use crate::{Element, Parser};
+
+/// An element found inside other text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Island {
+    /// Byte range of the element in the scanned text.
+    pub span: Range<usize>,
+    pub root: Element,
+}
+
+/// Iterator over the elements embedded in arbitrary text; see `islands`.
+pub struct Islands<'p, 'a> {
+    parser: &'p Parser,
+    input: &'a str,
+    tokens: Tokens<'a>,
+    /// Where the input of `tokens` starts in `input`.
+    offset: usize,
+}
+
+/// Finds every complete element in `input`, such as a log file or an email with XML or HTML
+/// pasted in, skipping the text around them. Each start tag the tokenizer finds outside an
+/// earlier island is tried as the start of one, so an element that does not parse is skipped
+/// and the elements inside it may still be found.
+pub fn islands<'p, 'a>(parser: &'p Parser, input: &'a str) -> Islands<'p, 'a> {
+    Islands { parser, input, tokens: tokens(input), offset: 0 }
+}
+
+impl<'p, 'a> Iterator for Islands<'p, 'a> {
+    type Item = Island;
+
+    fn next(&mut self) -> Option<Island> {
+        for (kind, range) in self.tokens.by_ref() {
+            if kind != TokenKind::TagOpen {
+                continue;
+            }
+            let start = self.offset + range.start;
+            if let Ok((after, mut root)) = self.parser.element(&self.input[start..]) {
+                let end = self.input.len() - after.len();
+                self.parser.whitespace.apply(&mut root);
+                self.offset = end;
+                self.tokens = tokens(&self.input[end..]);
+                return Some(Island { span: start..end, root });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::islands::*;
+
+    #[test]
+    fn islands_in_logs() {
+        let log = "12:00 recv <msg id=\"1\"><body>hi</body></msg>\n\
+                   12:01 if a <b then fail\n\
+                   12:02 From: Jo <jo@example.com> <broken><ok n=\"2\"/>\n\
+                   12:03 <msg id=\"3\">x &amp; y</msg> done";
+        let found: Vec<(&str, String)> = islands(&Parser::default(), log)
+            .map(|island| (&log[island.span.clone()], island.root.name().to_string()))
+            .collect();
+        assert_eq!(found, [
+            ("<msg id=\"1\"><body>hi</body></msg>", "msg".to_string()),
+            ("<ok n=\"2\"/>", "ok".to_string()),
+            ("<msg id=\"3\">x &amp; y</msg>", "msg".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+
+use crate::{Element, Node};
+use crate::reader::ReadError;
+
+fn write_string<W: io::Write>(w: &mut W, value: &str) -> io::Result<()> {
+    w.write_all(b"\"")?;
+    for ch in value.chars() {
+        match ch {
+            '"' => w.write_all(b"\\\"")?,
+            '\\' => w.write_all(b"\\\\")?,
+            '\n' => w.write_all(b"\\n")?,
+            '\r' => w.write_all(b"\\r")?,
+            '\t' => w.write_all(b"\\t")?,
+            ch if (ch as u32) < 0x20 => write!(w, "\\u{:04x}", ch as u32)?,
+            ch => write!(w, "{}", ch)?,
+        }
+    }
+    w.write_all(b"\"")
+}
+
+/// Direct text of an element, ignoring whitespace-only runs between child elements.
+pub(crate) fn own_text(element: &Element) -> String {
+    element.children.iter()
+        .filter_map(Node::as_cdata)
+        .filter(|data| !data.trim().is_empty())
+        .map(String::as_str)
+        .collect()
+}
+
+fn write_value<W: io::Write>(w: &mut W, element: &Element, force_object: bool) -> io::Result<()> {
+    if element.is_nil() && !force_object {
+        return w.write_all(b"null");
+    }
+    let text = own_text(element);
+    let mut groups: Vec<(&str, Vec<&Element>)> = vec![];
+    for child in element.children.iter().filter_map(Node::as_element) {
+        match groups.iter_mut().find(|(name, _)| *name == child.name) {
+            Some((_, group)) => group.push(child),
+            None => groups.push((&child.name, vec![child])),
+        }
+    }
+    if !force_object && element.attributes.is_empty() && groups.is_empty() {
+        return write_string(w, &text);
+    }
+    w.write_all(b"{")?;
+    let mut first = true;
+    let mut key = |w: &mut W, key: &str| -> io::Result<()> {
+        if !std::mem::take(&mut first) {
+            w.write_all(b",")?;
+        }
+        write_string(w, key)?;
+        w.write_all(b":")
+    };
+    for (name, value) in element.attributes_sorted() {
+        key(w, &format!("@{}", name))?;
+        write_string(w, value)?;
+    }
+    for (name, group) in &groups {
+        key(w, name)?;
+        if let [only] = group.as_slice() {
+            write_value(w, only, false)?;
+        } else {
+            w.write_all(b"[")?;
+            for (index, child) in group.iter().enumerate() {
+                if index > 0 {
+                    w.write_all(b",")?;
+                }
+                write_value(w, child, false)?;
+            }
+            w.write_all(b"]")?;
+        }
+    }
+    if !text.is_empty() {
+        key(w, "#text")?;
+        write_string(w, &text)?;
+    }
+    w.write_all(b"}")
+}
+
+/// Writes `element` as JSON: attributes under `@name` keys, child elements under their names
+/// (an array when a name repeats, in order of first appearance) and the element's own text
+/// under `#text`. An element with nothing but text is written as a plain string, and an
+/// `xsi:nil` element as `null`.
+pub fn write_json<W: io::Write>(mut w: W, element: &Element) -> io::Result<()> {
+    write_value(&mut w, element, false)
+}
+
+pub fn to_json(element: &Element) -> String {
+    let mut out = vec![];
+    write_value(&mut out, element, false).expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("JSON output is built from str")
+}
+
+/// Writes each record as one JSON object per line, e.g. from `Reader::split_on`, stopping at
+/// the first read error (returned as `InvalidData` wrapping the `ReadError`). Returns the
+/// number of records written.
+pub fn write_json_lines<W, I>(mut w: W, records: I) -> io::Result<usize>
+    where W: io::Write, I: IntoIterator<Item=Result<Element, ReadError>> {
+    let mut count = 0;
+    for record in records {
+        let record = record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_value(&mut w, &record, true)?;
+        w.write_all(b"\n")?;
+        count += 1;
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::json::*;
+    use crate::reader::Reader;
+
+    #[test]
+    fn json_mapping() {
+        let res = Parser::default()
+            .complete_element(r#"<order id="7"><item>a "b"</item><item sku="x">c</item><note>line
+two</note><gift xsi:nil="true"/>tail</order>"#)
+            .unwrap();
+        assert_eq!(
+            to_json(&res),
+            r##"{"@id":"7","item":["a \"b\"",{"@sku":"x","#text":"c"}],"note":"line\ntwo","gift":null,"#text":"tail"}"##
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let parser = Parser::default();
+        let input = "<export>\n  <row id=\"1\"><v>a</v></row>\n  <row>b</row>\n  <row></oops>\n</export>";
+        let mut out = vec![];
+        let err = write_json_lines(&mut out, Reader::new(&parser, input).split_on("row")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"@id\":\"1\",\"v\":\"a\"}\n{\"#text\":\"b\"}\n");
+    }
+}
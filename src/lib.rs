@@ -1,27 +1,341 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod arena;
+pub mod borrowed;
+pub mod builder;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod config;
+pub mod csv;
+pub mod dtd;
+pub mod encoding;
+pub mod expand;
+pub mod foreign;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod incremental;
+pub mod index;
+pub mod islands;
+pub mod json;
+pub mod links;
+pub mod ns;
+pub mod outline;
+pub mod parallel;
+pub mod pattern;
+pub mod prelude;
+pub mod reader;
+#[cfg(feature = "regex")]
+pub mod regex_query;
+pub mod report;
+pub mod rewrite;
+pub mod rules;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+pub mod text;
+#[cfg(feature = "toml")]
+pub mod toml;
+pub mod tokens;
+pub mod tree;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[cfg(feature = "write")]
+pub mod transform;
+pub mod typed;
+pub mod view;
+#[cfg(feature = "write")]
+pub mod writer;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_until, take_while, take_while1};
+use nom::character::complete::char;
 use nom::multi::{many0, many_till};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Document {
-    pub version: i32,
-    pub encoding: Option<String>,
-    pub root: Element,
+    pub(crate) version: i32,
+    pub(crate) encoding: Option<String>,
+    pub(crate) source_encoding: Option<(encoding::Encoding, bool)>,
+    pub(crate) root: Element,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Node {
     CharData(String),
     Element(Element),
+    Comment(String),
+    ProcessingInstruction { target: String, data: String },
+    /// An element parsed by one of `Parser::foreign`.
+    Foreign(foreign::Foreign),
+}
+
+/// Shape of an element subtree, counting elements only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubtreeStats {
+    /// Levels of elements below this one; 0 for an element without element children.
+    pub depth_below: usize,
+    /// Elements in the subtree, including the element itself.
+    pub size: usize,
+    /// The most element children any single element in the subtree has.
+    pub max_breadth: usize,
 }
 
-#[derive(Debug)]
+/// Equality is structural: same name, attributes in any order, and equal children.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Element {
-    pub name: String,
-    pub attributes: HashMap<String, String>,
-    pub children: Vec<Node>,
+    pub(crate) name: String,
+    pub(crate) attributes: HashMap<String, String>,
+    pub(crate) children: Vec<Node>,
+}
+
+impl Document {
+    /// A version 1.0 document without an encoding declaration.
+    pub fn new(root: Element) -> Document {
+        Document {
+            version: 0,
+            encoding: None,
+            source_encoding: None,
+            root,
+        }
+    }
+    /// Sets the declared encoding, which `to_bytes` then writes in instead of the one read.
+    pub fn with_encoding(mut self, encoding: &str) -> Document {
+        self.encoding = Some(encoding.to_string());
+        self.source_encoding = None;
+        self
+    }
+    /// Minor version number, i.e. `0` for `version="1.0"`.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+    /// For a document read with `complete_document_bytes`, the encoding it was in and whether
+    /// it had a byte order mark.
+    pub fn source_encoding(&self) -> Option<(encoding::Encoding, bool)> {
+        self.source_encoding
+    }
+
+    /// Writes the declaration and the root, then encodes them as the document was read or, once
+    /// `with_encoding` was called, as declared, so unedited documents keep their encoding.
+    /// Fails with `InvalidInput` for an unsupported encoding or a character it cannot hold.
+    #[cfg(feature = "write")]
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        let (encoding, bom) = match (self.source_encoding, &self.encoding) {
+            (Some(source), _) => source,
+            (None, None) => (encoding::Encoding::Utf8, false),
+            (None, Some(label)) => {
+                let encoding = encoding::Encoding::for_label(label)
+                    .ok_or_else(|| invalid(format!("unsupported encoding {:?}", label)))?;
+                (encoding, matches!(encoding, encoding::Encoding::Utf16Le | encoding::Encoding::Utf16Be))
+            }
+        };
+        let text = self.to_xml_string()?;
+        let mut bytes = if bom { encoding.byte_order_mark().to_vec() } else { vec![] };
+        bytes.extend(encoding.encode(&text).map_err(|e| invalid(e.to_string()))?);
+        Ok(bytes)
+    }
+    /// Writes the declaration and the root as UTF-8, whatever encoding is declared; see
+    /// `to_bytes` for output in the declared encoding.
+    #[cfg(feature = "write")]
+    pub fn write_xml<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        let mut writer = writer::Writer::new(w);
+        writer.declaration(self.version, self.encoding())?;
+        writer.write_element(&self.root)?;
+        writer.flush()
+    }
+    #[cfg(feature = "write")]
+    pub fn to_xml_string(&self) -> std::io::Result<String> {
+        let mut out = vec![];
+        self.write_xml(&mut out)?;
+        Ok(String::from_utf8(out).expect("the writer writes UTF-8"))
+    }
+    pub fn root(&self) -> &Element {
+        &self.root
+    }
+    pub fn root_mut(&mut self) -> &mut Element {
+        &mut self.root
+    }
+    pub fn into_root(self) -> Element {
+        self.root
+    }
+    /// The root as an arena `Tree`, to edit by `NodeId`; `set_tree` puts it back. Each call
+    /// builds a new arena, so ids only hold for the tree they came from.
+    pub fn to_tree(&self) -> arena::Tree {
+        arena::Tree::from_element(&self.root)
+    }
+    /// Replaces the root with the content of `tree`, keeping the declaration and encoding.
+    pub fn set_tree<T: text::Text>(&mut self, tree: &arena::Tree<T>) {
+        self.root = tree.to_element();
+    }
+    /// The element an intra-document reference such as `#intro` points to; see
+    /// `links::Fragment` for the forms understood.
+    pub fn resolve_fragment(&self, reference: &str) -> Option<&Element> {
+        links::Fragment::parse(reference)?.resolve(&self.root)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameMatch<'n> {
+    Exact(&'n str),
+    IgnoreAsciiCase(&'n str),
+    /// Locale-independent Unicode caseless match, so `STRASSE` matches `straße` and `ΟΔΟΣ`
+    /// matches `οδος`. Falls back to the ASCII comparison when both names are ASCII.
+    IgnoreCase(&'n str),
+    /// Glob pattern where `*` matches any run of characters and `?` a single one, ignoring ASCII case.
+    Wildcard(&'n str),
+}
+
+impl<'n> From<&'n str> for NameMatch<'n> {
+    fn from(name: &'n str) -> Self {
+        if name.contains(['*', '?']) {
+            NameMatch::Wildcard(name)
+        } else {
+            NameMatch::IgnoreAsciiCase(name)
+        }
+    }
+}
+
+impl<'n> NameMatch<'n> {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameMatch::Exact(expected) => name == *expected,
+            NameMatch::IgnoreAsciiCase(expected) => name.eq_ignore_ascii_case(expected),
+            NameMatch::IgnoreCase(expected) if name.is_ascii() && expected.is_ascii() => name.eq_ignore_ascii_case(expected),
+            NameMatch::IgnoreCase(expected) => case_folded(name).eq(case_folded(expected)),
+            NameMatch::Wildcard(pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_matches(&pattern, &name)
+            }
+        }
+    }
+}
+
+/// An element name known at compile time, declared with `tag_names!` so that application code
+/// names each element once. Matches like a plain name in queries, ignoring ASCII case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TagName(&'static str);
+
+impl TagName {
+    /// Panics, at compile time in a constant, if `name` is not a valid element name.
+    pub const fn new(name: &'static str) -> TagName {
+        let bytes = name.as_bytes();
+        assert!(!bytes.is_empty(), "empty element name");
+        let mut index = 0;
+        while index < bytes.len() {
+            let byte = bytes[index];
+            let valid = !byte.is_ascii() || byte.is_ascii_alphabetic() || byte == b'_' || byte == b':'
+                || (index > 0 && (byte.is_ascii_digit() || byte == b'-' || byte == b'.'));
+            assert!(valid, "invalid element name");
+            index += 1;
+        }
+        TagName(name)
+    }
+    pub const fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl std::fmt::Display for TagName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl AsRef<str> for TagName {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl PartialEq<str> for TagName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl From<TagName> for NameMatch<'static> {
+    fn from(name: TagName) -> NameMatch<'static> {
+        NameMatch::IgnoreAsciiCase(name.0)
+    }
+}
+
+impl From<TagName> for NameToken {
+    fn from(name: TagName) -> NameToken {
+        NameToken(name.0.to_string())
+    }
+}
+
+/// Declares `TagName` constants, checked at compile time:
+/// `tag_names! { pub ITEM = "item", ORDER = "order" }`.
+#[macro_export]
+macro_rules! tag_names {
+    ($($(#[$meta:meta])* $vis:vis $name:ident = $value:literal),* $(,)?) => {
+        $($(#[$meta])* $vis const $name: $crate::TagName = $crate::TagName::new($value);)*
+    };
+}
+
+/// Case folding without locale tailoring: uppercasing first folds `ß` to `ss` and final
+/// sigma to `σ`, which lowercasing alone does not.
+fn case_folded(name: &str) -> impl Iterator<Item=char> + '_ {
+    name.chars().flat_map(char::to_uppercase).flat_map(char::to_lowercase)
+}
+
+/// On a mismatch only the most recent `*` is retried, one character further on: earlier stars
+/// never need to match more, so the work is bounded by pattern length times name length.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// XPath-style position predicate, counted from 1 among the matching siblings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    At(usize),
+    Last,
+    GreaterThan(usize),
+    LessThan(usize),
+}
+
+impl Position {
+    pub fn matches(&self, position: usize, last: usize) -> bool {
+        match *self {
+            Position::At(index) => position == index,
+            Position::Last => position == last,
+            Position::GreaterThan(index) => position > index,
+            Position::LessThan(index) => position < index,
+        }
+    }
 }
 
 pub trait QuerySupport<'a, T> {
@@ -29,7 +343,86 @@ pub trait QuerySupport<'a, T> {
     fn first(&self) -> Option<&'a T>;
     fn nth(&self, index: usize) -> Option<&'a T>;
     fn last(&self) -> Option<&'a T>;
-    fn elem_name(&self, name: &str) -> Vec<&'a Element>;
+    fn elem_name<'n>(&self, name: impl Into<NameMatch<'n>>) -> Vec<&'a Element>;
+    fn elem_where<F: Fn(&Element) -> bool>(&self, predicate: F) -> Vec<&'a Element>;
+    fn child_at<'n>(&self, name: impl Into<NameMatch<'n>>, position: Position) -> Vec<&'a Element>;
+}
+
+/// Aggregates over query results. Values that are missing or fail to parse are skipped.
+pub trait Aggregate {
+    fn count<'n>(&self, name: impl Into<NameMatch<'n>>) -> usize;
+    fn sum_attr(&self, name: &str) -> f64;
+    fn min_text_as<T: std::str::FromStr + PartialOrd>(&self) -> Option<T>;
+    fn max_text_as<T: std::str::FromStr + PartialOrd>(&self) -> Option<T>;
+}
+
+impl Aggregate for Vec<&Element> {
+    fn count<'n>(&self, name: impl Into<NameMatch<'n>>) -> usize {
+        self.elem_name(name).len()
+    }
+
+    fn sum_attr(&self, name: &str) -> f64 {
+        self.iter()
+            .filter_map(|x| x.attributes.get(name))
+            .filter_map(|value| value.trim().parse::<f64>().ok())
+            .sum()
+    }
+
+    fn min_text_as<T: std::str::FromStr + PartialOrd>(&self) -> Option<T> {
+        self.iter()
+            .filter_map(|x| x.text().trim().parse::<T>().ok())
+            .fold(None, |min, value| match min {
+                Some(min) if min <= value => Some(min),
+                _ => Some(value),
+            })
+    }
+
+    fn max_text_as<T: std::str::FromStr + PartialOrd>(&self) -> Option<T> {
+        self.iter()
+            .filter_map(|x| x.text().trim().parse::<T>().ok())
+            .fold(None, |max, value| match max {
+                Some(max) if max >= value => Some(max),
+                _ => Some(value),
+            })
+    }
+}
+
+pub trait Grouping<'a> {
+    /// Groups elements by the value of an attribute; elements without it are left out.
+    fn group_by_attr(&self, name: &str) -> HashMap<String, Vec<&'a Element>>;
+    fn group_by_name(&self) -> HashMap<String, Vec<&'a Element>>;
+}
+
+impl<'a> Grouping<'a> for Vec<&'a Element> {
+    fn group_by_attr(&self, name: &str) -> HashMap<String, Vec<&'a Element>> {
+        let mut map: HashMap<String, Vec<&'a Element>> = HashMap::new();
+        for x in self {
+            if let Some(value) = x.attributes.get(name) {
+                map.entry(value.clone()).or_default().push(x);
+            }
+        }
+        map
+    }
+
+    fn group_by_name(&self) -> HashMap<String, Vec<&'a Element>> {
+        let mut map: HashMap<String, Vec<&'a Element>> = HashMap::new();
+        for x in self {
+            map.entry(x.name.clone()).or_default().push(x);
+        }
+        map
+    }
+}
+
+impl<'a> Grouping<'a> for Vec<&'a Node> {
+    fn group_by_attr(&self, name: &str) -> HashMap<String, Vec<&'a Element>> {
+        let elements: Vec<&'a Element> = self.iter().filter_map(|x| x.as_element()).collect();
+        elements.group_by_attr(name)
+    }
+
+    fn group_by_name(&self) -> HashMap<String, Vec<&'a Element>> {
+        let elements: Vec<&'a Element> = self.iter().filter_map(|x| x.as_element()).collect();
+        elements.group_by_name()
+    }
 }
 
 impl<'a> QuerySupport<'a, Node> for Vec<&'a Node> {
@@ -54,21 +447,22 @@ impl<'a> QuerySupport<'a, Node> for Vec<&'a Node> {
     }
 
     fn last(&self) -> Option<&'a Node> {
-        self.nth(self.len() - 1)
+        self.as_slice().last().copied()
     }
 
-    fn elem_name(&self, name: &str) -> Vec<&'a Element> {
-        let mut v = vec![];
-        for x in self {
-            if let Node::Element(element) = &x {
-                if element.name.eq_ignore_ascii_case(name) {
-                    v.push(element);
-                } else {
-                    v.append(&mut element.children().elem_name(name));
-                }
-            }
-        }
-        v
+    fn elem_name<'n>(&self, name: impl Into<NameMatch<'n>>) -> Vec<&'a Element> {
+        let name = name.into();
+        self.elem_where(|element| element.is_named(name))
+    }
+
+    fn elem_where<F: Fn(&Element) -> bool>(&self, predicate: F) -> Vec<&'a Element> {
+        let elements: Vec<&'a Element> = self.iter().filter_map(|x| x.as_element()).collect();
+        elements.elem_where(predicate)
+    }
+
+    fn child_at<'n>(&self, name: impl Into<NameMatch<'n>>, position: Position) -> Vec<&'a Element> {
+        let elements: Vec<&'a Element> = self.iter().filter_map(|x| x.as_element()).collect();
+        elements.child_at(name, position)
     }
 }
 impl<'a> QuerySupport<'a, Element> for Vec<&'a Element> {
@@ -93,27 +487,105 @@ impl<'a> QuerySupport<'a, Element> for Vec<&'a Element> {
     }
 
     fn last(&self) -> Option<&'a Element> {
-        if self.len() == 0 {
-            None
-        } else {
-            Some(&self[self.len() - 1])
+        self.as_slice().last().copied()
+    }
+
+    fn elem_name<'n>(&self, name: impl Into<NameMatch<'n>>) -> Vec<&'a Element> {
+        let name = name.into();
+        self.elem_where(|element| element.is_named(name))
+    }
+
+    fn elem_where<F: Fn(&Element) -> bool>(&self, predicate: F) -> Vec<&'a Element> {
+        fn visit<'a>(element: &'a Element, predicate: &dyn Fn(&Element) -> bool, v: &mut Vec<&'a Element>) {
+            if predicate(element) {
+                v.push(element);
+            } else {
+                for child in &element.children {
+                    if let Node::Element(child) = child {
+                        visit(child, predicate, v);
+                    }
+                }
+            }
+        }
+        let mut v = vec![];
+        for x in self {
+            visit(x, &predicate, &mut v);
         }
+        v
     }
 
-    fn elem_name(&self, name: &str) -> Vec<&'a Element> {
+    fn child_at<'n>(&self, name: impl Into<NameMatch<'n>>, position: Position) -> Vec<&'a Element> {
+        let name = name.into();
         let mut v = vec![];
         for x in self {
-            let element = *x;
-            if element.name.eq_ignore_ascii_case(name) {
-                v.push(element);
-            } else {
-                v.append(&mut element.children().elem_name(name));
+            let matching: Vec<&'a Element> = x.children.iter()
+                .filter_map(Node::as_element)
+                .filter(|child| child.is_named(name))
+                .collect();
+            let last = matching.len();
+            for (index, child) in matching.into_iter().enumerate() {
+                if position.matches(index + 1, last) {
+                    v.push(child);
+                }
             }
         }
         v
     }
 }
 
+/// Lazy, document-order equivalent of `elem_name` over the descendants of an element.
+pub struct Select<'a, 'n> {
+    stack: Vec<std::slice::Iter<'a, Node>>,
+    name: NameMatch<'n>,
+}
+
+impl<'a, 'n> Iterator for Select<'a, 'n> {
+    type Item = &'a Element;
+
+    fn next(&mut self) -> Option<&'a Element> {
+        while let Some(top) = self.stack.last_mut() {
+            match top.next() {
+                Some(Node::Element(element)) => {
+                    if element.is_named(self.name) {
+                        return Some(element);
+                    }
+                    self.stack.push(element.children.iter());
+                }
+                Some(_) => {}
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a, 'n> Select<'a, 'n> {
+    pub fn chunks(self, size: usize) -> Chunks<'a, 'n> {
+        assert!(size > 0, "chunk size must be non-zero");
+        Chunks { select: self, size }
+    }
+}
+
+pub struct Chunks<'a, 'n> {
+    select: Select<'a, 'n>,
+    size: usize,
+}
+
+impl<'a, 'n> Iterator for Chunks<'a, 'n> {
+    type Item = Vec<&'a Element>;
+
+    fn next(&mut self) -> Option<Vec<&'a Element>> {
+        let chunk: Vec<&'a Element> = self.select.by_ref().take(self.size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
 impl Node {
     pub fn as_cdata(&self) -> Option<&String> {
         match self {
@@ -145,17 +617,300 @@ impl Node {
     pub fn is_element(&self) -> bool {
         matches!(self, Node::Element(_))
     }
+    pub fn as_comment(&self) -> Option<&str> {
+        match self {
+            Node::Comment(comment) => Some(comment),
+            _ => None
+        }
+    }
 }
 
-#[derive(Debug, Default)]
+/// What happens to whitespace in element content once an element has been parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhitespacePolicy {
+    /// Keep every text node exactly as written, including indentation between tags.
+    Preserve,
+    /// Drop text nodes that are only whitespace, such as indentation, and keep the rest as written.
+    #[default]
+    DropBlank,
+    /// Drop whitespace-only text nodes and trim the others.
+    Trim,
+}
+
+impl WhitespacePolicy {
+    pub fn apply(self, element: &mut Element) {
+        match self {
+            WhitespacePolicy::Preserve => {}
+            WhitespacePolicy::DropBlank => element.strip_whitespace(),
+            WhitespacePolicy::Trim => element.trim_whitespace(),
+        }
+    }
+}
+
+/// Maps element and attribute names as written to the names stored in the tree.
+pub type NameNormalizer = fn(&str) -> Cow<'_, str>;
+
+/// The default `NameNormalizer`: ASCII lowercase, as HTML treats names.
+pub fn lowercase_name(name: &str) -> Cow<'_, str> {
+    if name.bytes().any(|x| x.is_ascii_uppercase()) {
+        Cow::Owned(name.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// A `NameNormalizer` keeping names exactly as written, as XML vocabularies expect.
+pub fn keep_name(name: &str) -> Cow<'_, str> {
+    Cow::Borrowed(name)
+}
+
+/// A `Parser::normalize_text` bringing text and attribute values to Unicode NFC, so that
+/// precomposed and decomposed spellings of the same text compare equal.
+#[cfg(feature = "unicode-normalization")]
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+    match is_nfc_quick(text.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(text),
+        _ => Cow::Owned(text.nfc().collect()),
+    }
+}
+
+/// What the parser does with an attribute value or text node over its length limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// Fail the parse, as for a malformed input.
+    #[default]
+    Error,
+    /// Keep as much as fits, cut back to a character boundary.
+    Truncate,
+}
+
+#[derive(Debug, Clone)]
 pub struct Parser {
-    pub allow_no_close: Vec<String>
+    pub allow_no_close: Vec<String>,
+    /// Treat a `<` that cannot start markup (as in `3 < 5`) as text, like HTML parsers do.
+    pub lenient: bool,
+    pub whitespace: WhitespacePolicy,
+    /// Inputs longer than this many bytes are rejected before any parsing happens.
+    pub max_input_len: Option<usize>,
+    /// Longest attribute value, in bytes once decoded and normalized; see `over_limit`.
+    pub max_attribute_len: Option<usize>,
+    /// Longest text node or CDATA section, measured like `max_attribute_len`.
+    pub max_text_len: Option<usize>,
+    pub over_limit: LimitPolicy,
+    #[cfg(feature = "trace")]
+    pub trace: Option<trace::TraceSink>,
+    /// Applied to element and attribute names; `allow_no_close` is matched after it.
+    pub normalize_name: NameNormalizer,
+    /// Applied to all text, CDATA and attribute values after references are decoded, e.g.
+    /// to bring mixed input to Unicode NFC with `nfc` (the `unicode-normalization` feature).
+    pub normalize_text: Option<fn(&str) -> Cow<'_, str>>,
+    /// Elements handed to another parser by name, as normalized, such as `math` to a MathML
+    /// parser; each becomes a `Node::Foreign` instead of an element, and an `Event::Foreign` in
+    /// the pull reader.
+    pub foreign: Vec<(String, foreign::ForeignParser)>,
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser {
+            allow_no_close: vec![],
+            lenient: false,
+            whitespace: WhitespacePolicy::default(),
+            max_input_len: None,
+            max_attribute_len: None,
+            max_text_len: None,
+            over_limit: LimitPolicy::default(),
+            #[cfg(feature = "trace")]
+            trace: None,
+            normalize_name: lowercase_name,
+            normalize_text: None,
+            foreign: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputTooLarge {
+    pub len: usize,
+    pub limit: usize,
+}
+
+impl std::fmt::Display for InputTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "input of {} bytes exceeds the limit of {} bytes", self.len, self.limit)
+    }
+}
+
+impl std::error::Error for InputTooLarge {}
+
+/// A place in the input: its byte offset, and the 1-based line and column, counting
+/// characters, that an editor shows for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Where `offset` falls in `input`. An offset past the end or inside a character is moved
+    /// back to the end or to the start of that character.
+    pub fn new(input: &str, offset: usize) -> Location {
+        let mut offset = offset.min(input.len());
+        while !input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &input[..offset];
+        let line_start = memchr::memrchr(b'\n', before.as_bytes()).map_or(0, |newline| newline + 1);
+        Location {
+            offset,
+            line: memchr::memchr_iter(b'\n', before.as_bytes()).count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Why `complete_element` or `complete_document` rejected an input, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input ended inside an element.
+    UnexpectedEof { at: Location },
+    /// `found` closes an element opened as `expected`; `at` is the `</`.
+    MismatchedCloseTag { expected: String, found: String, at: Location },
+    /// `at` is the `<` of the start tag.
+    DuplicateAttribute { name: String, at: Location },
+    /// A missing or malformed XML declaration, which `complete_document` requires.
+    BadDeclaration { at: Location },
+    /// Something other than whitespace after the root element.
+    TrailingContent { at: Location },
+    /// The input, an attribute value or a text node is over its limit, as `what` says.
+    TooLarge { what: &'static str, at: Location },
+    /// Bytes that could not be decoded, for `complete_document_bytes`.
+    Encoding(encoding::EncodingError),
+    /// Any other malformed markup, described as `reader::Reader` describes it.
+    Malformed { message: &'static str, at: Location },
+}
+
+impl Error {
+    /// Where the problem is in the text; `None` for `Encoding`, which happens before there is
+    /// any.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            Error::UnexpectedEof { at }
+            | Error::MismatchedCloseTag { at, .. }
+            | Error::DuplicateAttribute { at, .. }
+            | Error::BadDeclaration { at }
+            | Error::TrailingContent { at }
+            | Error::TooLarge { at, .. }
+            | Error::Malformed { at, .. } => Some(*at),
+            Error::Encoding(_) => None,
+        }
+    }
+
+    /// The error for a part of `input` starting at byte `start`, located in all of `input`.
+    #[cfg(feature = "html")]
+    pub(crate) fn within(mut self, input: &str, start: usize) -> Error {
+        match &mut self {
+            Error::UnexpectedEof { at }
+            | Error::MismatchedCloseTag { at, .. }
+            | Error::DuplicateAttribute { at, .. }
+            | Error::BadDeclaration { at }
+            | Error::TrailingContent { at }
+            | Error::TooLarge { at, .. }
+            | Error::Malformed { at, .. } => *at = Location::new(input, start + at.offset),
+            Error::Encoding(_) => {}
+        }
+        self
+    }
+
+    /// Byte offset of the problem; for `Encoding`, in the undecoded bytes.
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::Encoding(e) => e.offset,
+            _ => self.location().map_or(0, |at| at.offset),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnexpectedEof { .. } => f.write_str("unexpected end of input")?,
+            Error::MismatchedCloseTag { expected, found, .. } => write!(f, "</{}> does not close <{}>", found, expected)?,
+            Error::DuplicateAttribute { name, .. } => write!(f, "duplicate attribute {}", name)?,
+            Error::BadDeclaration { .. } => f.write_str("missing or malformed XML declaration")?,
+            Error::TrailingContent { .. } => f.write_str("content after the root element")?,
+            Error::TooLarge { what, .. } => write!(f, "{} too large", what)?,
+            Error::Encoding(e) => return write!(f, "{}", e),
+            Error::Malformed { message, .. } => f.write_str(message)?,
+        }
+        match self.location() {
+            Some(at) => write!(f, " at {}", at),
+            None => Ok(()),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
 pub type IResult<'a, T> = nom::IResult<&'a str, T>;
 
-fn name_char(ch: char) -> bool {
-    ch == ':' || ('a' <= ch && ch <= 'z') || ('A' <= ch && ch <= 'Z') || ch == '!'
+const fn name_char_table(start: bool) -> [bool; 128] {
+    let mut table = [false; 128];
+    let mut index = 0;
+    while index < 128 {
+        let ch = index as u8;
+        table[index] = ch.is_ascii_alphabetic() || matches!(ch, b':' | b'_' | b'!')
+            || (!start && (ch.is_ascii_digit() || matches!(ch, b'-' | b'.')));
+        index += 1;
+    }
+    table
+}
+
+/// ASCII characters allowed in names (`!` included for `<!DOCTYPE`); anything outside ASCII is
+/// accepted as a name character.
+const NAME_CHARS: [bool; 128] = name_char_table(false);
+/// `NAME_CHARS` without digits, `-` and `.`, which cannot begin a name.
+const NAME_START_CHARS: [bool; 128] = name_char_table(true);
+
+#[inline]
+const fn name_char(ch: char) -> bool {
+    let code = ch as u32;
+    if code < 128 {
+        NAME_CHARS[code as usize]
+    } else {
+        true
+    }
+}
+
+#[inline]
+const fn name_start_char(ch: char) -> bool {
+    let code = ch as u32;
+    if code < 128 {
+        NAME_START_CHARS[code as usize]
+    } else {
+        true
+    }
+}
+
+/// Reports a decision to the parser's trace sink; compiled out without the `trace` feature.
+macro_rules! trace {
+    ($parser: expr, $at: expr, |$offset: ident| $event: expr) => {
+        #[cfg(feature = "trace")]
+        if let Some(sink) = &$parser.trace {
+            sink.emit($at, |$offset| $event);
+        }
+        #[cfg(not(feature = "trace"))]
+        let _ = $at;
+    }
 }
 
 macro_rules! ws {
@@ -164,18 +919,24 @@ macro_rules! ws {
     }
 }
 
-fn identifier<'a>(input: &'a str) -> IResult<&'a str> {
-    take_while1(name_char)(input)
+#[inline]
+fn identifier(input: &str) -> IResult<'_, &str> {
+    match input.chars().next() {
+        Some(ch) if name_start_char(ch) => take_while1(name_char)(input),
+        _ => Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::TakeWhile1 })),
+    }
 }
 
-fn attribute_value<'a>(input: &'a str) -> IResult<&'a str> {
-    let (input, quote) = alt((tag("\""), tag("\'")))(input)?;
-    let (input, data) = take_while(|ch| format!("{}", ch) != quote)(input)?;
-    let (input, _) = tag(quote)(input)?;
+#[inline]
+fn attribute_value(input: &str) -> IResult<'_, &str> {
+    let (input, quote) = alt((char('"'), char('\'')))(input)?;
+    let (input, data) = take_while(move |ch| ch != quote)(input)?;
+    let (input, _) = char(quote)(input)?;
     Ok((input, data))
 }
 
-fn eq(input: &str) -> IResult<()> {
+#[inline]
+fn eq(input: &str) -> IResult<'_, ()> {
     ws!(input);
     let (input, _) = tag("=")(input)?;
     ws!(input);
@@ -191,96 +952,249 @@ fn quoted<'a, T, F: Fn(&'a str) -> IResult<'a, T>>(f: impl Fn(&'a str) -> F) ->
     }
 }
 
-fn attribute<'a>(input: &'a str) -> IResult<(String, &'a str)> {
+/// Attribute value normalization: literal tabs and line breaks each become a space, then
+/// references are decoded as in text, so an escaped `&#10;` still comes out as a newline.
+fn normalize_attribute_value(raw: &str) -> String {
+    if !raw.contains(['&', '\t', '\n', '\r']) {
+        return raw.to_string();
+    }
+    let mut value = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(ch) = rest.chars().next() {
+        if let Ok((after, decoded)) = reference(rest) {
+            value.push(decoded);
+            rest = after;
+            continue;
+        }
+        rest = &rest[ch.len_utf8()..];
+        match ch {
+            '\r' => {
+                rest = rest.strip_prefix('\n').unwrap_or(rest);
+                value.push(' ');
+            }
+            '\t' | '\n' => value.push(' '),
+            ch => value.push(ch),
+        }
+    }
+    value
+}
+
+/// An attribute with its name as written; callers normalize it.
+fn attribute(input: &str) -> IResult<'_, (&str, String)> {
     ws!(input);
     let (input, key) = identifier(input)?;
-    let key = key.to_ascii_lowercase();
     let (input, _) = eq(input)?;
     let (input, value) = attribute_value(input)?;
     ws!(input);
-    Ok((input, (key, value)))
+    Ok((input, (key, normalize_attribute_value(value))))
 }
 
 impl Parser {
-    pub fn complete_element(&self, input: &str) -> Option<Element> {
-        let (input, mut element) = self.element(input).ok()?;
-        // let (input, mut element) = self.element(input).unwrap();
-        if input.len() == 0 {
-            element.strip_whitespace();
-            Some(element)
-        } else {
-            None
+    /// Checks `input` against `max_input_len`. Every entry point runs this first: `complete_*`
+    /// and `borrowed::parse_borrowed` fail with `Error::TooLarge`, and `documents` and `Reader`
+    /// with a `ReadError`.
+    pub fn check_input_len(&self, input: &str) -> Result<(), InputTooLarge> {
+        match self.max_input_len {
+            Some(limit) if input.len() > limit => Err(InputTooLarge { len: input.len(), limit }),
+            _ => Ok(()),
         }
     }
-    pub fn complete_document(&self, input: &str) -> Option<Document> {
-        let (input, mut document) = self.document(input).ok()?;
-        if input.len() == 0 {
-            document.root.strip_whitespace();
-            Some(document)
-        } else {
-            None
-        }
+
+    fn guard<'a>(&self, input: &'a str) -> Result<(), nom::Err<nom::error::Error<&'a str>>> {
+        self.check_input_len(input)
+            .map_err(|_| nom::Err::Failure(nom::error::Error { input, code: nom::error::ErrorKind::TooLarge }))
     }
 
-    pub fn element<'a>(&self, input: &'a str) -> IResult<'a, Element> {
-        let (input, _) = tag("<")(input)?;
-        let (input, name) = identifier(input)?;
-        if name == "!DOCTYPE" {
-            let (mut input, _) = take_until(">")(input)?;
-            if input.len() > 0 {
-                input = &input[1..];
-            } else {
-                return Err(nom::Err::Error(nom::error::Error {
-                    // "expected shit after > in doctype decl"
-                    input: "expected shit after > in doctype decl",
-                    code: nom::error::ErrorKind::Tag
-                }))
+    /// How many bytes of `value` to keep under `limit`, or `None` when it is over the limit and
+    /// the policy is `LimitPolicy::Error`.
+    pub(crate) fn limit(&self, value: &str, limit: Option<usize>) -> Option<usize> {
+        match (limit, self.over_limit) {
+            (Some(limit), LimitPolicy::Error) if value.len() > limit => None,
+            (Some(limit), LimitPolicy::Truncate) if value.len() > limit => {
+                Some((0..=limit).rev().find(|&end| value.is_char_boundary(end)).unwrap_or(0))
             }
-            return Ok((input, Element {
-                name: "doctype_decl".to_string(),
-                attributes: Default::default(),
+            _ => Some(value.len()),
+        }
+    }
+
+    fn limited<'a>(&self, mut value: String, limit: Option<usize>, input: &'a str) -> Result<String, nom::Err<nom::error::Error<&'a str>>> {
+        let keep = self.limit(&value, limit)
+            .ok_or(nom::Err::Failure(nom::error::Error { input, code: nom::error::ErrorKind::TooLarge }))?;
+        value.truncate(keep);
+        Ok(value)
+    }
+
+    pub(crate) fn name(&self, name: &str) -> String {
+        (self.normalize_name)(name).into_owned()
+    }
+
+    pub(crate) fn normalized(&self, text: String) -> String {
+        match self.normalize_text.map(|normalize| normalize(&text)) {
+            Some(Cow::Owned(normalized)) => normalized,
+            _ => text,
+        }
+    }
+
+    pub fn complete_element(&self, input: &str) -> Result<Element, Error> {
+        let (rest, mut element) = self.element(input).map_err(|e| self.diagnose(input, e))?;
+        if rest.trim_start().is_empty() {
+            self.whitespace.apply(&mut element);
+            Ok(element)
+        } else {
+            Err(Error::TrailingContent { at: Location::new(input, input.len() - rest.trim_start().len()) })
+        }
+    }
+    pub fn complete_document(&self, input: &str) -> Result<Document, Error> {
+        self.check_input_len(input).map_err(|_| Error::TooLarge { what: "input", at: Location::new(input, 0) })?;
+        let prolog = input.trim_start();
+        if declaration(prolog).is_err() {
+            return Err(Error::BadDeclaration { at: Location::new(input, input.len() - prolog.len()) });
+        }
+        let (rest, mut document) = self.document(input).map_err(|e| self.diagnose(input, e))?;
+        if rest.is_empty() {
+            self.whitespace.apply(&mut document.root);
+            Ok(document)
+        } else {
+            Err(Error::TrailingContent { at: Location::new(input, input.len() - rest.len()) })
+        }
+    }
+
+    /// Like `complete_document` for raw bytes, decoded as `encoding::detect` finds.
+    /// `max_input_len` applies to the bytes, so an oversized input is never decoded.
+    pub fn complete_document_bytes(&self, input: &[u8]) -> Result<Document, Error> {
+        if self.max_input_len.is_some_and(|limit| input.len() > limit) {
+            return Err(Error::TooLarge { what: "input", at: Location::new("", 0) });
+        }
+        let (text, encoding, bom) = encoding::decode(input).map_err(Error::Encoding)?;
+        let mut document = self.complete_document(&text)?;
+        document.source_encoding = Some((encoding, bom));
+        Ok(document)
+    }
+
+    /// Explains a failed parse of `input`. The pull reader finds the same problems and says
+    /// what they are, so it is rerun up to the first one; anything it accepts is reported
+    /// where the tree parser stopped.
+    fn diagnose(&self, input: &str, failure: nom::Err<nom::error::Error<&str>>) -> Error {
+        // Reading again must not repeat the trace events of the failed parse.
+        #[cfg(feature = "trace")]
+        let quiet = Parser { trace: None, ..self.clone() };
+        #[cfg(feature = "trace")]
+        let parser = &quiet;
+        #[cfg(not(feature = "trace"))]
+        let parser = self;
+        let mut reader = reader::Reader::new(parser, input);
+        let e = match reader.by_ref().find_map(Result::err) {
+            Some(e) => e,
+            None => {
+                let offset = match failure {
+                    nom::Err::Error(e) | nom::Err::Failure(e) => input.len().saturating_sub(e.input.len()),
+                    nom::Err::Incomplete(_) => input.len(),
+                };
+                return Error::Malformed { message: "malformed element", at: Location::new(input, offset) };
+            }
+        };
+        let rest = &input[e.offset..];
+        let at = Location::new(input, e.offset);
+        match e.kind {
+            reader::ReadErrorKind::UnexpectedEof => Error::UnexpectedEof { at },
+            reader::ReadErrorKind::MismatchedEndTag => Error::MismatchedCloseTag {
+                expected: reader.open_elements().last().cloned().unwrap_or_default(),
+                found: nom::sequence::preceded(tag("</"), nom::sequence::preceded(take_while(char::is_whitespace), identifier))(rest)
+                    .map_or_else(|_| String::new(), |(_, name)| self.name(name)),
+                at,
+            },
+            reader::ReadErrorKind::DuplicateAttribute => {
+                let names = nom::sequence::preceded(tag("<"), identifier)(rest)
+                    .and_then(|(rest, _)| many0(attribute)(rest.trim_start()))
+                    .map_or_else(|_| vec![], |(_, attributes)| attributes.into_iter().map(|(key, _)| self.name(key)).collect::<Vec<_>>());
+                let name = names.iter().enumerate().find(|&(i, name)| names[..i].contains(name)).map(|(_, name)| name.clone());
+                Error::DuplicateAttribute { name: name.unwrap_or_default(), at }
+            }
+            reader::ReadErrorKind::BadDeclaration => Error::BadDeclaration { at },
+            reader::ReadErrorKind::TooLarge { what } => Error::TooLarge { what, at },
+            _ => Error::Malformed { message: e.message, at },
+        }
+    }
+
+    /// Parses a concatenation of documents, each starting with its own XML declaration, as
+    /// appended by some logging systems. A malformed record is reported and skipped, and
+    /// parsing resumes at the next declaration.
+    pub fn documents<'p, 'a>(&'p self, input: &'a str) -> Documents<'p, 'a> {
+        Documents {
+            parser: self,
+            input,
+            remaining: input,
+            checked: false,
+        }
+    }
+
+    pub(crate) fn element<'a>(&self, input: &'a str) -> IResult<'a, Element> {
+        self.guard(input)?;
+        #[cfg(feature = "trace")]
+        if let Some(sink) = &self.trace {
+            sink.start(input);
+        }
+        self.element_inner(input)
+    }
+
+    fn element_inner<'a>(&self, input: &'a str) -> IResult<'a, Element> {
+        let start = input;
+        let (input, _) = tag("<")(input)?;
+        let (input, name) = identifier(input)?;
+        if name == "!DOCTYPE" {
+            let (input, _) = doctype_body(input)?;
+            return Ok((input, Element {
+                name: "doctype_decl".to_string(),
+                attributes: Default::default(),
                 children: vec![]
             }))
         }
-        let name = name.to_ascii_lowercase();
+        let name = self.name(name);
         ws!(input);
         let (input, attributes) = many0(attribute)(input)?;
+        trace!(self, start, |offset| trace::Trace::Open { name: name.clone(), offset });
         let (input, children) = alt((|input| {
             if self.allow_no_close.contains(&name) {
                 if let Ok((input, _)) = tag::<_, _, nom::error::Error<&str>>(">")(input) {
+                    trace!(self, start, |offset| trace::Trace::Void { name: name.clone(), offset });
                     return Ok((input, vec![]))
                 }
             }
             let (input, _) = tag("/>")(input)?;
+            trace!(self, start, |offset| trace::Trace::Empty { name: name.clone(), offset });
             Ok((input, vec![]))
         }, |input| {
+            // Whitespace in content belongs to the text nodes; `WhitespacePolicy` decides what
+            // to keep once the whole element is parsed.
             let (input, _) = tag(">")(input)?;
-            ws!(input);
-            let (input, (children, _)) = many_till(|input| self.node(input), tag("</"))(input)?;
+            let (input, (children, close)) = many_till(|input| self.node(input), tag("</"))(input)?;
             ws!(input);
             let (input, res) = identifier(input)?;
-            let res = res.to_ascii_lowercase();
+            let res = self.name(res);
             if name == res {
                 ws!(input);
                 let (input, _) = tag(">")(input)?;
+                trace!(self, close, |offset| trace::Trace::Close { name: name.clone(), offset });
                 Ok((input, children))
             } else {
+                trace!(self, close, |offset| trace::Trace::Mismatch { expected: name.clone(), found: res, offset });
                 Err(nom::Err::Failure(nom::error::Error{ input, code: nom::error::ErrorKind::Tag }))
             }
         }))(input)?;
-        ws!(input);
         Ok((input, Element {
             name,
             attributes: {
                 let mut map = HashMap::new();
-                for (key, value) in &attributes {
-                    if map.contains_key(key) {
+                for (key, value) in attributes {
+                    let key = self.name(key);
+                    if map.contains_key(&key) {
+                        trace!(self, start, |offset| trace::Trace::DuplicateAttribute { name: key, offset });
                         return Err(nom::Err::Error(nom::error::Error {
                             input: "duplicate attribute",
                             code: nom::error::ErrorKind::Verify,
                         }));
                     }
-                    map.insert(key.to_string(), value.to_string());
+                    map.insert(key, self.limited(self.normalized(value), self.max_attribute_len, start)?);
                 }
                 map
             },
@@ -288,69 +1202,289 @@ impl Parser {
         }))
     }
 
-    pub fn element_into_node<'a>(&self, input: &'a str) -> IResult<'a, Node> {
-        let (input, element) = self.element(input)?;
+    pub(crate) fn element_into_node<'a>(&self, input: &'a str) -> IResult<'a, Node> {
+        let (input, element) = self.element_inner(input)?;
         Ok((input, Node::Element(element)))
     }
 
-    pub fn node<'a>(&self, input: &'a str) -> IResult<'a, Node> {
-        alt((|input| self.element_into_node(input), char_data_into_node))(input)
+    /// One piece of element content. Markup with a fixed opener is tried before elements so
+    /// that `<!--`, `<![CDATA[` and `<?` are never mistaken for tag names, and text stops at
+    /// the next `<`, leaving whatever follows to the next call.
+    pub(crate) fn node<'a>(&self, input: &'a str) -> IResult<'a, Node> {
+        alt((
+            comment_into_node,
+            char_data_into_node_from(|input| self.cdata(input)),
+            processing_instruction_into_node,
+            |input| self.foreign_node(input),
+            |input| self.element_into_node(input),
+            char_data_into_node_from(|input| self.text(input)),
+        ))(input)
     }
 
-    pub fn document<'a>(&self, input: &'a str) -> IResult<'a, Document> {
-        ws!(input);
-        let (input, _) = tag("<?xml")(input)?;
-        ws!(input);
-        let (input, _) = tag("version")(input)?;
-        let (input, _) = eq(input)?;
-        let (input, version) = quoted(|_| version_num)(input)?;
-        ws!(input);
-        let (input, encoding) = encoding(input)?;
+    /// An element registered in `foreign`, parsed by its handler; an error for any other input
+    /// so that `node` goes on to parse it as usual.
+    fn foreign_node<'a>(&self, input: &'a str) -> IResult<'a, Node> {
+        let fail = |code| nom::Err::Error(nom::error::Error { input, code });
+        if self.foreign.is_empty() {
+            return Err(fail(nom::error::ErrorKind::Tag));
+        }
+        let (_, name) = nom::sequence::preceded(tag("<"), identifier)(input)?;
+        let name = self.name(name);
+        let (_, handler) = self.foreign.iter().find(|(foreign, _)| *foreign == name).ok_or(fail(nom::error::ErrorKind::Tag))?;
+        match handler(input) {
+            Some((len, value)) if len > 0 && input.is_char_boundary(len) => {
+                let source = input[..len].to_string();
+                Ok((&input[len..], Node::Foreign(foreign::Foreign { name, source, value })))
+            }
+            _ => Err(nom::Err::Failure(nom::error::Error { input, code: nom::error::ErrorKind::Verify })),
+        }
+    }
+
+    /// `text_data`, continuing past stray `<` characters when lenient.
+    pub(crate) fn text<'a>(&self, input: &'a str) -> IResult<'a, String> {
+        let (mut input, mut data) = text_data(input)?;
+        while self.lenient && stray_angle_bracket(input) {
+            trace!(self, input, |offset| trace::Trace::StrayAngleBracket { offset });
+            let (rest, more) = text_data(&input[1..])?;
+            data.push('<');
+            data.push_str(&more);
+            input = rest;
+        }
+        let data = self.limited(self.normalized(data), self.max_text_len, input)?;
+        Ok((input, data))
+    }
+
+    pub(crate) fn cdata<'a>(&self, input: &'a str) -> IResult<'a, String> {
+        let (input, data) = cdata_section(input)?;
+        let data = self.limited(self.normalized(data), self.max_text_len, input)?;
+        Ok((input, data))
+    }
+
+    pub(crate) fn document<'a>(&self, input: &'a str) -> IResult<'a, Document> {
+        self.guard(input)?;
+        #[cfg(feature = "trace")]
+        if let Some(sink) = &self.trace {
+            sink.start(input);
+        }
+        self.document_inner(input)
+    }
+
+    fn document_inner<'a>(&self, input: &'a str) -> IResult<'a, Document> {
         ws!(input);
-        let (input, _) = tag("?>")(input)?;
+        let (input, (version, encoding)) = declaration(input)?;
         ws!(input);
-        let (input, root) = self.element(input)?;
+        let (input, root) = self.element_inner(input)?;
         ws!(input);
         Ok((input, Document {
             version,
             encoding: encoding.map(&str::to_string),
+            source_encoding: None,
             root,
         }))
     }
 }
 
-fn is_char(x: char) -> bool {
-    x != '<' && x != '>'
+pub struct Documents<'p, 'a> {
+    parser: &'p Parser,
+    input: &'a str,
+    remaining: &'a str,
+    checked: bool,
+}
+
+impl<'p, 'a> Iterator for Documents<'p, 'a> {
+    type Item = Result<Document, reader::ReadError>;
+
+    fn next(&mut self) -> Option<Result<Document, reader::ReadError>> {
+        if !std::mem::replace(&mut self.checked, true) && self.parser.check_input_len(self.input).is_err() {
+            self.remaining = "";
+            return Some(Err(reader::ReadError::new(0, reader::ReadErrorKind::TooLarge { what: "input" }, "input too large")));
+        }
+        self.remaining = self.remaining.trim_start();
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let offset = self.input.len() - self.remaining.len();
+        #[cfg(feature = "trace")]
+        if let Some(sink) = &self.parser.trace {
+            sink.start(self.input);
+        }
+        match self.parser.document_inner(self.remaining) {
+            Ok((input, mut document)) => {
+                self.remaining = input;
+                self.parser.whitespace.apply(&mut document.root);
+                Some(Ok(document))
+            }
+            Err(_) => {
+                let first = self.remaining.chars().next().map_or(0, char::len_utf8);
+                let skip = self.remaining[first..].find("<?xml").map_or(self.remaining.len(), |x| x + first);
+                self.remaining = &self.remaining[skip..];
+                Some(Err(reader::ReadError::new(offset, reader::ReadErrorKind::Malformed, "malformed document")))
+            }
+        }
+    }
+}
+
+fn declaration(input: &str) -> IResult<'_, (i32, Option<&str>)> {
+    let (input, _) = tag("<?xml")(input)?;
+    ws!(input);
+    let (input, _) = tag("version")(input)?;
+    let (input, _) = eq(input)?;
+    let (input, version) = quoted(|_| version_num)(input)?;
+    ws!(input);
+    let (input, encoding) = encoding(input)?;
+    ws!(input);
+    let (input, _) = tag("?>")(input)?;
+    Ok((input, (version, encoding)))
+}
+
+/// Everything after `<!DOCTYPE` up to the closing `>`, which may not be the first `>`: quoted
+/// identifiers and the bracketed internal subset (with its own declarations and comments)
+/// are skipped over.
+fn doctype_body(input: &str) -> IResult<'_, &str> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut rest = input;
+    while let Some(ch) = rest.chars().next() {
+        match (quote, ch) {
+            (Some(q), ch) if ch == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(ch),
+            (None, '[') => depth += 1,
+            (None, ']') => depth = depth.saturating_sub(1),
+            (None, '<') if depth > 0 && rest.starts_with("<!--") => {
+                let (after, _) = take_until("-->")(rest)?;
+                rest = &after[2..];
+            }
+            (None, '>') if depth == 0 => {
+                let body = &input[..input.len() - rest.len()];
+                return Ok((&rest[1..], body));
+            }
+            _ => {}
+        }
+        rest = &rest[ch.len_utf8()..];
+    }
+    Err(nom::Err::Error(nom::error::Error { input: rest, code: nom::error::ErrorKind::TakeUntil }))
+}
+
+/// Whether `input` starts with a `<` that cannot open a tag, comment, or declaration.
+fn stray_angle_bracket(input: &str) -> bool {
+    let mut chars = input.chars();
+    chars.next() == Some('<') && !chars.next().is_some_and(|ch| name_start_char(ch) || ch == '/' || ch == '?')
+}
+
+/// Decodes a predefined entity or a character reference such as `&#233;` or `&#xE9;`.
+fn reference(input: &str) -> IResult<'_, char> {
+    let (input, _) = char('&')(input)?;
+    let (rest, name) = take_while1(|ch: char| ch.is_ascii_alphanumeric() || ch == '#')(input)?;
+    let (rest, _) = char(';')(rest)?;
+    let decoded = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+            u32::from_str_radix(hex, 16).ok().and_then(std::char::from_u32)
+        } else if let Some(decimal) = name.strip_prefix('#') {
+            decimal.parse().ok().and_then(std::char::from_u32)
+        } else {
+            None
+        }
+    };
+    match decoded {
+        Some(ch) => Ok((rest, ch)),
+        None => Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::Char })),
+    }
+}
+
+fn comment(input: &str) -> IResult<'_, &str> {
+    let (input, _) = tag("<!--")(input)?;
+    let (input, data) = take_until("-->")(input)?;
+    let (input, _) = tag("-->")(input)?;
+    Ok((input, data))
+}
+
+fn comment_into_node(input: &str) -> IResult<'_, Node> {
+    let (input, data) = comment(input)?;
+    Ok((input, Node::Comment(data.to_string())))
+}
+
+/// `<?target data?>`, other than the XML declaration.
+fn processing_instruction(input: &str) -> IResult<'_, (&str, &str)> {
+    let (input, _) = tag("<?")(input)?;
+    let (input, target) = identifier(input)?;
+    if target.eq_ignore_ascii_case("xml") {
+        return Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::Verify }));
+    }
+    ws!(input);
+    let (input, data) = take_until("?>")(input)?;
+    let (input, _) = tag("?>")(input)?;
+    Ok((input, (target, data.trim_end())))
+}
+
+fn processing_instruction_into_node(input: &str) -> IResult<'_, Node> {
+    let (input, (target, data)) = processing_instruction(input)?;
+    Ok((input, Node::ProcessingInstruction { target: target.to_string(), data: data.to_string() }))
+}
+
+fn char_data_into_node_from<'a>(parser: impl Fn(&'a str) -> IResult<'a, String>) -> impl Fn(&'a str) -> IResult<'a, Node> {
+    move |input| {
+        let (input, data) = parser(input)?;
+        Ok((input, Node::CharData(data)))
+    }
 }
 
-fn cdata_section(input: &str) -> IResult<String> {
+fn cdata_section(input: &str) -> IResult<'_, String> {
     let (input, _) = tag("<![CDATA[")(input)?;
     let (input, data) = take_until("]]>")(input)?;
     let (input, _) = tag("]]>")(input)?;
     Ok((input, data.to_string()))
 }
 
-fn text_data(input: &str) -> IResult<String> {
-    let (input, data) = take_while(is_char)(input)?;
-    Ok((input, data.to_string()))
+/// Text up to the next `<`, with references decoded. A `&` that does not start a known
+/// reference is kept as-is, as scraped pages are full of bare ampersands.
+fn text_data(input: &str) -> IResult<'_, String> {
+    let mut data = String::new();
+    let mut input = input;
+    loop {
+        let end = memchr::memchr2(b'<', b'&', input.as_bytes()).unwrap_or(input.len());
+        data.push_str(&input[..end]);
+        input = &input[end..];
+        if !input.starts_with('&') {
+            return Ok((input, data));
+        }
+        match reference(input) {
+            Ok((rest, ch)) => {
+                data.push(ch);
+                input = rest;
+            }
+            Err(_) => {
+                data.push('&');
+                input = &input[1..];
+            }
+        }
+    }
 }
 
-pub fn char_data(input: &str) -> IResult<String> {
+pub fn char_data(input: &str) -> IResult<'_, String> {
     alt((cdata_section, text_data))(input)
 }
 
-pub fn char_data_into_node(input: &str) -> IResult<Node> {
+pub fn char_data_into_node(input: &str) -> IResult<'_, Node> {
     let (input, data) = char_data(input)?;
     Ok((input, Node::CharData(data)))
 }
 
-fn version_num(input: &str) -> IResult<i32> {
+fn version_num(input: &str) -> IResult<'_, i32> {
     let (input, _) = tag("1.")(input)?;
-    let (input, data) = take_while(|ch: char| ch.is_ascii_digit())(input)?;
-    Ok((input, data.parse().unwrap()))
+    let (rest, data) = take_while1(|ch: char| ch.is_ascii_digit())(input)?;
+    match data.parse() {
+        Ok(version) => Ok((rest, version)),
+        Err(_) => Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::Digit })),
+    }
 }
 
-fn encoding<'a>(input: &'a str) -> IResult<Option<&'a str>> {
+fn encoding(input: &str) -> IResult<'_, Option<&str>> {
     if let Ok((input, _)) = tag::<_, _, nom::error::Error<&str>>("encoding")(input) {
         let (input, _) = eq(input)?;
         let (input, data) = attribute_value(input)?;
@@ -360,15 +1494,389 @@ fn encoding<'a>(input: &'a str) -> IResult<Option<&'a str>> {
     }
 }
 
+impl AsRef<Element> for Element {
+    fn as_ref(&self) -> &Element {
+        self
+    }
+}
+
+impl AsRef<Element> for Document {
+    fn as_ref(&self) -> &Element {
+        &self.root
+    }
+}
+
+/// A name that would not survive serialization, such as `"two words"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName(pub String);
+
+impl std::fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid XML name {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidName {}
+
+/// An element or attribute name known to be a valid XML name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NameToken(String);
+
+impl NameToken {
+    /// Accepts names starting with a letter, `_`, `:` or non-ASCII character, followed by
+    /// those or digits, `-` and `.`.
+    pub fn new(name: &str) -> Result<NameToken, InvalidName> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|ch| name_start_char(ch) && ch != '!')
+            && chars.all(|ch| name_char(ch) && ch != '!');
+        if valid {
+            Ok(NameToken(name.to_string()))
+        } else {
+            Err(InvalidName(name.to_string()))
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::convert::TryFrom<&str> for NameToken {
+    type Error = InvalidName;
+
+    fn try_from(name: &str) -> Result<NameToken, InvalidName> {
+        NameToken::new(name)
+    }
+}
+
+impl std::fmt::Display for NameToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 impl Element {
+    pub fn new(name: &str) -> Result<Element, InvalidName> {
+        Ok(Element::named(NameToken::new(name)?))
+    }
+    pub fn named(name: NameToken) -> Element {
+        Element {
+            name: name.0,
+            attributes: HashMap::new(),
+            children: vec![],
+        }
+    }
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Element {
+        self.set_attribute(name, value);
+        self
+    }
+    /// `with_attribute`, rejecting an invalid attribute name.
+    pub fn try_with_attribute(self, name: &str, value: &str) -> Result<Element, InvalidName> {
+        NameToken::new(name)?;
+        Ok(self.with_attribute(name, value))
+    }
+    pub fn with_child(mut self, child: Node) -> Element {
+        self.children.push(child);
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+    /// Like `set_name`, returning the old name.
+    pub fn rename(&mut self, to: &str) -> String {
+        std::mem::replace(&mut self.name, to.to_string())
+    }
+    /// Renames this element and every descendant whose name is a key of `names`, returning
+    /// how many were renamed.
+    pub fn rename_elements(&mut self, names: &HashMap<&str, &str>) -> usize {
+        let mut count = 0;
+        if let Some(to) = names.get(self.name.as_str()) {
+            self.name = to.to_string();
+            count += 1;
+        }
+        for child in &mut self.children {
+            if let Node::Element(child) = child {
+                count += child.rename_elements(names);
+            }
+        }
+        count
+    }
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+    /// The attribute `local` in namespace `uri` (see `ns` for well-known ones), with prefixes
+    /// declared on this element itself. Use `attr_ns_in` when declarations sit on ancestors.
+    /// An empty `uri` means an unprefixed attribute.
+    pub fn attr_ns(&self, uri: &str, local: &str) -> Option<&str> {
+        self.attr_ns_scoped(&[self], uri, local)
+    }
+    /// Like `attr_ns`, also honouring declarations on this element's ancestors in `doc`.
+    pub fn attr_ns_in(&self, doc: &impl AsRef<Element>, uri: &str, local: &str) -> Option<&str> {
+        let mut scope = doc.as_ref().path_to(self)?;
+        scope.reverse();
+        self.attr_ns_scoped(&scope, uri, local)
+    }
+    /// Whether the element is marked `xsi:nil="true"`, XML Schema's null.
+    pub fn is_nil(&self) -> bool {
+        matches!(self.attr_ns(ns::XSI, "nil").or_else(|| self.attribute("xsi:nil")), Some("true") | Some("1"))
+    }
+    fn attr_ns_scoped(&self, scope: &[&Element], uri: &str, local: &str) -> Option<&str> {
+        if uri.is_empty() {
+            return self.attribute(local);
+        }
+        ns::prefixes_for(scope, uri).into_iter().find_map(|prefix| self.attribute(&format!("{}:{}", prefix, local)))
+    }
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.contains_key(name)
+    }
+    pub fn attributes(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.attributes.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        self.attributes.insert(name.to_string(), value.to_string())
+    }
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        self.attributes.remove(name)
+    }
+    /// Moves the value of `from` to `to`, replacing any existing `to`. Returns false if there
+    /// is no `from` attribute.
+    pub fn rename_attribute(&mut self, from: &str, to: &str) -> bool {
+        match self.attributes.remove(from) {
+            Some(value) => {
+                self.attributes.insert(to.to_string(), value);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn push_child(&mut self, child: Node) {
+        self.children.push(child);
+    }
+    pub fn children_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.children
+    }
+    pub fn into_children(self) -> Vec<Node> {
+        self.children
+    }
+    /// Moves all children into a new `name` element, which becomes the only child.
+    pub fn wrap_children(&mut self, name: &str) -> &mut Element {
+        let wrapper = Element {
+            name: name.to_string(),
+            attributes: HashMap::new(),
+            children: std::mem::take(&mut self.children),
+        };
+        self.children.push(Node::Element(wrapper));
+        match self.children.last_mut() {
+            Some(Node::Element(wrapper)) => wrapper,
+            _ => unreachable!(),
+        }
+    }
+    /// Stable sort of the element children. Non-element nodes (text, comments) move together
+    /// with the element that follows them; any after the last element stay at the end.
+    pub fn sort_children_by(&mut self, mut compare: impl FnMut(&Element, &Element) -> std::cmp::Ordering) {
+        let mut groups: Vec<(Vec<Node>, Element)> = vec![];
+        let mut pending = vec![];
+        for child in std::mem::take(&mut self.children) {
+            match child {
+                Node::Element(element) => groups.push((std::mem::take(&mut pending), element)),
+                other => pending.push(other),
+            }
+        }
+        groups.sort_by(|(_, a), (_, b)| compare(a, b));
+        for (leading, element) in groups {
+            self.children.extend(leading);
+            self.children.push(Node::Element(element));
+        }
+        self.children.extend(pending);
+    }
+    /// Computes all of `SubtreeStats` in one pass, without recursion.
+    pub fn subtree_stats(&self) -> SubtreeStats {
+        let mut stats = SubtreeStats { depth_below: 0, size: 0, max_breadth: 0 };
+        let mut stack = vec![(self, 0)];
+        while let Some((element, depth)) = stack.pop() {
+            stats.size += 1;
+            stats.depth_below = stats.depth_below.max(depth);
+            let before = stack.len();
+            stack.extend(element.children.iter().filter_map(Node::as_element).map(|x| (x, depth + 1)));
+            stats.max_breadth = stats.max_breadth.max(stack.len() - before);
+        }
+        stats
+    }
+    pub fn depth_below(&self) -> usize {
+        self.subtree_stats().depth_below
+    }
+    pub fn subtree_size(&self) -> usize {
+        self.subtree_stats().size
+    }
+    pub fn max_breadth(&self) -> usize {
+        self.subtree_stats().max_breadth
+    }
+    /// Removes element children whose key was already seen on an earlier sibling, keeping the
+    /// first. Returns how many were removed.
+    pub fn dedup_children_by<K: Eq + std::hash::Hash>(&mut self, mut key: impl FnMut(&Element) -> K) -> usize {
+        let mut seen = std::collections::HashSet::new();
+        let before = self.children.len();
+        self.children.retain(|child| match child {
+            Node::Element(element) => seen.insert(key(element)),
+            _ => true,
+        });
+        before - self.children.len()
+    }
+    /// Removes element children structurally equal to an earlier sibling.
+    pub fn dedup_children(&mut self) -> usize {
+        let mut kept: Vec<Element> = vec![];
+        let before = self.children.len();
+        self.children.retain(|child| match child {
+            Node::Element(element) if kept.contains(element) => false,
+            Node::Element(element) => {
+                kept.push(element.clone());
+                true
+            }
+            _ => true,
+        });
+        before - self.children.len()
+    }
+    /// Sorts element children by the value of attribute `name`; elements without it go first.
+    pub fn sort_children_by_attr(&mut self, name: &str) {
+        self.sort_children_by(|a, b| a.attribute(name).cmp(&b.attribute(name)));
+    }
+    /// Replaces the element child at `index` with its own children. Returns false, leaving
+    /// the children alone, if there is no element at `index`.
+    pub fn unwrap_child(&mut self, index: usize) -> bool {
+        if !self.children.get(index).is_some_and(Node::is_element) {
+            return false;
+        }
+        if let Node::Element(child) = self.children.remove(index) {
+            self.children.splice(index..index, child.children);
+        }
+        true
+    }
+    pub fn is_named<'n>(&self, name: impl Into<NameMatch<'n>>) -> bool {
+        name.into().matches(&self.name)
+    }
     pub fn strip_whitespace(&mut self) {
-        self.children.retain(|e| if let Node::CharData(data) = e { data.trim().len() > 0 } else { true });
+        self.children.retain(|e| if let Node::CharData(data) = e { !data.trim().is_empty() } else { true });
         for x in &mut self.children {
             if let Node::Element(y) = x {
                 y.strip_whitespace();
             }
         }
     }
+    /// Like `strip_whitespace`, also trimming the text that remains.
+    pub fn trim_whitespace(&mut self) {
+        self.strip_whitespace();
+        for x in &mut self.children {
+            match x {
+                Node::CharData(data) => *data = data.trim().to_string(),
+                Node::Element(y) => y.trim_whitespace(),
+                _ => {}
+            }
+        }
+    }
+    /// All character data below this element, concatenated in document order.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for x in &self.children {
+            match x {
+                Node::CharData(data) => text.push_str(data),
+                Node::Element(element) => text.push_str(&element.text()),
+                Node::Comment(_) | Node::ProcessingInstruction { .. } | Node::Foreign(_) => {}
+            }
+        }
+        text
+    }
+    pub fn select_iter<'a, 'n>(&'a self, name: impl Into<NameMatch<'n>>) -> Select<'a, 'n> {
+        Select {
+            stack: vec![self.children.iter()],
+            name: name.into(),
+        }
+    }
+    /// Elements from `self` down to `target` (both inclusive), where `target` is a node somewhere in this tree.
+    pub fn path_to<'a>(&'a self, target: &Element) -> Option<Vec<&'a Element>> {
+        if std::ptr::eq(self, target) {
+            return Some(vec![self]);
+        }
+        for child in &self.children {
+            if let Node::Element(child) = child {
+                if let Some(mut path) = child.path_to(target) {
+                    path.insert(0, self);
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+    pub fn parent_of<'a>(&'a self, target: &Element) -> Option<&'a Element> {
+        self.ancestors_of(target).first()
+    }
+    /// Ancestors of `target`, nearest first.
+    pub fn ancestors_of<'a>(&'a self, target: &Element) -> Vec<&'a Element> {
+        let mut path = self.path_to(target).unwrap_or_default();
+        path.pop();
+        path.reverse();
+        path
+    }
+    /// Element siblings after `target`, in document order.
+    pub fn following_siblings_of<'a>(&'a self, target: &Element) -> Vec<&'a Element> {
+        match self.parent_of(target) {
+            Some(parent) => parent.children.iter()
+                .filter_map(Node::as_element)
+                .skip_while(|x| !std::ptr::eq(*x, target))
+                .skip(1)
+                .collect(),
+            None => vec![],
+        }
+    }
+    /// Path from the root of `doc` to `self`, like `html > body > div#main > p[3]`: an `id`
+    /// attribute is shown as `#id`, otherwise a 1-based position is added when the parent has
+    /// more than one child element of that name. `None` if `self` is not in `doc`.
+    pub fn breadcrumb(&self, doc: &impl AsRef<Element>) -> Option<String> {
+        let path = doc.as_ref().path_to(self)?;
+        let mut steps = vec![path[0].name.clone()];
+        for pair in path.windows(2) {
+            let (parent, element) = (pair[0], pair[1]);
+            let step = match element.attribute("id") {
+                Some(id) => format!("{}#{}", element.name, id),
+                None => {
+                    let same = parent.children.iter().filter_map(Node::as_element).filter(|x| x.name == element.name);
+                    match same.clone().count() {
+                        1 => element.name.clone(),
+                        _ => format!("{}[{}]", element.name, same.take_while(|x| !std::ptr::eq(*x, element)).count() + 1),
+                    }
+                }
+            };
+            steps.push(step);
+        }
+        Some(steps.join(" > "))
+    }
+    /// Element siblings before `target`, nearest first.
+    pub fn preceding_siblings_of<'a>(&'a self, target: &Element) -> Vec<&'a Element> {
+        match self.parent_of(target) {
+            Some(parent) => parent.children.iter()
+                .rev()
+                .filter_map(Node::as_element)
+                .skip_while(|x| !std::ptr::eq(*x, target))
+                .skip(1)
+                .collect(),
+            None => vec![],
+        }
+    }
+    /// Treats each child element as a row; a column takes the row's attribute of that name,
+    /// falling back to the text of its first child element with that name.
+    pub fn to_table(&self, columns: &[&str]) -> Vec<Vec<Option<String>>> {
+        self.children.iter()
+            .filter_map(Node::as_element)
+            .map(|row| columns.iter()
+                .map(|column| row.attributes.get(*column).cloned()
+                    .or_else(|| row.children.iter()
+                        .filter_map(Node::as_element)
+                        .find(|x| x.is_named(*column))
+                        .map(Element::text)))
+                .collect())
+            .collect()
+    }
     pub fn children(&self) -> Vec<&Node> {
         let mut v = vec![];
         for x in &self.children {
@@ -376,11 +1884,40 @@ impl Element {
         }
         v
     }
+    /// Writes the subtree as XML, attributes sorted by name and text escaped. Fails with
+    /// `InvalidInput` for content XML cannot hold, such as a comment containing `--`.
+    #[cfg(feature = "write")]
+    pub fn write_xml<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        let mut writer = writer::Writer::new(w);
+        writer.write_element(self)?;
+        writer.flush()
+    }
+    #[cfg(feature = "write")]
+    pub fn to_xml_string(&self) -> std::io::Result<String> {
+        let mut out = vec![];
+        self.write_xml(&mut out)?;
+        Ok(String::from_utf8(out).expect("the writer writes UTF-8"))
+    }
+    /// `to_xml_string` indented by `indent` spaces per level; see `writer::PrettyPrinter` for
+    /// the other options.
+    #[cfg(feature = "write")]
+    pub fn to_pretty_string(&self, indent: usize) -> std::io::Result<String> {
+        writer::PrettyPrinter::new().spaces(indent).element_to_string(self)
+    }
+    /// Attributes sorted by name, for deterministic iteration.
+    pub fn attributes_sorted(&self) -> Vec<(&str, &str)> {
+        let mut v: Vec<(&str, &str)> = self.attributes.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        v.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        v
+    }
 }
 
 pub fn strip_whitespace(node: Node) -> Node {
     match node {
         Node::CharData(data) => Node::CharData(data.trim().to_string()),
+        node @ Node::Comment(_) | node @ Node::ProcessingInstruction { .. } | node @ Node::Foreign(_) => node,
         Node::Element(data) => Node::Element(Element {
             name: data.name,
             attributes: data.attributes,
@@ -389,7 +1926,7 @@ pub fn strip_whitespace(node: Node) -> Node {
                 for x in data.children {
                     let x = strip_whitespace(x);
                     if let Node::CharData(d) = &x {
-                        if d.len() == 0 {
+                        if d.is_empty() {
                             continue;
                         }
                     }
@@ -407,7 +1944,8 @@ mod tests {
 
     fn e(x: &str) {
         let parser: Parser = Parser {
-            allow_no_close: vec!["img".to_string()]
+            allow_no_close: vec!["img".to_string()],
+            ..Default::default()
         };
         let res = parser.complete_element(x).unwrap();
         println!("{}\n{:#?}", x, res);
@@ -417,4 +1955,577 @@ mod tests {
     fn it_works() {
         e(&std::fs::read_to_string("test.xml").unwrap());
     }
+
+    #[test]
+    fn accessors() {
+        let mut element = Element::new("item").unwrap()
+            .with_attribute("id", "1")
+            .with_child(Node::CharData("text".to_string()));
+        assert_eq!(element.name(), "item");
+        assert_eq!(element.attribute("id"), Some("1"));
+        assert_eq!(element.set_attribute("id", "2"), Some("1".to_string()));
+        assert_eq!(element.remove_attribute("id"), Some("2".to_string()));
+        assert!(!element.has_attribute("id"));
+        element.push_child(Node::Element(Element::new("sub").unwrap()));
+        element.set_name("entry");
+        let document = Document::new(element).with_encoding("UTF-8");
+        assert_eq!(document.encoding(), Some("UTF-8"));
+        assert_eq!(document.root().name(), "entry");
+        assert_eq!(document.into_root().into_children().len(), 2);
+    }
+
+    #[test]
+    fn text_with_angle_brackets_and_references() {
+        let res = Parser::default().complete_element("<p>a > b &amp;&amp; c &lt; d</p>").unwrap();
+        assert_eq!(res.text(), "a > b && c < d");
+        let res = Parser::default().complete_element("<p>AT&T &#233;&#xe9; &bogus; &#xZZ;</p>").unwrap();
+        assert_eq!(res.text(), "AT&T éé &bogus; &#xZZ;");
+    }
+
+    #[test]
+    fn lenient_angle_brackets() {
+        let input = "<p>3 < 5 and 2 <= 4 <<b>x</b></p>";
+        assert!(Parser::default().complete_element(input).is_err());
+        let parser = Parser { lenient: true, ..Default::default() };
+        let res = parser.complete_element(input).unwrap();
+        assert_eq!(res.text(), "3 < 5 and 2 <= 4 <x");
+        assert_eq!(res.children().elem_name("b").len(), 1);
+    }
+
+    #[test]
+    fn doctype_subsets() {
+        let body = r#" root SYSTEM "a>b.dtd" [ <!ENTITY x "y>"> <!-- ] > --> <!ELEMENT root ANY> ]"#;
+        let input = format!("{}><root/>", body);
+        assert_eq!(doctype_body(&input), Ok(("<root/>", body)));
+        assert!(doctype_body(" root [ <!ENTITY x 'y'>").is_err());
+    }
+
+    #[test]
+    fn content_ordering() {
+        let pieces = [
+            ("a>b&amp;c", "text a>b&c"),
+            ("<![CDATA[<x>]]>", "text <x>"),
+            ("<!-- c -->", "comment  c "),
+            ("<?pi some data?>", "pi pi some data"),
+            ("<e/>", "element e"),
+        ];
+        let describe = |node: &Node| match node {
+            Node::CharData(data) => format!("text {}", data),
+            Node::Comment(data) => format!("comment {}", data),
+            Node::ProcessingInstruction { target, data } => format!("pi {} {}", target, data),
+            Node::Element(element) => format!("element {}", element.name()),
+            Node::Foreign(foreign) => format!("foreign {}", foreign.name()),
+        };
+        let mut order: Vec<usize> = (0..pieces.len()).collect();
+        // Heap's algorithm, to cover every adjacency of every kind of content.
+        let mut counters = vec![0; order.len()];
+        let mut i = 0;
+        loop {
+            let input = format!("<r>{}</r>", order.iter().map(|&x| pieces[x].0).collect::<String>());
+            let res = Parser::default().complete_element(&input).unwrap();
+            let got: Vec<String> = res.children.iter().map(describe).collect();
+            let expected: Vec<&str> = order.iter().map(|&x| pieces[x].1).collect();
+            assert_eq!(got, expected, "{}", input);
+            while i < order.len() && counters[i] >= i {
+                counters[i] = 0;
+                i += 1;
+            }
+            if i == order.len() {
+                break;
+            }
+            order.swap(if i % 2 == 0 { 0 } else { counters[i] }, i);
+            counters[i] += 1;
+            i = 0;
+        }
+    }
+
+    #[test]
+    fn whitespace_policies() {
+        let input = "<a>\n  <b> x </b> y \n</a>\n";
+        let texts = |whitespace| {
+            let res = Parser { whitespace, ..Default::default() }.complete_element(input).unwrap();
+            res.children.iter().map(|x| match x {
+                Node::CharData(data) => data.clone(),
+                _ => "<b>".to_string(),
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(texts(WhitespacePolicy::Preserve), vec!["\n  ", "<b>", " y \n"]);
+        assert_eq!(texts(WhitespacePolicy::DropBlank), vec!["<b>", " y \n"]);
+        assert_eq!(texts(WhitespacePolicy::Trim), vec!["<b>", "y"]);
+    }
+
+    #[test]
+    fn attribute_normalization() {
+        let res = Parser::default()
+            .complete_element("<a title=\"say &quot;hi&quot; &amp; go\" lines='one\r\ntwo\tthree&#10;four' raw=\"AT&T\"/>")
+            .unwrap();
+        assert_eq!(res.attribute("title"), Some("say \"hi\" & go"));
+        assert_eq!(res.attribute("lines"), Some("one two three\nfour"));
+        assert_eq!(res.attribute("raw"), Some("AT&T"));
+    }
+
+    #[test]
+    fn document_streams() {
+        let input = "<?xml version=\"1.0\"?><log n=\"1\"/>\n<?xml version=\"1.0\"?><log>oops</gol>\n<?xml version=\"1.0\"?>\n<log n=\"3\"/>\n";
+        let parser = Parser::default();
+        let docs: Vec<_> = parser.documents(input).collect();
+        assert_eq!(docs.len(), 3);
+        assert_eq!(docs[0].as_ref().unwrap().root().attribute("n"), Some("1"));
+        assert_eq!(docs[1].as_ref().unwrap_err().offset, 34);
+        assert_eq!(docs[2].as_ref().unwrap().root().attribute("n"), Some("3"));
+    }
+
+    #[test]
+    fn input_size_limit() {
+        let parser = Parser { max_input_len: Some(8), ..Default::default() };
+        assert!(parser.complete_element("<a>x</a>").is_ok());
+        assert!(parser.complete_element("<a>xy</a>").is_err());
+        assert_eq!(parser.check_input_len("<a>xy</a>"), Err(InputTooLarge { len: 9, limit: 8 }));
+        assert!(matches!(parser.element("<a>xy</a>"), Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge));
+        assert_eq!(parser.documents("<a>xy</a>").next().unwrap().unwrap_err().message, "input too large");
+        assert_eq!(reader::Reader::new(&parser, "<a>xy</a>").next().unwrap().unwrap_err().message, "input too large");
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("<a/>".encode_utf16().flat_map(u16::to_le_bytes));
+        assert!(matches!(parser.complete_document_bytes(&utf16), Err(Error::TooLarge { .. })));
+    }
+
+    #[test]
+    fn tag_name_constants() {
+        tag_names! { ORDER = "order", pub(crate) LINE = "line" }
+        let order = Parser::default().complete_element("<ORDER><line/><Line/><note/></ORDER>").unwrap();
+        assert!(order.is_named(ORDER));
+        assert_eq!(vec![&order].elem_name(LINE).len(), 2);
+        assert_eq!(order.select_iter(LINE).count(), 2);
+        assert_eq!(Element::named(LINE.into()).name(), "line");
+        assert!(ORDER == *"order");
+        assert_eq!(ORDER.to_string(), "order");
+    }
+
+    #[test]
+    fn value_length_limits() {
+        let input = "<a k=\"caf\u{e9}s\"><![CDATA[12345]]>abcdef</a>";
+        let mut parser = Parser { max_attribute_len: Some(4), max_text_len: Some(5), ..Default::default() };
+        assert!(parser.complete_element(input).is_err());
+        let events: Vec<_> = reader::Reader::new(&parser, input).collect();
+        assert_eq!(events.last().unwrap().as_ref().unwrap_err().message, "attribute value too long");
+        assert_eq!(borrowed::parse_borrowed(&parser, input).unwrap_err(), parser.complete_element(input).unwrap_err());
+
+        parser.over_limit = LimitPolicy::Truncate;
+        let element = parser.complete_element(input).unwrap();
+        assert_eq!(element.attribute("k"), Some("caf"));
+        assert_eq!(element.children, [Node::CharData("12345".to_string()), Node::CharData("abcde".to_string())]);
+        let borrowed = borrowed::parse_borrowed(&parser, input).unwrap();
+        assert_eq!(borrowed.attribute("k").map(|x| x.as_ref()), Some("caf"));
+
+        parser.max_attribute_len = None;
+        parser.over_limit = LimitPolicy::Error;
+        let events: Vec<_> = reader::Reader::new(&parser, input).collect();
+        assert_eq!(events.last().unwrap().as_ref().unwrap_err().message, "text too long");
+    }
+
+    /// Mangles seed documents with a fixed-seed generator and runs every entry point over the
+    /// results. Any panic fails the test; the outcome of each parse does not matter.
+    #[test]
+    fn no_panics_on_mangled_input() {
+        let tokens = [
+            "<", ">", "/", "/>", "</", "&", "&#", "&#x", "&amp;", ";", "\"", "'", "=", " ", "\n",
+            "<!--", "-->", "<![CDATA[", "]]>", "<?", "?>", "<?xml version=\"1.", "<!DOCTYPE", "[", "]",
+            "é", "\u{0}", "1.", "<a>", "</a>",
+        ];
+        let mut seeds = vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE r [ <!ENTITY x "y"> ]><r a="1&amp;2"><!--c--><?pi d?>t<![CDATA[x]]><e/></r>"#.to_string(),
+            "<p>3 < 5 &bogus; <br><b>é</b></p>".to_string(),
+        ];
+        seeds.push(std::fs::read_to_string("test.xml").unwrap());
+        let strict = Parser::default();
+        let lenient = Parser {
+            allow_no_close: vec!["br".to_string()],
+            lenient: true,
+            whitespace: WhitespacePolicy::Trim,
+            ..Default::default()
+        };
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+        for round in 0..3000 {
+            let seed = &seeds[round % seeds.len()];
+            let mut input: Vec<char> = seed.chars().collect();
+            for _ in 0..1 + random(4) {
+                let at = random(input.len() + 1);
+                match random(3) {
+                    0 => input.splice(at..at, tokens[random(tokens.len())].chars()).for_each(drop),
+                    1 => input.truncate(at),
+                    _ => if at < input.len() {
+                        input.remove(at);
+                    },
+                }
+            }
+            let input: String = input.into_iter().collect();
+            for parser in [&strict, &lenient] {
+                let _ = parser.complete_element(&input);
+                let _ = parser.complete_document(&input);
+                parser.documents(&input).for_each(drop);
+                reader::Reader::new(parser, &input).for_each(drop);
+            }
+        }
+        assert!(Vec::<&Node>::new().last().is_none());
+        assert!(Parser::default().complete_document("<?xml version=\"1.99999999999\"?><a/>").is_err());
+    }
+
+    #[test]
+    fn renaming() {
+        let mut res = Parser::default()
+            .complete_element(r#"<vendor-a><item sku="1"><label>x</label></item><item sku="2"/></vendor-a>"#)
+            .unwrap();
+        assert_eq!(res.rename("feed"), "vendor-a");
+        let names = HashMap::from([("item", "entry"), ("label", "title")]);
+        assert_eq!(res.rename_elements(&names), 3);
+        let entries = res.children().elem_name("entry");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].children().elem_name("title").len(), 1);
+        let mut entry = entries[0].clone();
+        assert!(entry.rename_attribute("sku", "id"));
+        assert!(!entry.rename_attribute("sku", "id"));
+        assert_eq!(entry.attribute("id"), Some("1"));
+    }
+
+    #[test]
+    fn wrap_and_unwrap() {
+        let mut res = Parser::default().complete_element("<ul><li>a</li>text<li>b</li></ul>").unwrap();
+        res.wrap_children("group").set_attribute("kind", "letters");
+        assert_eq!(res.children.len(), 1);
+        let group = res.children().elem_name("group")[0];
+        assert_eq!(group.children.len(), 3);
+        assert_eq!(group.attribute("kind"), Some("letters"));
+        assert!(!res.unwrap_child(1));
+        assert!(res.unwrap_child(0));
+        assert_eq!(res.children.len(), 3);
+        assert_eq!(res.children[1].as_cdata().map(String::as_str), Some("text"));
+    }
+
+    #[test]
+    fn sorting_children() {
+        let mut res = Parser::default()
+            .complete_element(r#"<deps><!--web--><dep name="serde"/><dep name="nom"/><!--parsing--><dep/><dep name="anyhow"/>tail</deps>"#)
+            .unwrap();
+        res.sort_children_by_attr("name");
+        let order: Vec<String> = res.children.iter().map(|x| match x {
+            Node::Element(e) => e.attribute("name").unwrap_or("-").to_string(),
+            Node::Comment(c) => format!("#{}", c),
+            Node::CharData(t) => t.clone(),
+            _ => String::new(),
+        }).collect();
+        assert_eq!(order, vec!["#parsing", "-", "anyhow", "nom", "#web", "serde", "tail"]);
+        res.sort_children_by(|a, b| b.attribute("name").cmp(&a.attribute("name")));
+        assert_eq!(res.children().elem_name("dep")[0].attribute("name"), Some("serde"));
+    }
+
+    #[test]
+    fn deduplicating_children() {
+        let input = r#"<merged><a id="1" x="y"><v/></a><a x="y" id="1"><v/></a><a id="1"/><b id="1"/></merged>"#;
+        let mut res = Parser::default().complete_element(input).unwrap();
+        assert_eq!(res.dedup_children(), 1);
+        assert_eq!(res.children.len(), 3);
+        assert_eq!(res.dedup_children_by(|x| x.attribute("id").map(str::to_string)), 2);
+        assert_eq!(res.children().elem_name("a").len(), 1);
+    }
+
+    #[test]
+    fn subtree_shape() {
+        let res = Parser::default()
+            .complete_element("<a><b><c/><c/><c><d/></c></b>text<b/></a>")
+            .unwrap();
+        assert_eq!(res.subtree_stats(), SubtreeStats { depth_below: 3, size: 7, max_breadth: 3 });
+        assert_eq!(res.depth_below(), 3);
+        assert_eq!(res.subtree_size(), 7);
+        assert_eq!(Element::new("leaf").unwrap().max_breadth(), 0);
+    }
+
+    #[test]
+    fn name_normalizers() {
+        let input = r#"<Feed xml:Lang="en"><Entry/></Feed>"#;
+        let res = Parser::default().complete_element(input).unwrap();
+        assert_eq!(res.name(), "feed");
+        assert_eq!(res.attribute("xml:lang"), Some("en"));
+        let parser = Parser { normalize_name: keep_name, ..Default::default() };
+        let res = parser.complete_element(input).unwrap();
+        assert_eq!(res.name(), "Feed");
+        assert_eq!(res.attribute("xml:Lang"), Some("en"));
+        assert!(parser.complete_element("<Feed></feed>").is_err());
+        let events: Vec<_> = reader::Reader::new(&parser, input).map(Result::unwrap).collect();
+        assert_eq!(events[0], reader::Event::Start { name: "Feed".to_string(), attributes: vec![("xml:Lang".to_string(), "en".to_string())] });
+    }
+
+    #[test]
+    fn text_normalizer() {
+        fn fold_e_acute(text: &str) -> Cow<'_, str> {
+            if text.contains("e\u{301}") {
+                Cow::Owned(text.replace("e\u{301}", "\u{e9}"))
+            } else {
+                Cow::Borrowed(text)
+            }
+        }
+        let parser = Parser { normalize_text: Some(fold_e_acute), ..Default::default() };
+        let input = "<a t=\"cafe\u{301}\">cafe\u{301}<![CDATA[e\u{301}]]></a>";
+        let res = parser.complete_element(input).unwrap();
+        assert_eq!(res.attribute("t"), Some("caf\u{e9}"));
+        assert_eq!(res.text(), "caf\u{e9}\u{e9}");
+        assert_eq!(Parser::default().complete_element(input).unwrap().text(), "cafe\u{301}e\u{301}");
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn nfc_text() {
+        let parser = Parser { normalize_text: Some(nfc), ..Default::default() };
+        let res = parser.complete_element("<a t=\"A\u{30a}\">\u{212b}ngstr\u{f6}m cafe\u{301}</a>").unwrap();
+        assert_eq!(res.attribute("t"), Some("\u{c5}"));
+        assert_eq!(res.text(), "\u{c5}ngstr\u{f6}m caf\u{e9}");
+        assert!(matches!(nfc("plain caf\u{e9}"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn unicode_caseless_names() {
+        assert!(NameMatch::IgnoreCase("STRASSE").matches("straße"));
+        assert!(NameMatch::IgnoreCase("ΟΔΟΣ").matches("οδος"));
+        assert!(NameMatch::IgnoreCase("Élément").matches("éLÉMENT"));
+        assert!(!NameMatch::IgnoreAsciiCase("Élément").matches("éLÉMENT"));
+        assert!(!NameMatch::IgnoreCase("item").matches("items"));
+        let res = Parser::default().complete_element("<liste><ÉLÉMENT/><élément/></liste>").unwrap();
+        assert_eq!(res.children().elem_name(NameMatch::IgnoreCase("Élément")).len(), 2);
+    }
+
+    #[test]
+    fn checked_names() {
+        assert_eq!(Element::new("two words"), Err(InvalidName("two words".to_string())));
+        assert!(Element::new("").is_err());
+        assert!(Element::new("1st").is_err());
+        assert!(Element::new("!DOCTYPE").is_err());
+        assert_eq!(Element::new("ns:é-1.x").unwrap().name(), "ns:é-1.x");
+        let name = NameToken::new("_item").unwrap();
+        assert_eq!(Element::named(name).name(), "_item");
+        assert!(Element::new("a").unwrap().try_with_attribute("bad=", "1").is_err());
+        assert_eq!(Element::new("a").unwrap().try_with_attribute("id", "1").unwrap().attribute("id"), Some("1"));
+    }
+
+    #[test]
+    fn name_characters() {
+        const _: () = assert!(name_char('2') && name_char('é'));
+        assert!(!name_char('<') && !name_char(' ') && !name_char('='));
+        let res = Parser::default().complete_element(r#"<h2 data-x.y_z="1"><ns:é/></h2>"#).unwrap();
+        assert_eq!(res.attribute("data-x.y_z"), Some("1"));
+        assert_eq!(res.children().elem_name("ns:é").len(), 1);
+        const _: () = assert!(!name_start_char('2') && !name_start_char('-') && name_start_char('é'));
+        assert!(identifier("1b").is_err() && identifier("-x").is_err() && identifier(".x").is_err());
+        assert_eq!(identifier("_1.b-"), Ok(("", "_1.b-")));
+        assert!(Parser::default().complete_element("<p><1b/></p>").is_err());
+        assert!(Parser::default().complete_element("<p><-x/></p>").is_err());
+        let lenient = Parser { lenient: true, ..Default::default() };
+        assert_eq!(lenient.complete_element("<p>a <1b/> <-x/> b</p>").unwrap().text(), "a <1b/> <-x/> b");
+        assert!(NameToken::new("1b").is_err() && NameToken::new("x1").is_ok());
+    }
+
+    #[test]
+    fn attributes_sorted() {
+        let res = Parser::default().complete_element(r#"<a z="1" b="2" m="3"/>"#).unwrap();
+        assert_eq!(res.attributes_sorted(), vec![("b", "2"), ("m", "3"), ("z", "1")]);
+    }
+
+    #[test]
+    fn name_match() {
+        let res = Parser::default().complete_element("<Root><Item/><item/><items/></Root>").unwrap();
+        assert!(res.is_named("ROOT"));
+        assert!(!res.is_named(NameMatch::Exact("Root")));
+        assert_eq!(vec![&res].elem_name("ITEM").len(), 2);
+        assert_eq!(vec![&res].elem_name(NameMatch::Exact("item")).len(), 2);
+        assert_eq!(vec![&res].elem_name(NameMatch::Exact("Item")).len(), 0);
+        assert_eq!(vec![&res].elem_name(NameMatch::Wildcard("ite?*")).len(), 3);
+        let long = Element::new(&"a".repeat(40)).unwrap();
+        assert!(!long.is_named(NameMatch::Wildcard("*a*a*a*a*a*a*a*a*b")));
+        assert!(long.is_named(NameMatch::Wildcard("*a*a*a*a*a*a*a*a*")));
+        assert!(Element::new("abxbyd").unwrap().is_named(NameMatch::Wildcard("a*b?d")));
+    }
+
+    #[test]
+    fn wildcard_queries() {
+        let res = Parser::default()
+            .complete_element("<soap:header><soap:a/><soap:b><x/></soap:b><other/></soap:header>")
+            .unwrap();
+        assert_eq!(res.children().elem_name("soap:*").len(), 2);
+        assert_eq!(res.children().elem_name("*").len(), 3);
+        assert_eq!(vec![&res].elem_name("*").len(), 1);
+    }
+
+    #[test]
+    fn predicate_queries() {
+        let res = Parser::default()
+            .complete_element(r#"<ul><li class="item-1">one</li><li class="other">two</li><li class="item-22">three</li></ul>"#)
+            .unwrap();
+        let items = vec![&res].elem_where(|e| e.attributes.get("class").is_some_and(|c| c.starts_with("item-")));
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].text(), "three");
+        assert_eq!(res.children().elem_where(|e| e.text().contains('w')).len(), 1);
+    }
+
+    #[test]
+    fn select_iter_paging() {
+        let res = Parser::default()
+            .complete_element("<feed><item>1</item><group><item>2</item><item>3</item></group><item>4</item><item>5</item></feed>")
+            .unwrap();
+        let all: Vec<String> = res.select_iter("item").map(Element::text).collect();
+        assert_eq!(all, vec!["1", "2", "3", "4", "5"]);
+        let page: Vec<String> = res.select_iter("item").skip(1).take(2).map(Element::text).collect();
+        assert_eq!(page, vec!["2", "3"]);
+        let sizes: Vec<usize> = res.select_iter("item").chunks(2).map(|chunk| chunk.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn axes() {
+        let res = Parser::default()
+            .complete_element("<body><section><heading>Intro</heading><p>a</p><heading>Usage</heading><p>b</p><p>c</p></section></body>")
+            .unwrap();
+        let c = res.select_iter("p").last().unwrap();
+        let names: Vec<&str> = res.ancestors_of(c).iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["section", "body"]);
+        assert_eq!(res.parent_of(c).unwrap().name, "section");
+        let heading = res.preceding_siblings_of(c).elem_name("heading").first().unwrap();
+        assert_eq!(heading.text(), "Usage");
+        let a = res.select_iter("p").next().unwrap();
+        assert_eq!(res.following_siblings_of(a).len(), 3);
+        assert!(res.parent_of(&res).is_none());
+    }
+
+    #[test]
+    fn namespaced_attributes() {
+        let doc = Parser::default().complete_element(concat!(
+            r#"<root xmlns:i="http://www.w3.org/2001/XMLSchema-instance" xmlns:l="http://www.w3.org/1999/xlink">"#,
+            r##"<a i:type="T" l:href="#x" xml:lang="en" href="plain"/>"##,
+            r#"<b xmlns:i="urn:other" i:type="U"/>"#,
+            r#"</root>"#,
+        )).unwrap();
+        let a = doc.select_iter("a").next().unwrap();
+        assert_eq!(a.attr_ns_in(&doc, ns::XSI, "type"), Some("T"));
+        assert_eq!(a.attr_ns_in(&doc, ns::XLINK, "href"), Some("#x"));
+        assert_eq!(a.attr_ns_in(&doc, "", "href"), Some("plain"));
+        assert_eq!(a.attr_ns(ns::XML, "lang"), Some("en"));
+        assert_eq!(a.attr_ns(ns::XSI, "type"), None);
+        let b = doc.select_iter("b").next().unwrap();
+        assert_eq!(b.attr_ns_in(&doc, ns::XSI, "type"), None);
+        assert_eq!(b.attr_ns("urn:other", "type"), Some("U"));
+    }
+
+    #[test]
+    fn breadcrumb() {
+        let doc = Parser::default()
+            .complete_document("<?xml version=\"1.0\"?><html><body><div id=\"main\"><p/><i/><p/><p>x</p></div></body></html>")
+            .unwrap();
+        let p = doc.root().select_iter("p").last().unwrap();
+        assert_eq!(p.breadcrumb(&doc).unwrap(), "html > body > div#main > p[3]");
+        let i = doc.root().select_iter("i").next().unwrap();
+        assert_eq!(i.breadcrumb(doc.root()).unwrap(), "html > body > div#main > i");
+        assert_eq!(doc.root().breadcrumb(&doc).unwrap(), "html");
+        assert!(p.clone().breadcrumb(&doc).is_none());
+    }
+
+    #[test]
+    fn position_predicates() {
+        let res = Parser::default()
+            .complete_element("<table><tr><td>a</td><td>b</td><td>c</td></tr><tr><td>d</td><td>e</td></tr></table>")
+            .unwrap();
+        let rows = vec![&res].elem_name("tr");
+        let texts = |v: Vec<&Element>| v.iter().map(|e| e.text()).collect::<Vec<_>>();
+        assert_eq!(texts(rows.child_at("td", Position::At(2))), vec!["b", "e"]);
+        assert_eq!(texts(rows.child_at("td", Position::Last)), vec!["c", "e"]);
+        assert_eq!(texts(rows.child_at("td", Position::GreaterThan(2))), vec!["c"]);
+        assert_eq!(texts(res.children().child_at("td", Position::LessThan(2))), vec!["a", "d"]);
+    }
+
+    #[test]
+    fn aggregates() {
+        let res = Parser::default()
+            .complete_element(r#"<invoice><line price="2.5"><qty>3</qty></line><line price="4"><qty>12</qty></line><line><qty>n/a</qty></line></invoice>"#)
+            .unwrap();
+        let lines = vec![&res].elem_name("line");
+        assert_eq!(vec![&res].count("qty"), 3);
+        assert_eq!(lines.sum_attr("price"), 6.5);
+        let quantities = lines.elem_name("qty");
+        assert_eq!(quantities.max_text_as::<i64>(), Some(12));
+        assert_eq!(quantities.min_text_as::<i64>(), Some(3));
+        assert_eq!(Vec::<&Element>::new().max_text_as::<i64>(), None);
+    }
+
+    #[test]
+    fn grouping() {
+        let res = Parser::default()
+            .complete_element(r#"<shop><item category="fruit"/><item category="veg"/><item category="fruit"/><item/><note/></shop>"#)
+            .unwrap();
+        let by_category = vec![&res].elem_name("item").group_by_attr("category");
+        assert_eq!(by_category["fruit"].len(), 2);
+        assert_eq!(by_category["veg"].len(), 1);
+        assert_eq!(by_category.len(), 2);
+        let by_name = res.children().group_by_name();
+        assert_eq!(by_name["item"].len(), 4);
+        assert_eq!(by_name["note"].len(), 1);
+    }
+
+    #[test]
+    fn to_table() {
+        let res = Parser::default()
+            .complete_element(r#"<report><row id="1"><name>Ann</name></row><row id="2"><name>Bo</name><age>7</age></row></report>"#)
+            .unwrap();
+        assert_eq!(res.to_table(&["id", "name", "age"]), vec![
+            vec![Some("1".to_string()), Some("Ann".to_string()), None],
+            vec![Some("2".to_string()), Some("Bo".to_string()), Some("7".to_string())],
+        ]);
+    }
+
+    #[cfg(feature = "write")]
+    #[test]
+    fn xml_strings() {
+        let parser = Parser::default();
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?><note to="a&amp;b"><!--c--><body>1 &lt; 2</body><?pi x?></note>"#;
+        let document = parser.complete_document(input).unwrap();
+        assert_eq!(document.to_xml_string().unwrap(), input);
+        assert_eq!(parser.complete_document(&document.to_xml_string().unwrap()).unwrap().root(), document.root());
+        assert_eq!(document.root().to_xml_string().unwrap(), input["<?xml version=\"1.0\" encoding=\"UTF-8\"?>".len()..]);
+        let mut out = vec![];
+        Element::new("empty").unwrap().write_xml(&mut out).unwrap();
+        assert_eq!(out, b"<empty/>");
+        assert!(Element::new("a").unwrap().with_child(Node::Comment("--".to_string())).to_xml_string().is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parser = Parser::default();
+        let at = |offset, line, column| Location { offset, line, column };
+        let error = parser.complete_element("<a><b></c></a>").unwrap_err();
+        assert_eq!(error, Error::MismatchedCloseTag { expected: "b".to_string(), found: "c".to_string(), at: at(6, 1, 7) });
+        assert_eq!(error.to_string(), "</c> does not close <b> at line 1, column 7");
+        assert_eq!(parser.complete_element("<a><b x='1' y='2' X='3'/></a>"), Err(Error::DuplicateAttribute { name: "x".to_string(), at: at(3, 1, 4) }));
+        assert_eq!(parser.complete_element("<a><b>"), Err(Error::UnexpectedEof { at: at(6, 1, 7) }));
+        assert_eq!(parser.complete_element("<a/> <b/>"), Err(Error::TrailingContent { at: at(5, 1, 6) }));
+        assert_eq!(parser.complete_document("\n<a/>").unwrap_err(), Error::BadDeclaration { at: at(1, 2, 1) });
+        let limited = Parser { max_text_len: Some(2), ..Default::default() };
+        assert!(matches!(limited.complete_element("<a>abc</a>"), Err(Error::TooLarge { what: "text", .. })));
+        let error = parser.complete_document_bytes(b"<?xml version=\"1.0\"?><p>\xe9</p>").unwrap_err();
+        assert!(matches!(error, Error::Encoding(_)));
+        assert_eq!((error.location(), error.offset()), (None, 24));
+    }
+
+    #[test]
+    fn error_locations() {
+        let input = "<?xml version=\"1.0\"?>\r\n<feed>\n  <entry>\n    <title>café</title>\n    <p>é<b></i>\n</feed>";
+        let error = Parser::default().complete_document(input).unwrap_err();
+        assert_eq!(error.location(), Some(Location { offset: 77, line: 5, column: 12 }));
+        assert_eq!(&input[error.offset()..error.offset() + 4], "</i>");
+        assert_eq!(error.to_string(), "</i> does not close <b> at line 5, column 12");
+        let error = Parser::default().complete_element("<a>\n<b>\n").unwrap_err();
+        assert_eq!(error.location(), Some(Location { offset: 8, line: 3, column: 1 }));
+        assert_eq!(Location::new("a\nb", 99), Location { offset: 3, line: 2, column: 2 });
+        assert_eq!(Location::new("\u{e9}t\u{e9}", 4), Location { offset: 3, line: 1, column: 3 });
+    }
 }
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::{ns, Element, Node};
+
+/// Attributes holding references: `href` and XLink's `href` (under any prefix bound to
+/// `ns::XLINK`, reported as `xlink:href`), DITA's `conref` and DocBook's `linkend`, which is a
+/// bare id rather than a URI.
+const LINK_ATTRIBUTES: [&str; 4] = ["conref", "href", "linkend", "xlink:href"];
+
+/// An element's identifier: `xml:id`, else a plain `id` as in DITA and HTML.
+pub fn element_id(element: &Element) -> Option<&str> {
+    element.attr_ns(ns::XML, "id").or_else(|| element.attribute("id"))
+}
+
+/// Every element at or below `root` that has an identifier, by identifier; the first in
+/// document order wins when one is repeated.
+pub fn ids(root: &Element) -> HashMap<&str, &Element> {
+    let mut ids = HashMap::new();
+    let mut stack = vec![root];
+    while let Some(element) = stack.pop() {
+        if let Some(id) = element_id(element) {
+            ids.entry(id).or_insert(element);
+        }
+        stack.extend(element.children.iter().rev().filter_map(Node::as_element));
+    }
+    ids
+}
+
+/// Where a fragment identifier, the part of a reference after `#`, points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fragment {
+    /// `#intro`, or `#xpointer(id('intro'))`.
+    Id(String),
+    /// `#element(intro/2/1)` or `#element(/1/3)`: from the element with the id, or from the
+    /// document, down through the nth child element at each step, counting from 1.
+    ChildSequence { id: Option<String>, steps: Vec<usize> },
+    /// DITA's `#topic/element`: the element with the second id inside the one with the first.
+    Nested(String, String),
+}
+
+impl Fragment {
+    /// Reads `#fragment` or a bare fragment. `None` for a reference into another document, or
+    /// an XPointer other than those above.
+    pub fn parse(reference: &str) -> Option<Fragment> {
+        let fragment = match reference.split_once('#') {
+            Some(("", fragment)) => fragment,
+            Some(_) => return None,
+            None => reference,
+        };
+        if let Some(pointer) = fragment.strip_prefix("xpointer(").and_then(|rest| rest.strip_suffix(')')) {
+            let id = pointer.trim().strip_prefix("id(")?.strip_suffix(')')?.trim();
+            let quote = id.chars().next().filter(|&quote| quote == '\'' || quote == '"')?;
+            return Some(Fragment::Id(id.strip_prefix(quote)?.strip_suffix(quote)?.to_string()));
+        }
+        if let Some(sequence) = fragment.strip_prefix("element(").and_then(|rest| rest.strip_suffix(')')) {
+            let mut parts = sequence.split('/');
+            let id = parts.next().filter(|id| !id.is_empty()).map(str::to_string);
+            let steps = parts.map(|step| step.parse().ok().filter(|&n| n > 0)).collect::<Option<Vec<usize>>>()?;
+            return Some(Fragment::ChildSequence { id, steps });
+        }
+        match fragment.split_once('/') {
+            Some((outer, inner)) if !outer.is_empty() && !inner.is_empty() => Some(Fragment::Nested(outer.to_string(), inner.to_string())),
+            Some(_) => None,
+            None if fragment.is_empty() => None,
+            None => Some(Fragment::Id(fragment.to_string())),
+        }
+    }
+
+    /// The element this points to below `root`, given `ids(root)`.
+    fn resolve_in<'a>(&self, root: &'a Element, by_id: &HashMap<&str, &'a Element>) -> Option<&'a Element> {
+        match self {
+            Fragment::Id(id) => by_id.get(id.as_str()).copied(),
+            Fragment::ChildSequence { id, steps } => {
+                let (mut element, steps) = match id {
+                    Some(id) => (*by_id.get(id.as_str())?, &steps[..]),
+                    // The document's only child element is the root.
+                    None => match steps.split_first()? {
+                        (1, rest) => (root, rest),
+                        _ => return None,
+                    },
+                };
+                for &step in steps {
+                    element = element.children.iter().filter_map(Node::as_element).nth(step - 1)?;
+                }
+                Some(element)
+            }
+            Fragment::Nested(outer, inner) => ids(by_id.get(outer.as_str())?).get(inner.as_str()).copied(),
+        }
+    }
+
+    pub fn resolve<'a>(&self, root: &'a Element) -> Option<&'a Element> {
+        self.resolve_in(root, &ids(root))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub element: &'a Element,
+    pub attribute: &'static str,
+    pub reference: &'a str,
+    /// `None` for a broken link.
+    pub target: Option<&'a Element>,
+}
+
+/// Every link at or below `root` that points into the same document, in document order, with
+/// what it points to. References to other documents are left out.
+pub fn links(root: &Element) -> Vec<Link<'_>> {
+    let ids = ids(root);
+    let mut links = vec![];
+    // The element being visited and its ancestors, root first, for namespace declarations.
+    let mut chain: Vec<&Element> = vec![];
+    let mut stack = vec![(root, 0)];
+    while let Some((element, depth)) = stack.pop() {
+        chain.truncate(depth);
+        chain.push(element);
+        stack.extend(element.children.iter().rev().filter_map(Node::as_element).map(|child| (child, depth + 1)));
+        for attribute in LINK_ATTRIBUTES {
+            let value = match attribute {
+                "xlink:href" => xlink_href(&chain),
+                _ => element.attribute(attribute),
+            };
+            let reference = match value {
+                Some(reference) if attribute == "linkend" || reference.starts_with('#') => reference,
+                _ => continue,
+            };
+            let target = Fragment::parse(reference).and_then(|fragment| fragment.resolve_in(root, &ids));
+            links.push(Link { element, attribute, reference, target });
+        }
+    }
+    links
+}
+
+/// The XLink `href` of the last element of `chain`, which runs down from the root.
+fn xlink_href<'a>(chain: &[&'a Element]) -> Option<&'a str> {
+    let element = chain.last()?;
+    if !element.attributes.keys().any(|name| name.ends_with(":href")) {
+        return None;
+    }
+    let scope: Vec<&Element> = chain.iter().rev().copied().collect();
+    element.attr_ns_scoped(&scope, ns::XLINK, "href")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::links::*;
+
+    #[test]
+    fn fragments() {
+        let document = Parser::default().complete_document(concat!(
+            r#"<?xml version="1.0"?><book><chapter xml:id="intro"><para>a</para><para id="p2">b</para></chapter>"#,
+            r#"<topic id="t"><p id="p2">c</p></topic>"#,
+            r##"<xref linkend="intro"/><a href="#t/p2"/><a href="other.xml#intro"/><a href="#gone"/></book>"##,
+        )).unwrap();
+        let text = |reference| document.resolve_fragment(reference).map(Element::text);
+        assert_eq!(document.resolve_fragment("#intro").map(Element::name), Some("chapter"));
+        assert_eq!(text("#p2"), Some("b".to_string()));
+        assert_eq!(text("#t/p2"), Some("c".to_string()));
+        assert_eq!(text("#xpointer(id('p2'))"), Some("b".to_string()));
+        assert_eq!(text("#element(intro/1)"), Some("a".to_string()));
+        assert_eq!(text("#element(/1/2/1)"), Some("c".to_string()));
+        assert_eq!(text("#element(/2)"), None);
+        assert_eq!(text("other.xml#intro"), None);
+
+        let found: Vec<(&str, Option<&str>)> = links(document.root()).iter()
+            .map(|link| (link.reference, link.target.map(Element::name)))
+            .collect();
+        assert_eq!(found, [("intro", Some("chapter")), ("#t/p2", Some("p")), ("#gone", None)]);
+    }
+
+    #[test]
+    fn xlink_links() {
+        let svg = Parser::default().complete_element(concat!(
+            r##"<svg xmlns:l="http://www.w3.org/1999/xlink"><g id="shape"/><g><use l:href="#shape"/></g>"##,
+            r##"<use xlink:href="#shape"/><x xmlns:l="urn:other"><use l:href="#shape"/></x></svg>"##,
+        )).unwrap();
+        let found: Vec<(&str, &str, Option<&str>)> = links(&svg).iter()
+            .map(|link| (link.attribute, link.element.name(), link.target.map(Element::name)))
+            .collect();
+        assert_eq!(found, [("xlink:href", "use", Some("g"))]);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::Element;
+
+/// Bound to the `xml` prefix in every document, as for `xml:lang` and `xml:id`.
+pub const XML: &str = "http://www.w3.org/XML/1998/namespace";
+pub const XMLNS: &str = "http://www.w3.org/2000/xmlns/";
+/// XML Schema instance attributes such as `xsi:type` and `xsi:schemaLocation`.
+pub const XSI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+pub const XLINK: &str = "http://www.w3.org/1999/xlink";
+
+/// Prefixes bound to `uri` in `scope`, an element followed by its ancestors, nearest first.
+/// A prefix redeclared closer to the element hides its outer declarations.
+pub(crate) fn prefixes_for<'a>(scope: &[&'a Element], uri: &str) -> Vec<&'a str> {
+    let mut seen: Vec<&str> = vec!["xml", "xmlns"];
+    let mut prefixes = vec![];
+    match uri {
+        XML => prefixes.push("xml"),
+        XMLNS => prefixes.push("xmlns"),
+        _ => {}
+    }
+    for element in scope {
+        for (name, value) in element.attributes() {
+            if let Some(prefix) = name.strip_prefix("xmlns:") {
+                if !seen.contains(&prefix) {
+                    seen.push(prefix);
+                    if value == uri {
+                        prefixes.push(prefix);
+                    }
+                }
+            }
+        }
+    }
+    prefixes
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use crate::{Element, Node};
+
+/// One difference between two revisions of a document, located by element path such as
+/// `/config/server[3]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added { path: String },
+    Removed { path: String },
+    AttributeAdded { path: String, name: String, value: String },
+    AttributeRemoved { path: String, name: String, value: String },
+    AttributeChanged { path: String, name: String, old: String, new: String },
+    /// The element's own text, trimmed, not counting its descendants'.
+    TextChanged { path: String, old: String, new: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path } => write!(f, "added {}", path),
+            Change::Removed { path } => write!(f, "removed {}", path),
+            Change::AttributeAdded { path, name, value } => write!(f, "added attribute {} = {:?} to {}", name, value, path),
+            Change::AttributeRemoved { path, name, value } => write!(f, "removed attribute {} = {:?} from {}", name, value, path),
+            Change::AttributeChanged { path, name, old, new } => {
+                write!(f, "changed attribute {} of {} from {:?} to {:?}", name, path, old, new)
+            }
+            Change::TextChanged { path, old, new } => write!(f, "changed text of {} from {:?} to {:?}", path, old, new),
+        }
+    }
+}
+
+fn own_text(element: &Element) -> String {
+    let text: String = element.children.iter().filter_map(Node::as_cdata).map(String::as_str).collect();
+    text.trim().to_string()
+}
+
+/// A summary of what changed from `old` to `new`, for change logs between config revisions
+/// rather than for patching: in document order, each element's attributes by name, then its
+/// text, then its children.
+///
+/// Child elements are paired by name and position among their same-name siblings, so the
+/// second `server` is compared with the second `server`; a path step gets an index when
+/// either revision has more than one element of that name there. Whitespace-only text changes
+/// are ignored, as are comments and processing instructions.
+pub fn diff(old: &Element, new: &Element) -> Vec<Change> {
+    let mut changes = vec![];
+    let path = format!("/{}", new.name);
+    if old.name == new.name {
+        compare(old, new, &path, &mut changes);
+    } else {
+        changes.push(Change::Removed { path: format!("/{}", old.name) });
+        changes.push(Change::Added { path });
+    }
+    changes
+}
+
+fn compare(old: &Element, new: &Element, path: &str, changes: &mut Vec<Change>) {
+    let mut names: Vec<&str> = old.attributes.keys().chain(new.attributes.keys()).map(String::as_str).collect();
+    names.sort_unstable();
+    names.dedup();
+    for name in names {
+        let (path, name) = (path.to_string(), name.to_string());
+        match (old.attribute(&name), new.attribute(&name)) {
+            (None, Some(value)) => changes.push(Change::AttributeAdded { path, name, value: value.to_string() }),
+            (Some(value), None) => changes.push(Change::AttributeRemoved { path, name, value: value.to_string() }),
+            (Some(a), Some(b)) if a != b => changes.push(Change::AttributeChanged { path, name, old: a.to_string(), new: b.to_string() }),
+            _ => {}
+        }
+    }
+    let (old_text, new_text) = (own_text(old), own_text(new));
+    if old_text != new_text {
+        changes.push(Change::TextChanged { path: path.to_string(), old: old_text, new: new_text });
+    }
+
+    let old_children: Vec<&Element> = old.children.iter().filter_map(Node::as_element).collect();
+    let new_children: Vec<&Element> = new.children.iter().filter_map(Node::as_element).collect();
+    let count = |children: &[&Element], name: &str| children.iter().filter(|x| x.name == name).count();
+    // Old children no new one pairs with are reported after the new ones, in their own order.
+    let mut paired = vec![false; old_children.len()];
+    let step = |name: &str, index: usize| match count(&old_children, name).max(count(&new_children, name)) {
+        1 => format!("{}/{}", path, name),
+        _ => format!("{}/{}[{}]", path, name, index + 1),
+    };
+    for (position, child) in new_children.iter().enumerate() {
+        let index = new_children[..position].iter().filter(|x| x.name == child.name).count();
+        let child_path = step(&child.name, index);
+        match old_children.iter().enumerate().filter(|(_, x)| x.name == child.name).nth(index) {
+            Some((at, before)) => {
+                paired[at] = true;
+                compare(before, child, &child_path, changes);
+            }
+            None => changes.push(Change::Added { path: child_path }),
+        }
+    }
+    for (position, child) in old_children.iter().enumerate().filter(|&(at, _)| !paired[at]) {
+        let index = old_children[..position].iter().filter(|x| x.name == child.name).count();
+        changes.push(Change::Removed { path: step(&child.name, index) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::outline::*;
+
+    #[test]
+    fn config_changes() {
+        let parser = Parser::default();
+        let old = parser.complete_element(
+            r#"<config><name>web</name><server port="80"/><server port="81" tls="off"/><cache/></config>"#,
+        ).unwrap();
+        let new = parser.complete_element(
+            r#"<config><name>web-1</name><server port="8080"/><server port="81"/><server port="82" /></config>"#,
+        ).unwrap();
+        let changes: Vec<String> = diff(&old, &new).iter().map(Change::to_string).collect();
+        assert_eq!(changes, [
+            "changed text of /config/name from \"web\" to \"web-1\"",
+            "changed attribute port of /config/server[1] from \"80\" to \"8080\"",
+            "removed attribute tls = \"off\" from /config/server[2]",
+            "added /config/server[3]",
+            "removed /config/cache",
+        ]);
+        assert!(diff(&new, &new).is_empty());
+    }
+}
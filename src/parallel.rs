@@ -0,0 +1,84 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{Element, Error, Node, Parser};
+
+/// Lines each worker takes at a time: enough to keep the shared counter cold, few enough that
+/// uneven records still spread over every thread.
+const BATCH: usize = 256;
+
+/// The records of one batch, by the batch's position in the input.
+type Parsed = (usize, Result<Vec<Element>, RecordError>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordError {
+    /// 1-based line number of the first record that is not a complete element.
+    pub line: usize,
+    pub error: Error,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// Parses a line-oriented record file, each non-blank line one complete element, on `threads`
+/// worker threads (0 for one per core) and appends the records to `root` in input order.
+pub fn build_lines(parser: &Parser, input: &str, mut root: Element, threads: usize) -> Result<Element, RecordError> {
+    let lines: Vec<(usize, &str)> = input.lines().enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, usize::from),
+        threads => threads,
+    };
+    let batches: Vec<&[(usize, &str)]> = lines.chunks(BATCH).collect();
+    let next = AtomicUsize::new(0);
+    let done: Mutex<Vec<Parsed>> = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for _ in 0..threads.min(batches.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(batch) = batches.get(index) else { break };
+                let records = batch.iter()
+                    .map(|&(line, text)| parser.complete_element(text).map_err(|error| RecordError { line, error }))
+                    .collect();
+                done.lock().expect("no worker panics while holding the lock").push((index, records));
+            });
+        }
+    });
+    let mut done = done.into_inner().expect("workers have finished");
+    done.sort_by_key(|(index, _)| *index);
+    for (_, records) in done {
+        root.children.extend(records?.into_iter().map(Node::Element));
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::parallel::*;
+
+    #[test]
+    fn parallel_lines() {
+        let parser = Parser::default();
+        let input: String = (0..1000).map(|i| format!("<r n=\"{}\"><v>{}</v></r>\n{}", i, i * 2, if i % 10 == 0 { "\n" } else { "" })).collect();
+        let root = build_lines(&parser, &input, Element::new("records").unwrap(), 4).unwrap();
+        let numbers: Vec<usize> = root.children().iter()
+            .filter_map(|node| node.as_element()?.attribute("n")?.parse().ok())
+            .collect();
+        assert_eq!(numbers, (0..1000).collect::<Vec<_>>());
+        assert_eq!(build_lines(&parser, &input, Element::new("records").unwrap(), 0).unwrap(), root);
+
+        let broken = format!("{}<r>\n<s/>\n<r><oops></r>\n", input);
+        let error = build_lines(&parser, &broken, Element::new("records").unwrap(), 3).unwrap_err();
+        assert_eq!(error.to_string(), "line 1101: unexpected end of input at line 1, column 4");
+    }
+}
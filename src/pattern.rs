@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use crate::{Element, NameMatch, Node};
+
+#[derive(Debug, Clone)]
+pub enum ValueMatch {
+    Any,
+    Exact(String),
+    Contains(String),
+    Predicate(fn(&str) -> bool),
+}
+
+impl ValueMatch {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            ValueMatch::Any => true,
+            ValueMatch::Exact(expected) => value == expected,
+            ValueMatch::Contains(needle) => value.contains(needle.as_str()),
+            ValueMatch::Predicate(predicate) => predicate(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Captures<'a> {
+    pub elements: HashMap<String, &'a Element>,
+    pub values: HashMap<String, String>,
+}
+
+impl<'a> Captures<'a> {
+    fn bind_element(&mut self, name: &str, element: &'a Element) -> bool {
+        match self.elements.get(name) {
+            Some(bound) => std::ptr::eq(*bound, element),
+            None => {
+                self.elements.insert(name.to_string(), element);
+                true
+            }
+        }
+    }
+
+    fn bind_value(&mut self, name: &str, value: &str) -> bool {
+        match self.values.get(name) {
+            Some(bound) => bound == value,
+            None => {
+                self.values.insert(name.to_string(), value.to_string());
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Check {
+    matcher: ValueMatch,
+    capture: Option<String>,
+}
+
+/// Template describing the shape of a subtree. Child patterns must match distinct child
+/// elements in order, though other children may appear in between. A capture name used more
+/// than once must bind the same element or value everywhere it appears.
+#[derive(Debug, Clone, Default)]
+pub struct Pattern {
+    name: Option<String>,
+    attributes: Vec<(String, Check)>,
+    text: Vec<Check>,
+    children: Vec<Pattern>,
+    capture: Option<String>,
+}
+
+impl Pattern {
+    pub fn any() -> Pattern {
+        Pattern::default()
+    }
+    pub fn element(name: &str) -> Pattern {
+        Pattern {
+            name: Some(name.to_string()),
+            ..Pattern::default()
+        }
+    }
+    pub fn attr(mut self, name: &str, matcher: ValueMatch) -> Pattern {
+        self.attributes.push((name.to_string(), Check { matcher, capture: None }));
+        self
+    }
+    pub fn capture_attr(mut self, name: &str, capture: &str) -> Pattern {
+        self.attributes.push((name.to_string(), Check { matcher: ValueMatch::Any, capture: Some(capture.to_string()) }));
+        self
+    }
+    /// Matches against the element's trimmed `text()`.
+    pub fn text(mut self, matcher: ValueMatch) -> Pattern {
+        self.text.push(Check { matcher, capture: None });
+        self
+    }
+    pub fn capture_text(mut self, capture: &str) -> Pattern {
+        self.text.push(Check { matcher: ValueMatch::Any, capture: Some(capture.to_string()) });
+        self
+    }
+    pub fn child(mut self, child: Pattern) -> Pattern {
+        self.children.push(child);
+        self
+    }
+    pub fn capture(mut self, name: &str) -> Pattern {
+        self.capture = Some(name.to_string());
+        self
+    }
+
+    pub fn matches<'a>(&self, element: &'a Element) -> Option<Captures<'a>> {
+        let mut captures = Captures::default();
+        if self.unify(element, &mut captures) {
+            Some(captures)
+        } else {
+            None
+        }
+    }
+
+    /// Every match at or below `root`, in document order.
+    pub fn find_all<'a>(&self, root: &'a Element) -> Vec<Captures<'a>> {
+        let mut v = vec![];
+        if let Some(captures) = self.matches(root) {
+            v.push(captures);
+        }
+        for child in root.children.iter().filter_map(Node::as_element) {
+            v.append(&mut self.find_all(child));
+        }
+        v
+    }
+
+    /// How `find_all` evaluates this pattern, one check per line in evaluation order, so slow
+    /// patterns can be reshaped: cheap checks that reject early belong near the top.
+    pub fn explain(&self) -> String {
+        let mut out = "scan: every element at or below the root, in document order (no index)\n".to_string();
+        self.explain_into(1, &mut out);
+        out
+    }
+
+    fn explain_into(&self, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        let describe = |check: &Check| {
+            let matcher = match &check.matcher {
+                ValueMatch::Any => "any value".to_string(),
+                ValueMatch::Exact(value) => format!("equals {:?}", value),
+                ValueMatch::Contains(value) => format!("contains {:?}", value),
+                ValueMatch::Predicate(_) => "predicate".to_string(),
+            };
+            match &check.capture {
+                Some(capture) => format!("{}, capture ${}", matcher, capture),
+                None => matcher,
+            }
+        };
+        match &self.name {
+            Some(name) => out.push_str(&format!("{}name: {:?}, ignoring ASCII case\n", indent, name)),
+            None => out.push_str(&format!("{}name: any\n", indent)),
+        }
+        for (name, check) in &self.attributes {
+            out.push_str(&format!("{}attribute {}: {}\n", indent, name, describe(check)));
+        }
+        for check in &self.text {
+            out.push_str(&format!("{}text (whole subtree, trimmed): {}\n", indent, describe(check)));
+        }
+        if let Some(capture) = &self.capture {
+            out.push_str(&format!("{}capture element ${}\n", indent, capture));
+        }
+        if !self.children.is_empty() {
+            out.push_str(&format!("{}children: {} patterns matched in order against child elements, backtracking\n", indent, self.children.len()));
+            for child in &self.children {
+                child.explain_into(depth + 1, out);
+            }
+        }
+    }
+
+    fn unify<'a>(&self, element: &'a Element, captures: &mut Captures<'a>) -> bool {
+        if let Some(name) = &self.name {
+            if !element.is_named(NameMatch::IgnoreAsciiCase(name)) {
+                return false;
+            }
+        }
+        for (name, check) in &self.attributes {
+            match element.attributes.get(name) {
+                Some(value) if check.matcher.matches(value) => {
+                    if let Some(capture) = &check.capture {
+                        if !captures.bind_value(capture, value) {
+                            return false;
+                        }
+                    }
+                }
+                _ => return false,
+            }
+        }
+        if !self.text.is_empty() {
+            let text = element.text();
+            let text = text.trim();
+            for check in &self.text {
+                if !check.matcher.matches(text) {
+                    return false;
+                }
+                if let Some(capture) = &check.capture {
+                    if !captures.bind_value(capture, text) {
+                        return false;
+                    }
+                }
+            }
+        }
+        if let Some(capture) = &self.capture {
+            if !captures.bind_element(capture, element) {
+                return false;
+            }
+        }
+        let children: Vec<&'a Element> = element.children.iter().filter_map(Node::as_element).collect();
+        unify_children(&self.children, &children, captures)
+    }
+}
+
+fn unify_children<'a>(patterns: &[Pattern], children: &[&'a Element], captures: &mut Captures<'a>) -> bool {
+    let (pattern, rest) = match patterns.split_first() {
+        Some(split) => split,
+        None => return true,
+    };
+    for (index, child) in children.iter().enumerate() {
+        let mut attempt = captures.clone();
+        if pattern.unify(child, &mut attempt) && unify_children(rest, &children[index + 1..], &mut attempt) {
+            *captures = attempt;
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::pattern::*;
+
+    #[test]
+    fn structural_patterns() {
+        let res = Parser::default().complete_element(r#"<orders>
+            <order id="1"><customer ref="c1"/><total>10</total></order>
+            <order id="2"><total>5</total></order>
+            <order id="3"><customer ref="c3"/><note/><total>7</total></order>
+        </orders>"#).unwrap();
+        let pattern = Pattern::element("order")
+            .capture_attr("id", "id")
+            .child(Pattern::element("customer").capture_attr("ref", "customer"))
+            .child(Pattern::element("total").capture_text("total").capture("total_element"));
+        let found = pattern.find_all(&res);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].values["id"], "1");
+        assert_eq!(found[1].values["customer"], "c3");
+        assert_eq!(found[1].values["total"], "7");
+        assert_eq!(found[1].elements["total_element"].text(), "7");
+    }
+
+    #[test]
+    fn explain() {
+        let pattern = Pattern::element("order")
+            .attr("status", ValueMatch::Exact("open".to_string()))
+            .child(Pattern::any().capture_text("total").capture("line"));
+        assert_eq!(pattern.explain(), "\
+scan: every element at or below the root, in document order (no index)
+  name: \"order\", ignoring ASCII case
+  attribute status: equals \"open\"
+  children: 1 patterns matched in order against child elements, backtracking
+    name: any
+    text (whole subtree, trimmed): any value, capture $total
+    capture element $line
+");
+    }
+
+    #[test]
+    fn repeated_captures_unify() {
+        let res = Parser::default()
+            .complete_element(r#"<pair><a v="x"/><b v="y"/><b v="x"/></pair>"#)
+            .unwrap();
+        let same = Pattern::element("pair")
+            .child(Pattern::element("a").capture_attr("v", "v"))
+            .child(Pattern::element("b").capture_attr("v", "v"));
+        assert_eq!(same.matches(&res).unwrap().values["v"], "x");
+        let impossible = Pattern::any()
+            .child(Pattern::element("a").attr("v", ValueMatch::Exact("y".to_string())));
+        assert!(impossible.matches(&res).is_none());
+    }
+}
@@ -0,0 +1,38 @@
+pub use crate::borrowed::{FromBorrowed, FromBorrowedValue};
+pub use crate::pattern::Pattern;
+#[cfg(feature = "regex")]
+pub use crate::regex_query::RegexQuery;
+pub use crate::tree::{XmlElement, XmlNode};
+pub use crate::typed::{DeError, ExpectError, FromElement, FromValue};
+#[cfg(feature = "write")]
+pub use crate::writer::Writer;
+pub use crate::{tag_names, xml_struct};
+pub use crate::{Aggregate, Document, Element, Grouping, NameMatch, Node, Parser, Position, QuerySupport, TagName, WhitespacePolicy};
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    xml_struct! {
+        struct Server {
+            #[xml(attr)]
+            port: u16,
+            host: String,
+        }
+    }
+
+    tag_names! { SERVER = "server" }
+
+    #[test]
+    fn one_import() {
+        let config = Parser::default()
+            .complete_element(r#"<config><server port="80"><host>a</host></server><server port="81"><host>b</host></server></config>"#)
+            .unwrap();
+        let servers = vec![&config].elem_name(SERVER);
+        assert_eq!(servers.sum_attr("port"), 161.0);
+        assert_eq!(servers.group_by_attr("port").len(), 2);
+        let last = Server::from_element(servers.last().unwrap()).unwrap();
+        assert_eq!((last.port, last.host.as_str()), (81, "b"));
+        assert_eq!(Pattern::element("server").find_all(&config).len(), 2);
+    }
+}
@@ -0,0 +1,483 @@
+use std::fmt;
+
+use nom::bytes::complete::{tag, take_while};
+use nom::multi::many0;
+
+use crate::foreign::Foreign;
+use crate::{attribute, comment, declaration, doctype_body, identifier, processing_instruction, stray_angle_bracket, Element, NameMatch, Node, Parser};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Declaration { version: i32, encoding: Option<String> },
+    Doctype(String),
+    Start { name: String, attributes: Vec<(String, String)> },
+    End { name: String },
+    Text(String),
+    CData(String),
+    Comment(String),
+    ProcessingInstruction { target: String, data: String },
+    /// An element registered in `Parser::foreign`, whole, in place of its `Start` and `End`
+    /// and everything between.
+    Foreign(Foreign),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadError {
+    pub offset: usize,
+    pub kind: ReadErrorKind,
+    pub message: &'static str,
+}
+
+/// What went wrong, for matching on; `ReadError::message` says it in words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadErrorKind {
+    UnexpectedEof,
+    MismatchedEndTag,
+    DuplicateAttribute,
+    BadDeclaration,
+    /// Longer than a `Parser` limit allows; says which of input, attribute value or text.
+    TooLarge { what: &'static str },
+    InvalidCheckpoint,
+    Malformed,
+}
+
+impl ReadError {
+    pub(crate) fn new(offset: usize, kind: ReadErrorKind, message: &'static str) -> ReadError {
+        ReadError { offset, kind, message }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+/// Where a `Reader` stands between two events, to resume a long job after a restart with
+/// `Reader::resume`. Saves and loads as text through `Display` and `FromStr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub offset: usize,
+    /// Names of the open elements, outermost first.
+    pub open: Vec<String>,
+    /// The `End` still owed for a self-closing or `allow_no_close` element just read.
+    pub pending_end: Option<String>,
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.offset)?;
+        for name in &self.open {
+            write!(f, " {}", name)?;
+        }
+        if let Some(name) = &self.pending_end {
+            write!(f, " /{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Checkpoint {
+    type Err = ReadError;
+
+    fn from_str(text: &str) -> Result<Checkpoint, ReadError> {
+        let invalid = ReadError::new(0, ReadErrorKind::InvalidCheckpoint, "invalid checkpoint");
+        let mut fields = text.split_whitespace();
+        let offset = fields.next().and_then(|field| field.parse().ok()).ok_or_else(|| invalid.clone())?;
+        let mut checkpoint = Checkpoint { offset, open: vec![], pending_end: None };
+        for field in fields {
+            if checkpoint.pending_end.is_some() {
+                return Err(invalid);
+            }
+            match field.strip_prefix('/') {
+                Some(name) => checkpoint.pending_end = Some(name.to_string()),
+                None => checkpoint.open.push(field.to_string()),
+            }
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// Pull parser yielding one `Event` at a time. Self-closing and `allow_no_close` elements
+/// produce a `Start` immediately followed by an `End`.
+pub struct Reader<'p, 'a> {
+    parser: &'p Parser,
+    input: &'a str,
+    remaining: &'a str,
+    open: Vec<String>,
+    pending_end: Option<String>,
+    failed: bool,
+}
+
+impl<'p, 'a> Reader<'p, 'a> {
+    pub fn new(parser: &'p Parser, input: &'a str) -> Reader<'p, 'a> {
+        Reader {
+            parser,
+            input,
+            remaining: input,
+            open: vec![],
+            pending_end: None,
+            failed: false,
+        }
+    }
+
+    /// Continues from `checkpoint` over the same `input`, as if every earlier event had just
+    /// been read.
+    pub fn resume(parser: &'p Parser, input: &'a str, checkpoint: Checkpoint) -> Result<Reader<'p, 'a>, ReadError> {
+        parser.check_input_len(input).map_err(|_| ReadError::new(0, ReadErrorKind::TooLarge { what: "input" }, "input too large"))?;
+        let remaining = input.get(checkpoint.offset..)
+            .ok_or(ReadError::new(checkpoint.offset, ReadErrorKind::InvalidCheckpoint, "invalid checkpoint"))?;
+        Ok(Reader {
+            parser,
+            input,
+            remaining,
+            open: checkpoint.open,
+            pending_end: checkpoint.pending_end,
+            failed: false,
+        })
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            offset: self.offset(),
+            open: self.open.clone(),
+            pending_end: self.pending_end.clone(),
+        }
+    }
+
+    /// Byte offset of the next unread character.
+    pub fn offset(&self) -> usize {
+        self.input.len() - self.remaining.len()
+    }
+
+    /// Names of the currently open elements, outermost first.
+    pub fn open_elements(&self) -> &[String] {
+        &self.open
+    }
+
+    /// Turns the event stream into one `Element` per `name` element, skipping everything
+    /// outside of them, so huge exports can be processed one record at a time.
+    pub fn split_on(self, name: impl Into<NameMatch<'p>>) -> Records<'p, 'a> {
+        Records {
+            reader: self,
+            name: name.into(),
+        }
+    }
+
+    fn fail(&mut self, kind: ReadErrorKind, message: &'static str) -> Option<Result<Event, ReadError>> {
+        self.failed = true;
+        Some(Err(ReadError::new(self.offset(), kind, message)))
+    }
+
+    fn start_tag(&mut self) -> Option<Result<Event, ReadError>> {
+        let parsed = (|| {
+            let (input, _) = tag("<")(self.remaining)?;
+            let (input, name) = identifier(input)?;
+            let (input, _) = take_while(char::is_whitespace)(input)?;
+            let (input, attributes) = many0(attribute)(input)?;
+            Ok::<_, nom::Err<nom::error::Error<&str>>>((input, name, attributes))
+        })();
+        let (input, name, attributes) = match parsed {
+            Ok(parsed) => parsed,
+            Err(_) => return self.fail(ReadErrorKind::Malformed, "malformed start tag"),
+        };
+        let name = self.parser.name(name);
+        let mut seen: Vec<(String, String)> = vec![];
+        for (key, value) in attributes {
+            let key = self.parser.name(key);
+            if seen.iter().any(|(x, _)| *x == key) {
+                return self.fail(ReadErrorKind::DuplicateAttribute, "duplicate attribute");
+            }
+            let mut value = self.parser.normalized(value);
+            match self.parser.limit(&value, self.parser.max_attribute_len) {
+                Some(keep) => value.truncate(keep),
+                None => return self.fail(ReadErrorKind::TooLarge { what: "attribute value" }, "attribute value too long"),
+            }
+            seen.push((key, value));
+        }
+        let closed = if let Some(input) = input.strip_prefix("/>") {
+            self.remaining = input;
+            true
+        } else if let Some(input) = input.strip_prefix('>') {
+            self.remaining = input;
+            self.parser.allow_no_close.contains(&name)
+        } else {
+            self.remaining = input;
+            return self.fail(ReadErrorKind::Malformed, "malformed start tag");
+        };
+        if closed {
+            self.pending_end = Some(name.clone());
+        } else {
+            self.open.push(name.clone());
+        }
+        Some(Ok(Event::Start { name, attributes: seen }))
+    }
+
+    fn end_tag(&mut self) -> Option<Result<Event, ReadError>> {
+        let parsed = (|| {
+            let (input, _) = tag("</")(self.remaining)?;
+            let (input, _) = take_while(char::is_whitespace)(input)?;
+            let (input, name) = identifier(input)?;
+            let (input, _) = take_while(char::is_whitespace)(input)?;
+            let (input, _) = tag(">")(input)?;
+            Ok::<_, nom::Err<nom::error::Error<&str>>>((input, name))
+        })();
+        let (input, name) = match parsed {
+            Ok(parsed) => parsed,
+            Err(_) => return self.fail(ReadErrorKind::Malformed, "malformed end tag"),
+        };
+        let name = self.parser.name(name);
+        if self.open.last() != Some(&name) {
+            return self.fail(ReadErrorKind::MismatchedEndTag, "mismatched end tag");
+        }
+        self.open.pop();
+        self.remaining = input;
+        Some(Ok(Event::End { name }))
+    }
+}
+
+impl<'p, 'a> Iterator for Reader<'p, 'a> {
+    type Item = Result<Event, ReadError>;
+
+    fn next(&mut self) -> Option<Result<Event, ReadError>> {
+        if self.failed {
+            return None;
+        }
+        if self.remaining.len() == self.input.len() && self.parser.check_input_len(self.input).is_err() {
+            return self.fail(ReadErrorKind::TooLarge { what: "input" }, "input too large");
+        }
+        if let Some(name) = self.pending_end.take() {
+            return Some(Ok(Event::End { name }));
+        }
+        if self.remaining.is_empty() {
+            if self.open.is_empty() {
+                return None;
+            }
+            return self.fail(ReadErrorKind::UnexpectedEof, "unexpected end of input");
+        }
+        if self.remaining.starts_with("<?xml") {
+            return match declaration(self.remaining) {
+                Ok((input, (version, encoding))) => {
+                    self.remaining = input;
+                    Some(Ok(Event::Declaration { version, encoding: encoding.map(str::to_string) }))
+                }
+                Err(_) => self.fail(ReadErrorKind::BadDeclaration, "malformed declaration"),
+            };
+        }
+        if let Some(input) = self.remaining.strip_prefix("<!DOCTYPE") {
+            return match doctype_body(input) {
+                Ok((input, body)) => {
+                    self.remaining = input;
+                    Some(Ok(Event::Doctype(body.to_string())))
+                }
+                Err(_) => self.fail(ReadErrorKind::Malformed, "malformed doctype"),
+            };
+        }
+        if self.remaining.starts_with("<![CDATA[") {
+            return match self.parser.cdata(self.remaining) {
+                Ok((input, data)) => {
+                    self.remaining = input;
+                    Some(Ok(Event::CData(data)))
+                }
+                Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge => self.fail(ReadErrorKind::TooLarge { what: "text" }, "text too long"),
+                Err(_) => self.fail(ReadErrorKind::Malformed, "unterminated CDATA section"),
+            };
+        }
+        if self.remaining.starts_with("<!--") {
+            return match comment(self.remaining) {
+                Ok((input, data)) => {
+                    self.remaining = input;
+                    Some(Ok(Event::Comment(data.to_string())))
+                }
+                Err(_) => self.fail(ReadErrorKind::Malformed, "unterminated comment"),
+            };
+        }
+        if self.remaining.starts_with("<?") {
+            return match processing_instruction(self.remaining) {
+                Ok((input, (target, data))) => {
+                    self.remaining = input;
+                    Some(Ok(Event::ProcessingInstruction { target: target.to_string(), data: data.to_string() }))
+                }
+                Err(_) => self.fail(ReadErrorKind::Malformed, "malformed processing instruction"),
+            };
+        }
+        if self.remaining.starts_with("</") {
+            return self.end_tag();
+        }
+        if self.remaining.starts_with('<') && !(self.parser.lenient && stray_angle_bracket(self.remaining)) {
+            match self.parser.foreign_node(self.remaining) {
+                Ok((input, Node::Foreign(foreign))) => {
+                    self.remaining = input;
+                    return Some(Ok(Event::Foreign(foreign)));
+                }
+                Err(nom::Err::Failure(_)) => return self.fail(ReadErrorKind::Malformed, "malformed foreign element"),
+                _ => return self.start_tag(),
+            }
+        }
+        match self.parser.text(self.remaining) {
+            Ok((input, data)) if !data.is_empty() => {
+                self.remaining = input;
+                Some(Ok(Event::Text(data)))
+            }
+            Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge => self.fail(ReadErrorKind::TooLarge { what: "text" }, "text too long"),
+            _ => self.fail(ReadErrorKind::Malformed, "unexpected character"),
+        }
+    }
+}
+
+pub struct Records<'p, 'a> {
+    reader: Reader<'p, 'a>,
+    name: NameMatch<'p>,
+}
+
+impl<'p, 'a> Records<'p, 'a> {
+    /// Checkpoint of the underlying reader. Taken between records, resuming from it with
+    /// `Reader::resume(...).split_on(...)` yields exactly the records not yet returned.
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.reader.checkpoint()
+    }
+}
+
+impl<'p, 'a> Iterator for Records<'p, 'a> {
+    type Item = Result<Element, ReadError>;
+
+    fn next(&mut self) -> Option<Result<Element, ReadError>> {
+        let mut stack: Vec<Element> = vec![];
+        for event in &mut self.reader {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
+            };
+            match event {
+                Event::Start { name, attributes } => {
+                    if stack.is_empty() && !self.name.matches(&name) {
+                        continue;
+                    }
+                    stack.push(Element {
+                        name,
+                        attributes: attributes.into_iter().collect(),
+                        children: vec![],
+                    });
+                }
+                Event::End { .. } => {
+                    if let Some(mut element) = stack.pop() {
+                        match stack.last_mut() {
+                            Some(parent) => parent.children.push(Node::Element(element)),
+                            None => {
+                                self.reader.parser.whitespace.apply(&mut element);
+                                return Some(Ok(element));
+                            }
+                        }
+                    }
+                }
+                Event::Text(data) | Event::CData(data) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(Node::CharData(data));
+                    }
+                }
+                Event::Comment(data) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(Node::Comment(data));
+                    }
+                }
+                Event::ProcessingInstruction { target, data } => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(Node::ProcessingInstruction { target, data });
+                    }
+                }
+                Event::Foreign(foreign) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(Node::Foreign(foreign));
+                    }
+                }
+                Event::Declaration { .. } | Event::Doctype(_) => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::reader::*;
+
+    #[test]
+    fn pull_events() {
+        let parser = Parser {
+            allow_no_close: vec!["img".to_string()],
+            ..Default::default()
+        };
+        let input = r#"<?xml version="1.0"?><root a="1"><img src="x"><b/>text<![CDATA[<raw>]]><!--c--><?pi x?></root>"#;
+        let events: Result<Vec<Event>, ReadError> = Reader::new(&parser, input).collect();
+        let start = |name: &str| Event::Start { name: name.to_string(), attributes: vec![] };
+        let end = |name: &str| Event::End { name: name.to_string() };
+        assert_eq!(events.unwrap(), vec![
+            Event::Declaration { version: 0, encoding: None },
+            Event::Start { name: "root".to_string(), attributes: vec![("a".to_string(), "1".to_string())] },
+            Event::Start { name: "img".to_string(), attributes: vec![("src".to_string(), "x".to_string())] },
+            end("img"),
+            start("b"),
+            end("b"),
+            Event::Text("text".to_string()),
+            Event::CData("<raw>".to_string()),
+            Event::Comment("c".to_string()),
+            Event::ProcessingInstruction { target: "pi".to_string(), data: "x".to_string() },
+            end("root"),
+        ]);
+    }
+
+    #[test]
+    fn split_records() {
+        let parser = Parser::default();
+        let input = "<export><meta/><record id=\"1\"><v>a</v></record>\n<batch><record id=\"2\"/></batch><record>bad</oops></export>";
+        let mut records = Reader::new(&parser, input).split_on("record");
+        let first = records.next().unwrap().unwrap();
+        assert_eq!(first.attributes["id"], "1");
+        assert_eq!(first.text(), "a");
+        assert_eq!(records.next().unwrap().unwrap().attributes["id"], "2");
+        assert_eq!(records.next().unwrap().unwrap_err().message, "mismatched end tag");
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let parser = Parser::default();
+        let input = "<export><record id=\"1\"/><record id=\"2\">x</record><record id=\"3\"/></export>";
+        let mut reader = Reader::new(&parser, input);
+        reader.nth(1);
+        let saved = reader.checkpoint().to_string();
+        assert_eq!(saved, "24 export /record");
+        let rest: Vec<Event> = reader.map(Result::unwrap).collect();
+        let resumed = Reader::resume(&parser, input, saved.parse().unwrap()).unwrap();
+        assert_eq!(resumed.map(Result::unwrap).collect::<Vec<_>>(), rest);
+
+        let mut records = Reader::new(&parser, input).split_on("record");
+        records.next();
+        let checkpoint = records.checkpoint();
+        let ids: Vec<String> = Reader::resume(&parser, input, checkpoint).unwrap()
+            .split_on("record")
+            .map(|record| record.unwrap().attributes["id"].clone())
+            .collect();
+        assert_eq!(ids, vec!["2", "3"]);
+
+        let bad = Checkpoint { offset: 1000, open: vec![], pending_end: None };
+        assert_eq!(Reader::resume(&parser, input, bad).err().unwrap().message, "invalid checkpoint");
+        assert!("x".parse::<Checkpoint>().is_err());
+        assert!("3 /a b".parse::<Checkpoint>().is_err());
+    }
+
+    #[test]
+    fn pull_errors() {
+        let parser = Parser::default();
+        let last = |input: &str| Reader::new(&parser, input).last().unwrap();
+        assert_eq!(last("<a></b>"), Err(ReadError::new(3, ReadErrorKind::MismatchedEndTag, "mismatched end tag")));
+        assert_eq!(last("<a>"), Err(ReadError::new(3, ReadErrorKind::UnexpectedEof, "unexpected end of input")));
+        assert_eq!(last(r#"<a x="1" x="2"/>"#).unwrap_err().message, "duplicate attribute");
+        let lenient = Parser { lenient: true, ..Default::default() };
+        assert_eq!(Reader::new(&lenient, "<a>1 < 2</a>").nth(1), Some(Ok(Event::Text("1 < 2".to_string()))));
+    }
+}
@@ -0,0 +1,66 @@
+use regex::Regex;
+
+use crate::json::own_text;
+use crate::{Element, Node, QuerySupport};
+
+/// Queries by compiled regex, for scraping where exact names and values are unpredictable.
+/// Like `elem_where`, each returns the outermost matching elements. Patterns are unanchored:
+/// write `^...$` to match a whole name or value.
+pub trait RegexQuery<'a> {
+    fn elem_name_regex(&self, pattern: &Regex) -> Vec<&'a Element>;
+    /// Elements with an attribute `name` whose value matches.
+    fn attr_regex(&self, name: &str, pattern: &Regex) -> Vec<&'a Element>;
+    /// Elements whose own text matches, not counting their descendants' text.
+    fn text_regex(&self, pattern: &Regex) -> Vec<&'a Element>;
+}
+
+fn attr_matches(element: &Element, name: &str, pattern: &Regex) -> bool {
+    element.attribute(name).is_some_and(|value| pattern.is_match(value))
+}
+
+impl<'a> RegexQuery<'a> for Vec<&'a Element> {
+    fn elem_name_regex(&self, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| pattern.is_match(&element.name))
+    }
+    fn attr_regex(&self, name: &str, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| attr_matches(element, name, pattern))
+    }
+    fn text_regex(&self, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| pattern.is_match(&own_text(element)))
+    }
+}
+
+impl<'a> RegexQuery<'a> for Vec<&'a Node> {
+    fn elem_name_regex(&self, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| pattern.is_match(&element.name))
+    }
+    fn attr_regex(&self, name: &str, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| attr_matches(element, name, pattern))
+    }
+    fn text_regex(&self, pattern: &Regex) -> Vec<&'a Element> {
+        self.elem_where(|element| pattern.is_match(&own_text(element)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::*;
+    use crate::regex_query::*;
+
+    #[test]
+    fn regex_queries() {
+        let res = Parser::default()
+            .complete_element(r#"<page><div class="price-box-17"><span>Now $12.99</span><h2>Deals</h2></div><section id="item-3"><p>sold out</p></section></page>"#)
+            .unwrap();
+        let names: Vec<&str> = vec![&res].elem_name_regex(&Regex::new("^(h[1-6]|p)$").unwrap()).into_iter().map(Element::name).collect();
+        assert_eq!(names, ["h2", "p"]);
+        let boxes = vec![&res].attr_regex("class", &Regex::new(r"^price-box-\d+$").unwrap());
+        assert_eq!(boxes.len(), 1);
+        let prices = vec![&res].text_regex(&Regex::new(r"\$\d+\.\d{2}").unwrap());
+        assert_eq!(prices.iter().map(|x| x.text()).collect::<Vec<_>>(), ["Now $12.99"]);
+        assert_eq!(res.children().elem_name_regex(&Regex::new("^sec").unwrap()).len(), 1);
+        assert!(vec![&res].attr_regex("id", &Regex::new("^item-[a-z]").unwrap()).is_empty());
+    }
+}
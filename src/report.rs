@@ -0,0 +1,273 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::reader::Reader;
+use crate::tokens::{tokens, TokenKind};
+use crate::{Location, Parser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Input the parser rejects.
+    Error,
+    /// Input the parser accepts but that is probably not what the author meant.
+    Warning,
+    /// Broken input the parser repaired, as lenient mode and `allow_no_close` do.
+    Recovery,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Recovery => "recovery",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Byte range of the offending input.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// Everything worth telling someone triaging a bad feed about one input: where the parser
+/// gives up, and what it quietly repaired or let through on the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseReport {
+    pub source: String,
+    /// Sorted by position.
+    pub issues: Vec<Issue>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Something malformed that parsing tolerated instead of rejecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuirkKind {
+    /// A `<` that starts no markup, kept as text in lenient mode.
+    StrayLessThan,
+    /// A `&` that starts no reference, kept as text.
+    BareAmpersand,
+    /// An `allow_no_close` element without an end tag, treated as empty.
+    UnclosedTag { name: String },
+}
+
+impl QuirkKind {
+    /// How the source should be written instead, for sending back to whoever produced it.
+    pub fn fix(&self) -> String {
+        match self {
+            QuirkKind::StrayLessThan => "write `&lt;`".to_string(),
+            QuirkKind::BareAmpersand => "write `&amp;`".to_string(),
+            QuirkKind::UnclosedTag { name } => format!("write `<{}/>` or add `</{}>`", name, name),
+        }
+    }
+}
+
+impl fmt::Display for QuirkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuirkKind::StrayLessThan => f.write_str("stray `<` kept as text"),
+            QuirkKind::BareAmpersand => f.write_str("bare `&` kept as text"),
+            QuirkKind::UnclosedTag { name } => write!(f, "<{}> without a closing tag treated as empty", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quirk {
+    pub kind: QuirkKind,
+    /// Byte range of the `<` or `&`, or the `>` ending an unclosed start tag.
+    pub span: Range<usize>,
+}
+
+/// Every quirk `parser` tolerates in `source`, in order. Found from the tokens alone, so it
+/// also lists those before a point where parsing fails.
+pub fn quirks(parser: &Parser, source: &str) -> Vec<Quirk> {
+    let mut quirks = vec![];
+    let mut open_name = None;
+    for (kind, span) in tokens(source) {
+        let text = &source[span.clone()];
+        let kind = match kind {
+            TokenKind::Error if text == "<" && parser.lenient => Some(QuirkKind::StrayLessThan),
+            TokenKind::Text if text == "&" => Some(QuirkKind::BareAmpersand),
+            TokenKind::ElementName => {
+                open_name = Some(parser.name(text));
+                None
+            }
+            TokenKind::TagClose => match open_name.take() {
+                Some(name) if parser.allow_no_close.contains(&name) => Some(QuirkKind::UnclosedTag { name }),
+                _ => None,
+            },
+            TokenKind::EndTagOpen | TokenKind::EmptyTagClose => {
+                open_name = None;
+                None
+            }
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            quirks.push(Quirk { kind, span });
+        }
+    }
+    quirks
+}
+
+impl ParseReport {
+    pub fn new(parser: &Parser, source: &str) -> ParseReport {
+        let mut issues = vec![];
+        for event in Reader::new(parser, source) {
+            if let Err(e) = event {
+                let end = source[e.offset..].chars().next().map_or(e.offset, |ch| e.offset + ch.len_utf8());
+                issues.push(Issue { severity: Severity::Error, span: e.offset..end, message: e.message.to_string() });
+                break;
+            }
+        }
+        for quirk in quirks(parser, source) {
+            let severity = match quirk.kind {
+                QuirkKind::BareAmpersand => Severity::Warning,
+                QuirkKind::StrayLessThan | QuirkKind::UnclosedTag { .. } => Severity::Recovery,
+            };
+            issues.push(Issue { severity, span: quirk.span, message: quirk.kind.to_string() });
+        }
+        for (kind, span) in tokens(source) {
+            let is_quirk = parser.lenient && &source[span.clone()] == "<";
+            if kind == TokenKind::Error && !is_quirk {
+                issues.push(Issue { severity: Severity::Warning, span, message: "malformed markup".to_string() });
+            }
+        }
+        issues.sort_by_key(|issue| (issue.span.start, issue.severity));
+        ParseReport { source: source.to_string(), issues }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|issue| issue.severity == Severity::Error)
+    }
+
+    /// A standalone page listing every issue, each linked to its place in the source listing
+    /// below it, where the offending input is highlighted and links back to the list.
+    pub fn to_html(&self, title: &str) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>");
+        out.push_str(&escape(title));
+        out.push_str("</title><style>\
+            body{font-family:sans-serif}\
+            pre{background:#f6f6f6;padding:1em;line-height:1.4}\
+            .ln{color:#999;user-select:none}\
+            mark.error{background:#f99}mark.warning{background:#fd6}mark.recovery{background:#9cf}\
+            li.error{color:#a00}li.warning{color:#850}li.recovery{color:#036}\
+            </style></head><body>\n<h1>");
+        out.push_str(&escape(title));
+        out.push_str("</h1>\n");
+        if self.issues.is_empty() {
+            out.push_str("<p>No issues.</p>\n");
+        } else {
+            out.push_str("<ol>\n");
+            for (index, issue) in self.issues.iter().enumerate() {
+                let Location { line, column, .. } = Location::new(&self.source, issue.span.start);
+                out.push_str(&format!(
+                    "<li class=\"{0}\" id=\"issue-{1}\"><a href=\"#at-{1}\">line {2}, column {3}</a>: {0}: {4}</li>\n",
+                    issue.severity, index, line, column, escape(&issue.message),
+                ));
+            }
+            out.push_str("</ol>\n");
+        }
+        out.push_str("<pre>");
+        self.write_source(&mut out);
+        out.push_str("</pre>\n</body></html>\n");
+        out
+    }
+
+    /// The source with line numbers and a `<mark>` per issue; an issue overlapping an earlier
+    /// one is only marked where it does not overlap.
+    fn write_source(&self, out: &mut String) {
+        let mut marks: Vec<(usize, Range<usize>)> = vec![];
+        let mut covered = 0;
+        for (index, issue) in self.issues.iter().enumerate() {
+            let start = issue.span.start.max(covered);
+            // Empty spans, such as an error at the end of the input, still get a visible mark.
+            let end = issue.span.end.max(start);
+            if start < end || start == self.source.len() {
+                marks.push((index, start..end));
+                covered = end;
+            }
+        }
+        let mut line = 1;
+        out.push_str(&format!("<span class=\"ln\">{:4} </span>", line));
+        let mut position = 0;
+        let mut marks = marks.into_iter().peekable();
+        let mut write_text = |out: &mut String, text: &str| {
+            for (index, piece) in text.split('\n').enumerate() {
+                if index > 0 {
+                    line += 1;
+                    out.push_str(&format!("\n<span class=\"ln\">{:4} </span>", line));
+                }
+                out.push_str(&escape(piece));
+            }
+        };
+        while position < self.source.len() || marks.peek().is_some() {
+            match marks.next() {
+                Some((index, span)) => {
+                    write_text(out, &self.source[position..span.start]);
+                    let issue = &self.issues[index];
+                    out.push_str(&format!(
+                        "<a href=\"#issue-{0}\"><mark class=\"{1}\" id=\"at-{0}\" title=\"{2}\">",
+                        index, issue.severity, escape(&issue.message),
+                    ));
+                    write_text(out, if span.is_empty() { " " } else { &self.source[span.clone()] });
+                    out.push_str("</mark></a>");
+                    position = span.end;
+                }
+                None => {
+                    write_text(out, &self.source[position..]);
+                    position = self.source.len();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::report::*;
+
+    #[test]
+    fn feed_report() {
+        let parser = Parser { lenient: true, allow_no_close: vec!["br".to_string()], ..Default::default() };
+        let source = "<feed>\n<p>1 < 2 & 3<br></p>\n<q></feed>";
+        let report = ParseReport::new(&parser, source);
+        let found: Vec<(Severity, &str, usize)> = report.issues.iter().map(|i| (i.severity, i.message.as_str(), i.span.start)).collect();
+        assert_eq!(found, [
+            (Severity::Recovery, "stray `<` kept as text", 12),
+            (Severity::Warning, "bare `&` kept as text", 16),
+            (Severity::Recovery, "<br> without a closing tag treated as empty", 22),
+            (Severity::Error, "mismatched end tag", 31),
+        ]);
+        assert!(report.has_errors());
+
+        let html = report.to_html("feed <1>");
+        assert!(html.contains("<title>feed &lt;1&gt;</title>"));
+        assert!(html.contains("<li class=\"error\" id=\"issue-3\"><a href=\"#at-3\">line 3, column 4</a>: error: mismatched end tag</li>"));
+        assert!(html.contains("<a href=\"#issue-0\"><mark class=\"recovery\" id=\"at-0\" title=\"stray `&lt;` kept as text\">&lt;</mark></a>"));
+        assert!(html.contains("<span class=\"ln\">   3 </span>&lt;q&gt;<a href=\"#issue-3\"><mark class=\"error\""));
+        assert!(ParseReport::new(&parser, "<a/>").to_html("ok").contains("No issues."));
+    }
+
+    #[test]
+    fn quirk_list() {
+        let parser = Parser { lenient: true, allow_no_close: vec!["img".to_string()], ..Default::default() };
+        let found: Vec<(QuirkKind, Range<usize>, String)> = quirks(&parser, "<p>a & b <IMG src='x'> 1 < 2</p>").into_iter()
+            .map(|quirk| (quirk.kind.clone(), quirk.span, quirk.kind.fix()))
+            .collect();
+        assert_eq!(found, [
+            (QuirkKind::BareAmpersand, 5..6, "write `&amp;`".to_string()),
+            (QuirkKind::UnclosedTag { name: "img".to_string() }, 21..22, "write `<img/>` or add `</img>`".to_string()),
+            (QuirkKind::StrayLessThan, 25..26, "write `&lt;`".to_string()),
+        ]);
+        assert!(quirks(&Parser::default(), "<p>1 < 2</p>").is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{Element, Node};
+use crate::pattern::{Captures, Pattern};
+
+pub struct Rule {
+    pattern: Pattern,
+    replacement: Box<dyn Fn(&Captures) -> Element>,
+}
+
+impl Rule {
+    pub fn new(pattern: Pattern, replacement: impl Fn(&Captures) -> Element + 'static) -> Rule {
+        Rule {
+            pattern,
+            replacement: Box::new(replacement),
+        }
+    }
+}
+
+/// Applies rules bottom-up over a tree, pass after pass, until a pass makes no replacement.
+pub struct Rewriter {
+    rules: Vec<Rule>,
+    max_passes: usize,
+}
+
+impl Default for Rewriter {
+    fn default() -> Self {
+        Rewriter {
+            rules: vec![],
+            max_passes: 100,
+        }
+    }
+}
+
+impl Rewriter {
+    pub fn new() -> Rewriter {
+        Rewriter::default()
+    }
+    pub fn rule(mut self, rule: Rule) -> Rewriter {
+        self.rules.push(rule);
+        self
+    }
+    pub fn max_passes(mut self, max_passes: usize) -> Rewriter {
+        self.max_passes = max_passes;
+        self
+    }
+
+    /// Returns the number of replacements made, or `None` if the rules were still firing after
+    /// `max_passes` passes (e.g. a rule whose replacement matches its own pattern).
+    pub fn apply(&self, root: &mut Element) -> Option<usize> {
+        let mut total = 0;
+        for _ in 0..self.max_passes {
+            let rewrites = self.pass(root);
+            if rewrites == 0 {
+                return Some(total);
+            }
+            total += rewrites;
+        }
+        None
+    }
+
+    fn pass(&self, element: &mut Element) -> usize {
+        let mut rewrites = 0;
+        for child in &mut element.children {
+            if let Node::Element(child) = child {
+                rewrites += self.pass(child);
+            }
+        }
+        for rule in &self.rules {
+            if let Some(captures) = rule.pattern.matches(element) {
+                let replacement = (rule.replacement)(&captures);
+                *element = replacement;
+                rewrites += 1;
+            }
+        }
+        rewrites
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::*;
+    use crate::pattern::*;
+    use crate::rewrite::*;
+
+    #[test]
+    fn rewrite_to_fixpoint() {
+        let mut res = Parser::default()
+            .complete_element("<p><b>one</b> and <b>two <b>three</b></b><i>x</i></p>")
+            .unwrap();
+        let rewriter = Rewriter::new()
+            .rule(Rule::new(Pattern::element("b").capture("b"), |captures| Element {
+                name: "strong".to_string(),
+                attributes: vec![("class".to_string(), "x".to_string())].into_iter().collect(),
+                children: captures.elements["b"].children.clone(),
+            }))
+            .rule(Rule::new(Pattern::element("i").capture("i"), |captures| Element {
+                name: "b".to_string(),
+                attributes: HashMap::new(),
+                children: captures.elements["i"].children.clone(),
+            }));
+        assert_eq!(rewriter.apply(&mut res), Some(5));
+        assert_eq!(vec![&res].elem_name("b").len(), 0);
+        assert_eq!(vec![&res].elem_name("strong").len(), 3);
+        assert_eq!(res.children().elem_name("strong")[1].children().elem_name("strong")[0].text(), "three");
+    }
+
+    #[test]
+    fn diverging_rules_stop() {
+        let mut res = Parser::default().complete_element("<a/>").unwrap();
+        let rewriter = Rewriter::new()
+            .max_passes(10)
+            .rule(Rule::new(Pattern::element("a"), |_| Element {
+                name: "a".to_string(),
+                attributes: HashMap::new(),
+                children: vec![],
+            }));
+        assert_eq!(rewriter.apply(&mut res), None);
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::pattern::Pattern;
+use crate::{Element, Node};
+
+#[derive(Debug, Clone)]
+enum Test {
+    Pattern(Pattern),
+    Predicate(fn(&Element) -> bool),
+}
+
+impl Test {
+    fn holds(&self, element: &Element) -> bool {
+        match self {
+            Test::Pattern(pattern) => pattern.matches(element).is_some(),
+            Test::Predicate(predicate) => predicate(element),
+        }
+    }
+}
+
+/// Assertions about every element matching a context pattern, as in Schematron: "every
+/// `<order>` must contain a `<total>`".
+#[derive(Debug, Clone)]
+pub struct Rule {
+    context: Pattern,
+    assertions: Vec<(Test, String)>,
+}
+
+impl Rule {
+    pub fn for_each(context: Pattern) -> Rule {
+        Rule {
+            context,
+            assertions: vec![],
+        }
+    }
+    /// Each context element must also match `test`, e.g.
+    /// `Pattern::any().child(Pattern::element("total"))`.
+    pub fn assert(mut self, test: Pattern, message: &str) -> Rule {
+        self.assertions.push((Test::Pattern(test), message.to_string()));
+        self
+    }
+    pub fn assert_with(mut self, test: fn(&Element) -> bool, message: &str) -> Rule {
+        self.assertions.push((Test::Predicate(test), message.to_string()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Where the failing element is, as given by `Element::breadcrumb` for a `RuleSet`.
+    /// `Constraints` give positions only, like `orders > order[2]`, since an `id` may be the
+    /// very value that is duplicated.
+    pub path: String,
+    pub message: String,
+}
+
+/// A set of business rules checked together; far simpler than XSD, and usually what
+/// validating a payload actually needs.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new() -> RuleSet {
+        RuleSet::default()
+    }
+    pub fn rule(mut self, rule: Rule) -> RuleSet {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Every failed assertion at or below `root`, in document order and then rule order.
+    pub fn validate(&self, root: &Element) -> Vec<Violation> {
+        let mut elements = vec![root];
+        let mut violations = vec![];
+        while let Some(element) = elements.pop() {
+            elements.extend(element.children.iter().rev().filter_map(Node::as_element));
+            for rule in self.rules.iter().filter(|rule| rule.context.matches(element).is_some()) {
+                for (test, message) in &rule.assertions {
+                    if !test.holds(element) {
+                        violations.push(Violation {
+                            path: element.breadcrumb(root).expect("element is below root"),
+                            message: message.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// One step of a constraint path: a child element, or an attribute written `@name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Element(String),
+    Attribute(String),
+}
+
+/// A path like `order/@id`: the steps before the last pick context elements by their name and
+/// their ancestors' names, wherever they are, and the last step is looked up from each one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConstraintPath {
+    context: Vec<String>,
+    target: Step,
+}
+
+impl ConstraintPath {
+    fn parse(path: &str) -> ConstraintPath {
+        let mut steps: Vec<String> = path.split('/').filter(|step| !step.is_empty()).map(str::to_string).collect();
+        let last = steps.pop().unwrap_or_default();
+        let target = match last.strip_prefix('@') {
+            Some(name) => Step::Attribute(name.to_string()),
+            None => Step::Element(last),
+        };
+        ConstraintPath { context: steps, target }
+    }
+
+    /// Whether `chain`, from the root down to an element, ends with the context steps. With no
+    /// context steps only the root matches when the target is an element, and every element
+    /// does when it is an attribute.
+    fn is_context(&self, chain: &[&Element]) -> bool {
+        if self.context.is_empty() {
+            return chain.len() == 1 || matches!(self.target, Step::Attribute(_));
+        }
+        chain.len() >= self.context.len()
+            && chain[chain.len() - self.context.len()..].iter().zip(&self.context).all(|(element, name)| element.name == *name)
+    }
+
+    fn values(&self, element: &Element) -> Vec<String> {
+        match &self.target {
+            Step::Attribute(name) => element.attribute(name).map(str::to_string).into_iter().collect(),
+            Step::Element(name) => element.children.iter()
+                .filter_map(Node::as_element)
+                .filter(|x| x.name == *name)
+                .map(|x| x.text().trim().to_string())
+                .collect(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.target {
+            Step::Attribute(name) => format!("attribute {}", name),
+            Step::Element(name) => format!("element {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Constraint {
+    Require(ConstraintPath),
+    Unique(ConstraintPath),
+    Occurs(ConstraintPath, usize, usize),
+}
+
+/// Occurrence and uniqueness constraints checked against a document, a middle ground between
+/// hand-written checks and a schema language:
+/// `Constraints::new().require("order/id").unique("@id").max_occurs("order/note", 1)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Constraints {
+    constraints: Vec<Constraint>,
+}
+
+impl Constraints {
+    pub fn new() -> Constraints {
+        Constraints::default()
+    }
+    /// Every context element must have the target.
+    pub fn require(mut self, path: &str) -> Constraints {
+        self.constraints.push(Constraint::Require(ConstraintPath::parse(path)));
+        self
+    }
+    /// No two targets in the document may have the same value, an element's being its trimmed text.
+    pub fn unique(mut self, path: &str) -> Constraints {
+        self.constraints.push(Constraint::Unique(ConstraintPath::parse(path)));
+        self
+    }
+    pub fn min_occurs(self, path: &str, min: usize) -> Constraints {
+        self.occurs(path, min, usize::MAX)
+    }
+    pub fn max_occurs(self, path: &str, max: usize) -> Constraints {
+        self.occurs(path, 0, max)
+    }
+    /// Every context element must have between `min` and `max` targets.
+    pub fn occurs(mut self, path: &str, min: usize, max: usize) -> Constraints {
+        self.constraints.push(Constraint::Occurs(ConstraintPath::parse(path), min, max));
+        self
+    }
+
+    /// Every violated constraint, in document order and then constraint order.
+    pub fn check(&self, root: &Element) -> Vec<Violation> {
+        let mut seen: Vec<HashSet<String>> = vec![HashSet::new(); self.constraints.len()];
+        let mut violations = vec![];
+        // The elements from the root down to the current one, and their breadcrumb steps.
+        let mut chain: Vec<&Element> = vec![];
+        let mut steps: Vec<String> = vec![];
+        let mut stack = vec![(root, 0, root.name.clone())];
+        while let Some((element, depth, step)) = stack.pop() {
+            chain.truncate(depth);
+            steps.truncate(depth);
+            chain.push(element);
+            steps.push(step);
+            let children: Vec<&Element> = element.children.iter().filter_map(Node::as_element).collect();
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for child in &children {
+                *counts.entry(&child.name).or_default() += 1;
+            }
+            let mut positions: HashMap<&str, usize> = HashMap::new();
+            let start = stack.len();
+            for child in children {
+                let position = positions.entry(&child.name).or_default();
+                *position += 1;
+                let step = match counts[child.name.as_str()] {
+                    1 => child.name.clone(),
+                    _ => format!("{}[{}]", child.name, position),
+                };
+                stack.push((child, depth + 1, step));
+            }
+            stack[start..].reverse();
+            let mut violation = |message: String| violations.push(Violation {
+                path: steps.join(" > "),
+                message,
+            });
+            for (constraint, seen) in self.constraints.iter().zip(&mut seen) {
+                match constraint {
+                    Constraint::Require(path) if path.is_context(&chain) && path.values(element).is_empty() => {
+                        violation(format!("missing {}", path.describe()));
+                    }
+                    Constraint::Unique(path) if path.is_context(&chain) => {
+                        for value in path.values(element) {
+                            if seen.contains(&value) {
+                                violation(format!("duplicate {} {:?}", path.describe(), value));
+                            } else {
+                                seen.insert(value);
+                            }
+                        }
+                    }
+                    Constraint::Occurs(path, min, max) if path.is_context(&chain) => {
+                        let count = path.values(element).len();
+                        if count < *min {
+                            violation(format!("{} occurs {} times, at least {} expected", path.describe(), count, min));
+                        } else if count > *max {
+                            violation(format!("{} occurs {} times, at most {} allowed", path.describe(), count, max));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::pattern::*;
+    use crate::rules::*;
+
+    #[test]
+    fn business_rules() {
+        let parser = Parser::default();
+        let orders = parser.complete_element(
+            r#"<orders><order id="a"><line qty="1"/><total>5</total></order><order><line qty="0"/><line qty="2"/></order></orders>"#,
+        ).unwrap();
+        let rules = RuleSet::new()
+            .rule(Rule::for_each(Pattern::element("order"))
+                .assert(Pattern::any().child(Pattern::element("total")), "every order must contain a total"))
+            .rule(Rule::for_each(Pattern::element("line"))
+                .assert_with(|line| line.attribute("qty").and_then(|qty| qty.parse::<u32>().ok()).is_some_and(|qty| qty > 0), "quantity must be positive"));
+        let violations = rules.validate(&orders);
+        let found: Vec<(&str, &str)> = violations.iter().map(|v| (v.path.as_str(), v.message.as_str())).collect();
+        assert_eq!(found, [
+            ("orders > order[2]", "every order must contain a total"),
+            ("orders > order[2] > line[1]", "quantity must be positive"),
+        ]);
+    }
+
+    #[test]
+    fn occurrence_constraints() {
+        let parser = Parser::default();
+        let orders = parser.complete_element(
+            r#"<orders><order id="a"><note/></order><order id="a"><note/><note/></order><order/></orders>"#,
+        ).unwrap();
+        let constraints = Constraints::new().require("order/@id").unique("@id").max_occurs("order/note", 1).require("meta");
+        let found: Vec<(String, String)> = constraints.check(&orders).into_iter().map(|v| (v.path, v.message)).collect();
+        assert_eq!(found, [
+            ("orders".to_string(), "missing element meta".to_string()),
+            ("orders > order[2]".to_string(), "duplicate attribute id \"a\"".to_string()),
+            ("orders > order[2]".to_string(), "element note occurs 2 times, at most 1 allowed".to_string()),
+            ("orders > order[3]".to_string(), "missing attribute id".to_string()),
+        ]);
+
+        let shop = parser.complete_element(r#"<shop><dept><item sku="1"/><item sku="2"/></dept><dept><item sku="1"/></dept></shop>"#).unwrap();
+        let found: Vec<String> = Constraints::new().unique("@sku").require("dept/item").check(&shop).into_iter().map(|v| v.path).collect();
+        assert_eq!(found, ["shop > dept[2] > item"]);
+    }
+}
@@ -0,0 +1,532 @@
+use std::io;
+
+use crate::reader::{Event, Reader};
+use crate::{Element, Node, Parser};
+
+/// Generator for large synthetic documents with a controllable shape, for load tests and
+/// benches. Output depends only on the settings, so runs are comparable.
+///
+/// Every element has `breadth` element children down to `depth` levels below the root, so
+/// `Synthetic::new().depth(5).breadth(16)` is about 1.1M elements.
+#[derive(Debug, Clone)]
+pub struct Synthetic {
+    depth: usize,
+    breadth: usize,
+    text_ratio: f64,
+    attributes: usize,
+    seed: u64,
+}
+
+impl Default for Synthetic {
+    fn default() -> Synthetic {
+        Synthetic {
+            depth: 3,
+            breadth: 10,
+            text_ratio: 0.5,
+            attributes: 2,
+            seed: 1,
+        }
+    }
+}
+
+const WORDS: [&str; 12] = [
+    "lorem", "ipsum", "dolor", "sit", "amet", "record", "value", "&amp;", "data", "export", "entry", "item",
+];
+
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn chance(&mut self, ratio: f64) -> bool {
+        (self.next(1_000_000) as f64) < ratio * 1_000_000.0
+    }
+}
+
+impl Synthetic {
+    pub fn new() -> Synthetic {
+        Synthetic::default()
+    }
+    /// Levels of elements below the root.
+    pub fn depth(mut self, depth: usize) -> Synthetic {
+        self.depth = depth;
+        self
+    }
+    /// Element children of every non-leaf element.
+    pub fn breadth(mut self, breadth: usize) -> Synthetic {
+        self.breadth = breadth;
+        self
+    }
+    /// Chance, from 0 to 1, that an element gets a run of text before its children.
+    pub fn text_ratio(mut self, text_ratio: f64) -> Synthetic {
+        self.text_ratio = text_ratio;
+        self
+    }
+    /// Attributes per element, counting the `id` every element gets.
+    pub fn attributes(mut self, attributes: usize) -> Synthetic {
+        self.attributes = attributes;
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Synthetic {
+        self.seed = seed;
+        self
+    }
+
+    /// Number of elements the generated document contains.
+    pub fn element_count(&self) -> usize {
+        (0..=self.depth).map(|level| self.breadth.saturating_pow(level as u32)).fold(0, usize::saturating_add)
+    }
+
+    pub fn generate(&self) -> String {
+        let mut out = vec![];
+        self.write_to(&mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("generated documents are ASCII")
+    }
+
+    /// Streams the document out, for sizes that should not be held in memory twice.
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        let mut random = Random(self.seed.max(1));
+        let mut counter = 0usize;
+        // (level, children still to open); an element is closed when its count runs out.
+        let mut stack: Vec<(usize, usize)> = vec![];
+        self.open(&mut w, &mut random, &mut counter, 0)?;
+        stack.push((0, if self.depth > 0 { self.breadth } else { 0 }));
+        while let Some(&(level, remaining)) = stack.last() {
+            if remaining == 0 {
+                write!(w, "</n{}>", level)?;
+                stack.pop();
+                continue;
+            }
+            let top = stack.len() - 1;
+            stack[top].1 -= 1;
+            let level = level + 1;
+            self.open(&mut w, &mut random, &mut counter, level)?;
+            stack.push((level, if level < self.depth { self.breadth } else { 0 }));
+        }
+        Ok(())
+    }
+
+    fn open<W: io::Write>(&self, w: &mut W, random: &mut Random, counter: &mut usize, level: usize) -> io::Result<()> {
+        *counter += 1;
+        write!(w, "<n{} id=\"{}\"", level, counter)?;
+        for index in 1..self.attributes {
+            write!(w, " a{}=\"{}\"", index, WORDS[random.next(WORDS.len())])?;
+        }
+        w.write_all(b">")?;
+        if random.chance(self.text_ratio) {
+            for word in 0..1 + random.next(12) {
+                if word > 0 {
+                    w.write_all(b" ")?;
+                }
+                w.write_all(WORDS[random.next(WORDS.len())].as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse result in a form any XML parser can be mapped to: the event stream with declarations
+/// and doctypes removed, adjacent text and CDATA merged and whitespace-only text dropped, or a
+/// failure message.
+pub type Outcome = Result<Vec<Event>, String>;
+
+/// Where two parsers disagree on one input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub input: String,
+    /// Index of the first differing event or, when only one side failed, the length of the
+    /// other side's stream.
+    pub index: usize,
+    pub left: Result<Option<Event>, String>,
+    pub right: Result<Option<Event>, String>,
+}
+
+pub fn canonical(events: Vec<Event>) -> Vec<Event> {
+    let mut out: Vec<Event> = vec![];
+    for event in events {
+        match event {
+            Event::Declaration { .. } | Event::Doctype(_) => {}
+            Event::Text(text) | Event::CData(text) => match out.last_mut() {
+                Some(Event::Text(previous)) => previous.push_str(&text),
+                _ => out.push(Event::Text(text)),
+            },
+            event => out.push(event),
+        }
+    }
+    out.retain(|event| !matches!(event, Event::Text(text) if text.trim().is_empty()));
+    out
+}
+
+fn flatten(element: &Element, out: &mut Vec<Event>) {
+    out.push(Event::Start { name: element.name.clone(), attributes: element.attributes_sorted().into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect() });
+    for child in &element.children {
+        match child {
+            Node::CharData(text) => out.push(Event::Text(text.clone())),
+            Node::Element(child) => flatten(child, out),
+            Node::Comment(text) => out.push(Event::Comment(text.clone())),
+            Node::ProcessingInstruction { target, data } => out.push(Event::ProcessingInstruction { target: target.clone(), data: data.clone() }),
+            Node::Foreign(foreign) => out.push(Event::Foreign(foreign.clone())),
+        }
+    }
+    out.push(Event::End { name: element.name.clone() });
+}
+
+/// The tree parser as a differential-testing subject.
+pub fn tree_outcome(parser: &Parser, input: &str) -> Outcome {
+    let element = parser.complete_element(input).map_err(|e| format!("tree parser rejected input: {}", e))?;
+    let mut events = vec![];
+    flatten(&element, &mut events);
+    Ok(canonical(events))
+}
+
+/// The pull `Reader` as a differential-testing subject, with attributes sorted like the tree's.
+pub fn reader_outcome(parser: &Parser, input: &str) -> Outcome {
+    let events = Reader::new(parser, input)
+        .map(|event| event.map(|event| match event {
+            Event::Start { name, mut attributes } => {
+                attributes.sort();
+                Event::Start { name, attributes }
+            }
+            event => event,
+        }))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(canonical(events))
+}
+
+#[cfg(feature = "differential")]
+fn roxmltree_flatten(node: roxmltree::Node<'_, '_>, out: &mut Vec<Event>) {
+    let qualified = |uri: Option<&str>, local: &str| match uri.and_then(|uri| node.lookup_prefix(uri)).filter(|prefix| !prefix.is_empty()) {
+        Some(prefix) => format!("{}:{}", prefix, local),
+        None => local.to_string(),
+    };
+    match node.node_type() {
+        roxmltree::NodeType::Element => {
+            let name = qualified(node.tag_name().namespace(), node.tag_name().name());
+            let inherited: Vec<_> = node.parent_element().map(|parent| parent.namespaces().collect()).unwrap_or_default();
+            let mut attributes: Vec<(String, String)> = node.namespaces()
+                .filter(|ns| ns.name() != Some("xml") && !inherited.contains(ns))
+                .map(|ns| (ns.name().map_or_else(|| "xmlns".to_string(), |prefix| format!("xmlns:{}", prefix)), ns.uri().to_string()))
+                .chain(node.attributes().map(|attr| (qualified(attr.namespace(), attr.name()), attr.value().to_string())))
+                .collect();
+            attributes.sort();
+            out.push(Event::Start { name: name.clone(), attributes });
+            for child in node.children() {
+                roxmltree_flatten(child, out);
+            }
+            out.push(Event::End { name });
+        }
+        roxmltree::NodeType::Text => out.push(Event::Text(node.text().unwrap_or_default().to_string())),
+        roxmltree::NodeType::Comment => out.push(Event::Comment(node.text().unwrap_or_default().to_string())),
+        roxmltree::NodeType::PI => if let Some(pi) = node.pi() {
+            out.push(Event::ProcessingInstruction { target: pi.target.to_string(), data: pi.value.unwrap_or_default().to_string() });
+        },
+        roxmltree::NodeType::Root => {}
+    }
+}
+
+/// roxmltree as a differential-testing subject, covering the root element as `tree_outcome`
+/// does. Prefixed names are rebuilt from the prefixes in scope and namespace declarations
+/// reported as `xmlns` attributes, as this crate keeps them.
+#[cfg(feature = "differential")]
+pub fn roxmltree_outcome(input: &str) -> Outcome {
+    let document = roxmltree::Document::parse(input).map_err(|e| e.to_string())?;
+    let mut events = vec![];
+    roxmltree_flatten(document.root_element(), &mut events);
+    Ok(canonical(events))
+}
+
+/// quick-xml's reader as a differential-testing subject, with attributes sorted like the
+/// tree's and self-closing tags split into `Start` and `End`.
+#[cfg(feature = "differential")]
+pub fn quick_xml_outcome(input: &str) -> Outcome {
+    use quick_xml::events::{BytesStart, Event as Quick};
+
+    let utf8 = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    let start = |tag: &BytesStart<'_>| -> Result<Event, String> {
+        let mut attributes = vec![];
+        for attribute in tag.attributes() {
+            let attribute = attribute.map_err(|e| e.to_string())?;
+            let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+            attributes.push((utf8(attribute.key.as_ref()), value.into_owned()));
+        }
+        attributes.sort();
+        Ok(Event::Start { name: utf8(tag.name().as_ref()), attributes })
+    };
+    let mut reader = quick_xml::Reader::from_str(input);
+    let mut events = vec![];
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Quick::Start(tag) => events.push(start(&tag)?),
+            Quick::Empty(tag) => {
+                events.push(start(&tag)?);
+                events.push(Event::End { name: utf8(tag.name().as_ref()) });
+            }
+            Quick::End(tag) => events.push(Event::End { name: utf8(tag.name().as_ref()) }),
+            Quick::Text(text) => events.push(Event::Text(text.unescape().map_err(|e| e.to_string())?.into_owned())),
+            Quick::CData(text) => events.push(Event::CData(utf8(&text))),
+            Quick::Comment(text) => events.push(Event::Comment(utf8(&text))),
+            Quick::PI(pi) => events.push(Event::ProcessingInstruction { target: utf8(pi.target()), data: utf8(pi.content()).trim_start().to_string() }),
+            Quick::Decl(_) | Quick::DocType(_) => {}
+            Quick::Eof => break,
+        }
+    }
+    Ok(canonical(events))
+}
+
+/// Runs both parsers over every input and reports each disagreement. Either side can be
+/// `roxmltree_outcome` or `quick_xml_outcome` with the `differential` feature, or any other
+/// parser mapped to `Event`s and passed through `canonical`.
+pub fn differential<'i>(
+    corpus: impl IntoIterator<Item=&'i str>,
+    left: impl Fn(&str) -> Outcome,
+    right: impl Fn(&str) -> Outcome,
+) -> Vec<Divergence> {
+    let mut divergences = vec![];
+    for input in corpus {
+        let (a, b) = (left(input), right(input));
+        let divergence = match (&a, &b) {
+            (Ok(a), Ok(b)) => (0..a.len().max(b.len()))
+                .find(|&index| a.get(index) != b.get(index))
+                .map(|index| (index, Ok(a.get(index).cloned()), Ok(b.get(index).cloned()))),
+            (Err(_), Err(_)) => None,
+            (Ok(events), Err(e)) => Some((events.len(), Ok(None), Err(e.clone()))),
+            (Err(e), Ok(events)) => Some((events.len(), Err(e.clone()), Ok(None))),
+        };
+        if let Some((index, left, right)) = divergence {
+            divergences.push(Divergence { input: input.to_string(), index, left, right });
+        }
+    }
+    divergences
+}
+
+/// One line per event, indented by depth, for diffs a person can read.
+fn render(events: &[Event]) -> Vec<String> {
+    let mut depth = 0;
+    events.iter().map(|event| {
+        if let Event::End { .. } = event {
+            depth -= 1;
+        }
+        let indent = "  ".repeat(depth);
+        match event {
+            Event::Start { name, attributes } => {
+                depth += 1;
+                let attributes: String = attributes.iter().map(|(k, v)| format!(" {}={:?}", k, v)).collect();
+                format!("{}<{}{}>", indent, name, attributes)
+            }
+            Event::End { name } => format!("{}</{}>", indent, name),
+            Event::Text(text) | Event::CData(text) => format!("{}{:?}", indent, text),
+            Event::Comment(text) => format!("{}<!--{}-->", indent, text),
+            Event::ProcessingInstruction { target, data } => format!("{}<?{} {}?>", indent, target, data),
+            Event::Declaration { version, .. } => format!("{}<?xml version=\"1.{}\"?>", indent, version),
+            Event::Doctype(body) => format!("{}<!DOCTYPE {}>", indent, body),
+            Event::Foreign(foreign) => format!("{}{}", indent, foreign.source()),
+        }
+    }).collect()
+}
+
+/// Line diff of `left` against `right` with `-` and `+` markers and a few lines of context
+/// around each change, by longest common subsequence.
+fn diff(left: &[String], right: &[String]) -> String {
+    let (n, m) = (left.len(), right.len());
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if left[i] == right[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && left[i] == right[j] {
+            lines.push((' ', &left[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', &left[i]));
+            i += 1;
+        } else {
+            lines.push(('+', &right[j]));
+            j += 1;
+        }
+    }
+    const CONTEXT: usize = 2;
+    let changed: Vec<usize> = lines.iter().enumerate().filter(|(_, (mark, _))| *mark != ' ').map(|(index, _)| index).collect();
+    let mut out = String::new();
+    let mut last = None;
+    for (index, (mark, line)) in lines.iter().enumerate() {
+        if !changed.iter().any(|&c| c.abs_diff(index) <= CONTEXT) {
+            continue;
+        }
+        if last.is_some_and(|last| last + 1 != index) {
+            out.push_str("  ...\n");
+        }
+        out.push_str(&format!("{} {}\n", mark, line));
+        last = Some(index);
+    }
+    out
+}
+
+fn outcome_or_panic(parser: &Parser, input: &str, what: &str) -> Vec<Event> {
+    let element = parser.complete_element(input)
+        .or_else(|_| parser.complete_document(input).map(|document| document.root))
+        .unwrap_or_else(|e| panic!("{} does not parse ({}):\n{}", what, e, input));
+    let mut events = vec![];
+    flatten(&element, &mut events);
+    canonical(events)
+}
+
+/// Panics with a line diff unless `a` and `b` parse to the same content, ignoring what XML
+/// does not distinguish: attribute order and quoting, references versus literal characters,
+/// CDATA versus text, empty-element tags, declarations and whitespace-only text.
+pub fn assert_semantically_equal(a: &str, b: &str) {
+    let parser = Parser::default();
+    let (left, right) = (outcome_or_panic(&parser, a, "left"), outcome_or_panic(&parser, b, "right"));
+    if left != right {
+        panic!("documents differ (- left, + right):\n{}", diff(&render(&left), &render(&right)));
+    }
+}
+
+/// Panics with a line diff unless `input` survives being parsed by `parser`, written out by
+/// `Writer` and parsed again.
+#[cfg(feature = "write")]
+pub fn assert_roundtrip_with(parser: &Parser, input: &str) {
+    let before = outcome_or_panic(parser, input, "input");
+    let mut writer = crate::writer::Writer::new(vec![]);
+    for event in &before {
+        writer.write_event(event).unwrap_or_else(|e| panic!("cannot write parsed input: {}", e));
+    }
+    writer.flush().expect("writing to a Vec cannot fail");
+    let written = String::from_utf8(writer.into_inner()).expect("the writer emits UTF-8");
+    let after = outcome_or_panic(parser, &written, "written output");
+    if before != after {
+        panic!("round trip changed the document (- parsed, + reparsed):\n{}\nwritten as:\n{}", diff(&render(&before), &render(&after)), written);
+    }
+}
+
+#[cfg(feature = "write")]
+pub fn assert_roundtrip(input: &str) {
+    assert_roundtrip_with(&Parser::default(), input)
+}
+
+fn snapshot_into(element: &Element, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let attributes: String = element.attributes_sorted().into_iter().map(|(k, v)| format!(" {}={:?}", k, v)).collect();
+    if element.children.is_empty() {
+        out.push_str(&format!("{}<{}{}/>\n", indent, element.name, attributes));
+        return;
+    }
+    out.push_str(&format!("{}<{}{}>\n", indent, element.name, attributes));
+    for child in &element.children {
+        match child {
+            Node::CharData(text) => out.push_str(&format!("{}  {:?}\n", indent, text)),
+            Node::Element(child) => snapshot_into(child, depth + 1, out),
+            Node::Comment(text) => out.push_str(&format!("{}  <!--{:?}-->\n", indent, text)),
+            Node::ProcessingInstruction { target, data } => out.push_str(&format!("{}  <?{} {:?}?>\n", indent, target, data)),
+            Node::Foreign(foreign) => out.push_str(&format!("{}  foreign {} {:?}\n", indent, foreign.name(), foreign.source())),
+        }
+    }
+    out.push_str(&format!("{}</{}>\n", indent, element.name));
+}
+
+/// Deterministic dump for snapshot tests (`insta` and the like), one node per line: attributes
+/// sorted, and every text node quoted with escapes, so whitespace differences show up. The
+/// format is for diffing only and is not meant to be parsed back.
+pub fn snapshot(element: &impl AsRef<Element>) -> String {
+    let mut out = String::new();
+    snapshot_into(element.as_ref(), 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn synthetic_documents() {
+        let spec = Synthetic::new().depth(3).breadth(6).text_ratio(1.0).attributes(3);
+        let input = spec.generate();
+        assert_eq!(input, spec.generate());
+        let res = Parser::default().complete_element(&input).unwrap();
+        assert_eq!(res.subtree_stats(), SubtreeStats { depth_below: 3, size: spec.element_count(), max_breadth: 6 });
+        assert_eq!(res.attributes().count(), 3);
+        assert!(!res.text().is_empty());
+        assert_eq!(Synthetic::new().depth(5).breadth(16).element_count(), 1_118_481);
+        assert_eq!(Synthetic::new().depth(0).generate().matches("<n0").count(), 1);
+    }
+
+    #[test]
+    fn tree_and_reader_agree() {
+        let parser = Parser { allow_no_close: vec!["br".to_string()], ..Default::default() };
+        let generated = Synthetic::new().depth(2).breadth(4).generate();
+        let corpus = [
+            generated.as_str(),
+            r#"<a x="1" y='&lt;'>t<![CDATA[c]]>u<!--n--><?p d?><br><b/></a>"#,
+            "<a> <b>1 &gt; 0</b> </a>",
+            "<a></b>",
+            "<a/><b/>",
+        ];
+        let divergences = differential(corpus.iter().copied(), |x| tree_outcome(&parser, x), |x| reader_outcome(&parser, x));
+        assert_eq!(divergences.len(), 1, "{:#?}", divergences);
+        assert_eq!(divergences[0].input, "<a/><b/>");
+        assert_eq!(divergences[0].index, 4);
+        assert!(divergences[0].left.is_err());
+        assert_eq!(divergences[0].right, Ok(None));
+    }
+
+    #[cfg(feature = "write")]
+    #[cfg(feature = "differential")]
+    #[test]
+    fn reference_parsers() {
+        let parser = Parser { normalize_name: keep_name, ..Default::default() };
+        let generated = Synthetic::new().depth(2).breadth(4).generate();
+        let corpus = [
+            generated.as_str(),
+            r#"<a x="1" y='&lt;'>t<![CDATA[c]]>u<!--n--><?p d?><b/></a>"#,
+            r#"<r xmlns="urn:d" xmlns:p="urn:p"><p:i p:k="v" xml:lang="en">1 &gt; 0</p:i></r>"#,
+            "<a>x > y</a>",
+            "<a></b>",
+        ];
+        for reference in [roxmltree_outcome, quick_xml_outcome] {
+            let divergences = differential(corpus.iter().copied(), |x| tree_outcome(&parser, x), reference);
+            assert!(divergences.is_empty(), "{:#?}", divergences);
+        }
+        assert!(differential(["<a/><b/>"], |x| tree_outcome(&parser, x), roxmltree_outcome).is_empty());
+        let divergences = differential(["<a/><b/>"], |x| tree_outcome(&parser, x), quick_xml_outcome);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].index, 4);
+        assert!(divergences[0].left.is_err());
+    }
+
+    #[test]
+    fn golden_helpers() {
+        assert_semantically_equal("<a y='2' x=\"1\"><b>&lt;</b>\n<c/></a>", r#"<a x="1" y="2"><b><![CDATA[<]]></b><c></c></a>"#);
+        #[cfg(feature = "write")]
+        assert_roundtrip(r#"<?xml version="1.0"?><a x="&#9;"><b>1 &amp; 2</b><!--c--><?p d?></a>"#);
+        let failure = std::panic::catch_unwind(|| assert_semantically_equal("<a><b>1</b><c/><d/></a>", "<a><b>2</b><c/><d/></a>"));
+        let message = *failure.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(message, "documents differ (- left, + right):\n  <a>\n    <b>\n-     \"1\"\n+     \"2\"\n    </b>\n    <c>\n");
+    }
+
+    #[test]
+    fn snapshots() {
+        let parser = Parser { whitespace: WhitespacePolicy::Preserve, ..Default::default() };
+        let element = parser.complete_element("<a z=\"1\" b='&quot;'>\n  <b>x\ty</b><c/><!-- n --><?p d?></a>").unwrap();
+        assert_eq!(snapshot(&element), r#"<a b="\"" z="1">
+  "\n  "
+  <b>
+    "x\ty"
+  </b>
+  <c/>
+  <!--" n "-->
+  <?p "d"?>
+</a>
+"#);
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Storage for the character data of an `arena::Tree`. `String` is the default; `Rope` suits
+/// editors, where large text nodes are edited in place and trees are cloned for snapshots.
+pub trait Text: Clone + Default + fmt::Debug + PartialEq {
+    fn from_text(text: &str) -> Self;
+    /// Length in bytes.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn push_to(&self, out: &mut String);
+    /// Replaces a byte range, returning the removed text, or `None` without changing anything
+    /// if the range is out of bounds or splits a character.
+    fn splice(&mut self, range: Range<usize>, replacement: &str) -> Option<String>;
+
+    fn to_text(&self) -> String {
+        let mut out = String::with_capacity(self.len());
+        self.push_to(&mut out);
+        out
+    }
+}
+
+impl Text for String {
+    fn from_text(text: &str) -> String {
+        text.to_string()
+    }
+    fn len(&self) -> usize {
+        String::len(self)
+    }
+    fn push_to(&self, out: &mut String) {
+        out.push_str(self);
+    }
+    fn splice(&mut self, range: Range<usize>, replacement: &str) -> Option<String> {
+        let removed = self.get(range.clone())?.to_string();
+        self.replace_range(range, replacement);
+        Some(removed)
+    }
+}
+
+/// Bytes per rope chunk when text is split; edits only rewrite the chunks they touch.
+const CHUNK: usize = 1024;
+
+/// Text split into shared chunks: an edit copies only the chunks it touches, and a clone
+/// shares every chunk with the original until either is edited.
+#[derive(Debug, Clone, Default)]
+pub struct Rope {
+    chunks: Vec<Rc<str>>,
+    len: usize,
+}
+
+fn chunked(text: &str) -> Vec<Rc<str>> {
+    let mut chunks = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(CHUNK);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(Rc::from(&rest[..end]));
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+impl Rope {
+    pub fn chunks(&self) -> impl Iterator<Item=&str> {
+        self.chunks.iter().map(|chunk| &**chunk)
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+/// Equal when the text is, however it is chunked.
+impl PartialEq for Rope {
+    fn eq(&self, other: &Rope) -> bool {
+        self.len == other.len && self.to_text() == other.to_text()
+    }
+}
+
+impl Eq for Rope {}
+
+impl Text for Rope {
+    fn from_text(text: &str) -> Rope {
+        Rope { chunks: chunked(text), len: text.len() }
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn push_to(&self, out: &mut String) {
+        self.chunks().for_each(|chunk| out.push_str(chunk));
+    }
+    fn splice(&mut self, range: Range<usize>, replacement: &str) -> Option<String> {
+        if range.start > range.end || range.end > self.len {
+            return None;
+        }
+        // The chunks from the one holding `range.start` through the one holding `range.end`.
+        let mut first = 0;
+        let mut region_start = 0;
+        while first < self.chunks.len() && region_start + self.chunks[first].len() <= range.start {
+            region_start += self.chunks[first].len();
+            first += 1;
+        }
+        let mut last = first;
+        let mut offset = region_start;
+        while last < self.chunks.len() && (last == first || offset < range.end) {
+            offset += self.chunks[last].len();
+            last += 1;
+        }
+        let region: String = self.chunks[first..last].concat();
+        let local = range.start - region_start..range.end - region_start;
+        let removed = region.get(local.clone())?.to_string();
+        let edited = format!("{}{}{}", &region[..local.start], replacement, &region[local.end..]);
+        self.chunks.splice(first..last, chunked(&edited));
+        self.len = self.len - removed.len() + replacement.len();
+        Some(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::*;
+
+    #[test]
+    fn rope_edits() {
+        let source: String = (0..3000).map(|i| if i % 7 == 0 { 'é' } else { 'a' }).collect();
+        let mut rope = Rope::from_text(&source);
+        let mut string = source.clone();
+        let shared = rope.clone();
+        assert!(rope.chunks().count() > 2);
+        for (range, replacement) in [(0..0, "<"), (1020..1030, ""), (2000..2000, "xyz"), (5..1500, "mid"), (3..3, "")] {
+            assert_eq!(rope.splice(range.clone(), replacement), string.splice(range, replacement));
+        }
+        assert_eq!(rope.to_text(), string);
+        assert_eq!(rope.len(), string.len());
+        assert_eq!(rope.splice(0..string.len() + 1, "x"), None);
+        assert_eq!(Rope::from_text("é").splice(1..2, "x"), None);
+        assert_eq!(shared.to_text(), source);
+        let mut pushed = Rope::from_text("a");
+        pushed.splice(1..1, "b");
+        assert_eq!(pushed, Rope::from_text("ab"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::ops::Range;
+
+use crate::{doctype_body, name_char, name_start_char, reference};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// `<` opening a start tag.
+    TagOpen,
+    /// `</` opening an end tag.
+    EndTagOpen,
+    /// `>` closing a tag.
+    TagClose,
+    /// `/>` closing a self-closing tag.
+    EmptyTagClose,
+    ElementName,
+    AttributeName,
+    Equals,
+    /// An attribute value including its quotes.
+    AttributeValue,
+    Whitespace,
+    Text,
+    /// An entity or character reference such as `&amp;` or `&#10;`.
+    Reference,
+    /// A whole `<!-- -->` comment.
+    Comment,
+    /// A whole `<![CDATA[ ]]>` section.
+    CData,
+    /// A whole `<?...?>`, including the XML declaration.
+    ProcessingInstruction,
+    /// A whole `<!DOCTYPE ...>`, including any internal subset.
+    Doctype,
+    /// Bytes that fit nowhere, such as an unterminated comment or a stray `<`.
+    Error,
+}
+
+/// Lossless tokenizer for editors: consecutive tokens cover every byte of the input exactly
+/// once, whitespace and punctuation included, and malformed input never stops it.
+pub struct Tokens<'a> {
+    input: &'a str,
+    position: usize,
+    in_tag: bool,
+    expect_name: bool,
+}
+
+pub fn tokens(input: &str) -> Tokens<'_> {
+    Tokens {
+        input,
+        position: 0,
+        in_tag: false,
+        expect_name: false,
+    }
+}
+
+/// Tokens of `input` from byte `position`, which must be outside any tag.
+pub(crate) fn tokens_from(input: &str, position: usize) -> Tokens<'_> {
+    Tokens { position, ..tokens(input) }
+}
+
+/// Length of a construct running from `open` to `close`, or `None` if it is unterminated.
+fn delimited(rest: &str, open: &str, close: &str) -> Option<usize> {
+    rest[open.len()..].find(close).map(|end| open.len() + end + close.len())
+}
+
+fn run(rest: &str, predicate: impl Fn(char) -> bool) -> usize {
+    rest.find(|ch| !predicate(ch)).unwrap_or(rest.len())
+}
+
+impl<'a> Tokens<'a> {
+    fn content(&mut self, rest: &str) -> (TokenKind, usize) {
+        let whole = |length: Option<usize>, kind| match length {
+            Some(length) => (kind, length),
+            None => (TokenKind::Error, rest.len()),
+        };
+        if rest.starts_with("<!--") {
+            return whole(delimited(rest, "<!--", "-->"), TokenKind::Comment);
+        }
+        if rest.starts_with("<![CDATA[") {
+            return whole(delimited(rest, "<![CDATA[", "]]>"), TokenKind::CData);
+        }
+        if let Some(body) = rest.strip_prefix("<!DOCTYPE") {
+            let length = doctype_body(body).ok().map(|(after, _)| rest.len() - after.len());
+            return whole(length, TokenKind::Doctype);
+        }
+        if rest.starts_with("<?") {
+            return whole(delimited(rest, "<?", "?>"), TokenKind::ProcessingInstruction);
+        }
+        if rest.starts_with("</") {
+            self.in_tag = true;
+            self.expect_name = true;
+            return (TokenKind::EndTagOpen, 2);
+        }
+        if let Some(after) = rest.strip_prefix('<') {
+            if after.starts_with(name_start_char) {
+                self.in_tag = true;
+                self.expect_name = true;
+                return (TokenKind::TagOpen, 1);
+            }
+            return (TokenKind::Error, 1);
+        }
+        if rest.starts_with('&') {
+            return match reference(rest) {
+                Ok((after, _)) => (TokenKind::Reference, rest.len() - after.len()),
+                Err(_) => (TokenKind::Text, 1),
+            };
+        }
+        let length = memchr::memchr2(b'<', b'&', rest.as_bytes()).unwrap_or(rest.len());
+        if rest[..length].trim().is_empty() {
+            (TokenKind::Whitespace, length)
+        } else {
+            (TokenKind::Text, length)
+        }
+    }
+
+    fn tag(&mut self, rest: &str) -> (TokenKind, usize) {
+        let first = rest.chars().next().unwrap_or_default();
+        if first.is_whitespace() {
+            return (TokenKind::Whitespace, run(rest, char::is_whitespace));
+        }
+        if first == '>' || rest.starts_with("/>") {
+            self.in_tag = false;
+            return if first == '>' { (TokenKind::TagClose, 1) } else { (TokenKind::EmptyTagClose, 2) };
+        }
+        if first == '=' {
+            return (TokenKind::Equals, 1);
+        }
+        if first == '"' || first == '\'' {
+            return match rest[1..].find(first) {
+                Some(end) => (TokenKind::AttributeValue, end + 2),
+                None => {
+                    self.in_tag = false;
+                    (TokenKind::Error, rest.len())
+                }
+            };
+        }
+        // A name starting with a digit, `-` or `.`, which the parser rejects.
+        if name_char(first) && !name_start_char(first) {
+            return (TokenKind::Error, run(rest, name_char));
+        }
+        if name_char(first) {
+            let kind = if std::mem::take(&mut self.expect_name) { TokenKind::ElementName } else { TokenKind::AttributeName };
+            return (kind, run(rest, name_char));
+        }
+        if first == '<' {
+            // An unclosed tag; let content handling pick up the next construct.
+            self.in_tag = false;
+            return self.content(rest);
+        }
+        (TokenKind::Error, first.len_utf8())
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (TokenKind, Range<usize>);
+
+    fn next(&mut self) -> Option<(TokenKind, Range<usize>)> {
+        let rest = &self.input[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (kind, length) = if self.in_tag { self.tag(rest) } else { self.content(rest) };
+        let start = self.position;
+        self.position += length;
+        Some((kind, start..self.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tokens::*;
+
+    #[test]
+    fn lossless_tokens() {
+        let input = "<?xml version=\"1.0\"?>\n<a x = 'y'>t &amp; &bogus <!--c--><![CDATA[<z>]]><b/></a >< é";
+        let tokens: Vec<(TokenKind, &str)> = tokens(input).map(|(kind, range)| (kind, &input[range])).collect();
+        use TokenKind::*;
+        assert_eq!(tokens, vec![
+            (ProcessingInstruction, "<?xml version=\"1.0\"?>"), (Whitespace, "\n"),
+            (TagOpen, "<"), (ElementName, "a"), (Whitespace, " "), (AttributeName, "x"), (Whitespace, " "),
+            (Equals, "="), (Whitespace, " "), (AttributeValue, "'y'"), (TagClose, ">"),
+            (Text, "t "), (Reference, "&amp;"), (Whitespace, " "), (Text, "&"), (Text, "bogus "),
+            (Comment, "<!--c-->"), (CData, "<![CDATA[<z>]]>"),
+            (TagOpen, "<"), (ElementName, "b"), (EmptyTagClose, "/>"),
+            (EndTagOpen, "</"), (ElementName, "a"), (Whitespace, " "), (TagClose, ">"),
+            (Error, "<"), (Text, " é"),
+        ]);
+
+        let input = "<a 1x='y'><1b/></-c></a>";
+        let invalid: Vec<(TokenKind, &str)> = super::tokens(input).map(|(kind, range)| (kind, &input[range])).collect();
+        assert_eq!(invalid, vec![
+            (TagOpen, "<"), (ElementName, "a"), (Whitespace, " "), (Error, "1x"), (Equals, "="),
+            (AttributeValue, "'y'"), (TagClose, ">"),
+            (Error, "<"), (Text, "1b/>"),
+            (EndTagOpen, "</"), (Error, "-c"), (TagClose, ">"),
+            (EndTagOpen, "</"), (ElementName, "a"), (TagClose, ">"),
+        ]);
+    }
+
+    #[test]
+    fn tokens_cover_malformed_input() {
+        for input in ["<a b=\"unterminated", "<!-- open", "<a <b>", "<!DOCTYPE x [", "<a\u{7}>"] {
+            let mut end = 0;
+            for (_, range) in tokens(input) {
+                assert_eq!(range.start, end, "{}", input);
+                assert!(range.end > range.start);
+                end = range.end;
+            }
+            assert_eq!(end, input.len());
+        }
+    }
+}
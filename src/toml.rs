@@ -0,0 +1,90 @@
+use toml::value::{Table, Value};
+
+use crate::config::{self, ConfigValue, ConvertError, Shape};
+use crate::Element;
+
+impl ConfigValue for Value {
+    fn null() -> Option<Value> {
+        None
+    }
+    fn string(text: String) -> Value {
+        Value::String(text)
+    }
+    fn list(values: Vec<Value>) -> Value {
+        Value::Array(values)
+    }
+    fn map(entries: Vec<(String, Value)>) -> Value {
+        Value::Table(entries.into_iter().collect::<Table>())
+    }
+    fn shape(&self) -> Result<Shape<'_, Value>, ConvertError> {
+        Ok(match self {
+            Value::String(text) => Shape::Text(text.clone()),
+            Value::Integer(value) => Shape::Text(value.to_string()),
+            Value::Float(value) => Shape::Text(value.to_string()),
+            Value::Boolean(value) => Shape::Text(value.to_string()),
+            Value::Datetime(value) => Shape::Text(value.to_string()),
+            Value::Array(values) => Shape::List(values.iter().collect()),
+            Value::Table(entries) => Shape::Map(entries.iter().map(|(key, value)| (key.clone(), value)).collect()),
+        })
+    }
+}
+
+/// `element` as a TOML table: see `json::to_json` for the mapping. TOML has no null, so
+/// `xsi:nil` stays an attribute, and an element with only text becomes a `#text` key. The
+/// root's own name is not kept; `from_toml_value` takes it back as an argument.
+pub fn to_toml_value(element: &Element) -> Value {
+    config::from_element(element, true)
+}
+
+/// The element named `root` that `value` maps back to. Numbers, booleans and dates become
+/// their text.
+pub fn from_toml_value(value: &Value, root: &str) -> Result<Element, ConvertError> {
+    config::to_element(value, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::toml::*;
+
+    #[test]
+    fn toml_round_trip() {
+        let res = Parser::default()
+            .complete_element(r#"<config version="2"><name>web "main"</name><server port="80"><tls><cert>a.pem</cert></tls></server><server port="81"/><tag>a</tag><tag>b</tag><proxy xsi:nil="true"/></config>"#)
+            .unwrap();
+        let value = to_toml_value(&res);
+        assert_eq!(::toml::to_string(&value).unwrap(), concat!(
+            "\"@version\" = \"2\"\n",
+            "name = \"web \\\"main\\\"\"\n",
+            "tag = [\"a\", \"b\"]\n",
+            "\n[[server]]\n",
+            "\"@port\" = \"80\"\n",
+            "\n[server.tls]\n",
+            "cert = \"a.pem\"\n",
+            "\n[[server]]\n",
+            "\"@port\" = \"81\"\n",
+            "\n[proxy]\n",
+            "\"@xsi:nil\" = \"true\"\n",
+        ));
+        let back = from_toml_value(&value, "config").unwrap();
+        assert_eq!(to_toml_value(&back), value);
+        assert!(back.children().elem_name("proxy")[0].is_nil());
+    }
+
+    #[test]
+    fn toml_input() {
+        let input = "title = 'C:\\path'\nport = 8080\nratio = 0.5\nhosts = [\"a\", \"b\"]\nstarted = 1979-05-27T07:32:00Z\n\n[[users]]\nid = 1\n[[users]]\nid = 2\n[users.prefs]\ndark = true\n";
+        let value: ::toml::Value = ::toml::from_str(input).unwrap();
+        let res = from_toml_value(&value, "service").unwrap();
+        let text = |name: &str| res.children().elem_name(name).iter().map(|x| x.text()).collect::<Vec<_>>();
+        assert_eq!(text("title"), ["C:\\path"]);
+        assert_eq!(text("port"), ["8080"]);
+        assert_eq!(text("ratio"), ["0.5"]);
+        assert_eq!(text("hosts"), ["a", "b"]);
+        assert_eq!(text("started"), ["1979-05-27T07:32:00Z"]);
+        assert_eq!(text("users"), ["1", "2true"]);
+
+        let value: ::toml::Value = ::toml::from_str("\"two words\" = 1").unwrap();
+        assert!(from_toml_value(&value, "x").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// One parser decision. Offsets are bytes from the start of the input given to the entry
+/// point (`complete_*` or `documents`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Trace {
+    Open { name: String, offset: usize },
+    Close { name: String, offset: usize },
+    /// A self-closing tag, `<name/>`.
+    Empty { name: String, offset: usize },
+    /// A start tag closed with `>` but treated as complete because of `allow_no_close`.
+    Void { name: String, offset: usize },
+    Mismatch { expected: String, found: String, offset: usize },
+    /// A `<` kept as text in lenient mode.
+    StrayAngleBracket { offset: usize },
+    DuplicateAttribute { name: String, offset: usize },
+}
+
+type Sink = dyn Fn(&Trace) + Send + Sync;
+
+/// Receives `Trace` events from a `Parser`. Tracing one parser from several threads at once
+/// interleaves the events and garbles their offsets.
+#[derive(Clone)]
+pub struct TraceSink {
+    sink: Arc<Sink>,
+    base: Arc<AtomicUsize>,
+}
+
+impl fmt::Debug for TraceSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TraceSink")
+    }
+}
+
+impl TraceSink {
+    pub fn new(sink: impl Fn(&Trace) + Send + Sync + 'static) -> TraceSink {
+        TraceSink {
+            sink: Arc::new(sink),
+            base: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn start(&self, input: &str) {
+        self.base.store(input.as_ptr() as usize, Ordering::Relaxed);
+    }
+
+    pub(crate) fn emit(&self, at: &str, event: impl FnOnce(usize) -> Trace) {
+        let offset = (at.as_ptr() as usize).wrapping_sub(self.base.load(Ordering::Relaxed));
+        (self.sink)(&event(offset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::*;
+    use crate::trace::*;
+
+    #[test]
+    fn trace_decisions() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        let parser = Parser {
+            allow_no_close: vec!["br".to_string()],
+            lenient: true,
+            trace: Some(TraceSink::new(move |event| sink.lock().unwrap().push(event.clone()))),
+            ..Default::default()
+        };
+        assert!(parser.complete_element("<p>1 < 2<br><i/></q>").is_err());
+        let name = |x: &str| x.to_string();
+        assert_eq!(*events.lock().unwrap(), vec![
+            Trace::Open { name: name("p"), offset: 0 },
+            Trace::StrayAngleBracket { offset: 5 },
+            Trace::Open { name: name("br"), offset: 8 },
+            Trace::Void { name: name("br"), offset: 8 },
+            Trace::Open { name: name("i"), offset: 12 },
+            Trace::Empty { name: name("i"), offset: 12 },
+            Trace::Mismatch { expected: name("p"), found: name("q"), offset: 16 },
+        ]);
+    }
+}
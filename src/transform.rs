@@ -0,0 +1,188 @@
+use std::fmt;
+use std::io;
+
+use crate::reader::{Event, ReadError, Reader};
+use crate::writer::Writer;
+
+#[derive(Debug)]
+pub enum TransformError {
+    Read(ReadError),
+    Write(io::Error),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::Read(e) => write!(f, "read error: {}", e),
+            TransformError::Write(e) => write!(f, "write error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+impl From<ReadError> for TransformError {
+    fn from(e: ReadError) -> Self {
+        TransformError::Read(e)
+    }
+}
+
+impl From<io::Error> for TransformError {
+    fn from(e: io::Error) -> Self {
+        TransformError::Write(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filtered {
+    Emit(Event),
+    /// Drops the event. On a `Start`, drops only the element's tags: its content moves up into
+    /// the parent and its `End` is dropped too. An `End` whose start tag was written is always
+    /// written, so the output stays balanced.
+    Skip,
+    /// On a `Start` event, drops that element and everything up to its matching `End`.
+    SkipSubtree,
+}
+
+/// A chain of event filters run between a `Reader` and a `Writer`. Each event passes through
+/// the filters in order; the first one to skip it stops the chain.
+#[derive(Default)]
+pub struct Pipeline<'f> {
+    filters: Vec<Box<dyn FnMut(Event) -> Filtered + 'f>>,
+}
+
+impl<'f> Pipeline<'f> {
+    pub fn new() -> Pipeline<'f> {
+        Pipeline::default()
+    }
+
+    pub fn filter(mut self, filter: impl FnMut(Event) -> Filtered + 'f) -> Pipeline<'f> {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn drop_elements(self, name: &str) -> Pipeline<'f> {
+        let name = name.to_string();
+        self.filter(move |event| match event {
+            Event::Start { name: ref start, .. } if start.eq_ignore_ascii_case(&name) => Filtered::SkipSubtree,
+            event => Filtered::Emit(event),
+        })
+    }
+
+    pub fn rename_elements(self, from: &str, to: &str) -> Pipeline<'f> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.filter(move |event| Filtered::Emit(match event {
+            Event::Start { name, attributes } if name.eq_ignore_ascii_case(&from) => Event::Start { name: to.clone(), attributes },
+            Event::End { name } if name.eq_ignore_ascii_case(&from) => Event::End { name: to.clone() },
+            event => event,
+        }))
+    }
+
+    /// Lets `f` edit the attributes of every start tag, given the element name.
+    pub fn map_attributes(self, mut f: impl FnMut(&str, &mut Vec<(String, String)>) + 'f) -> Pipeline<'f> {
+        self.filter(move |event| Filtered::Emit(match event {
+            Event::Start { name, mut attributes } => {
+                f(&name, &mut attributes);
+                Event::Start { name, attributes }
+            }
+            event => event,
+        }))
+    }
+
+    pub fn run<W: io::Write>(mut self, reader: Reader, writer: &mut Writer<W>) -> Result<(), TransformError> {
+        let mut skipping = 0usize;
+        // For each open element, whether its start tag was skipped.
+        let mut unwrapped: Vec<bool> = vec![];
+        'events: for event in reader {
+            let mut event = event?;
+            if skipping > 0 {
+                match event {
+                    Event::Start { .. } => skipping += 1,
+                    Event::End { .. } => skipping -= 1,
+                    _ => {}
+                }
+                continue;
+            }
+            let is_start = matches!(event, Event::Start { .. });
+            let is_end = matches!(event, Event::End { .. });
+            if is_end && unwrapped.pop().unwrap_or(false) {
+                continue;
+            }
+            for filter in &mut self.filters {
+                event = match filter(event) {
+                    Filtered::Emit(event) => event,
+                    Filtered::Skip if is_start => {
+                        unwrapped.push(true);
+                        continue 'events;
+                    }
+                    Filtered::SkipSubtree if is_start => {
+                        skipping = 1;
+                        continue 'events;
+                    }
+                    Filtered::Skip | Filtered::SkipSubtree if is_end => {
+                        writer.end()?;
+                        continue 'events;
+                    }
+                    Filtered::Skip | Filtered::SkipSubtree => continue 'events,
+                };
+            }
+            if is_start {
+                unwrapped.push(false);
+            }
+            writer.write_event(&event)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::reader::*;
+    use crate::transform::*;
+
+    #[test]
+    fn pipeline() {
+        let parser = Parser::default();
+        let input = r#"<page><script>x</script><b id="1">bold <script>y</script></b><i secret="s" keep="k"/></page>"#;
+        let mut writer = Writer::new(vec![]);
+        Pipeline::new()
+            .drop_elements("script")
+            .rename_elements("b", "strong")
+            .map_attributes(|_, attributes| attributes.retain(|(key, _)| key != "secret"))
+            .filter(|event| match event {
+                Event::Text(text) => Filtered::Emit(Event::Text(text.to_uppercase())),
+                event => Filtered::Emit(event),
+            })
+            .run(Reader::new(&parser, input), &mut writer)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            r#"<page><strong id="1">BOLD </strong><i keep="k"/></page>"#
+        );
+    }
+
+    #[test]
+    fn skipped_tags() {
+        let parser = Parser::default();
+        let mut writer = Writer::new(vec![]);
+        Pipeline::new()
+            .filter(|event| match event {
+                Event::Start { ref name, .. } if name == "b" => Filtered::Skip,
+                Event::End { ref name } if name == "d" => Filtered::Skip,
+                event => Filtered::Emit(event),
+            })
+            .run(Reader::new(&parser, "<a><b><c/></b><d>x</d></a>"), &mut writer)
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"<a><c/><d>x</d></a>");
+    }
+
+    #[test]
+    fn pipeline_read_error() {
+        let parser = Parser::default();
+        let mut writer = Writer::new(vec![]);
+        let res = Pipeline::new().run(Reader::new(&parser, "<a></b>"), &mut writer);
+        assert!(matches!(res, Err(TransformError::Read(_))));
+    }
+}
@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+
+use crate::arena::{ElementHandle, NodeData};
+use crate::borrowed::{BorrowedElement, BorrowedNode};
+use crate::foreign::Foreign;
+use crate::text::Text;
+use crate::view::{ElementRef, NodeRef};
+use crate::{Element, NameMatch, Node};
+
+/// One child of an `XmlElement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlNode<'a, E> {
+    /// Borrowed unless the representation stores text in pieces, as a `Rope` does.
+    CharData(Cow<'a, str>),
+    Element(E),
+    Comment(&'a str),
+    ProcessingInstruction { target: &'a str, data: &'a str },
+    /// Borrowed except from a `BorrowedElement`, whose foreign elements keep only a slice of
+    /// the input.
+    Foreign(Cow<'a, Foreign>),
+}
+
+/// Read-only access to an element of any of the crate's trees: `&Element`, `ElementRef`,
+/// `&BorrowedElement` and arena `ElementHandle`s. Code written against it, such as `find_all`
+/// or `Writer::write_tree`, works with whichever representation the caller chose.
+pub trait XmlElement<'a>: Copy {
+    fn name(self) -> &'a str;
+    fn attribute(self, name: &str) -> Option<&'a str>;
+    /// In no particular order.
+    fn attributes(self) -> Vec<(&'a str, &'a str)>;
+    fn children(self) -> Vec<XmlNode<'a, Self>>;
+
+    fn attributes_sorted(self) -> Vec<(&'a str, &'a str)> {
+        let mut attributes = self.attributes();
+        attributes.sort();
+        attributes
+    }
+}
+
+impl<'a> XmlElement<'a> for &'a Element {
+    fn name(self) -> &'a str {
+        &self.name
+    }
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        Element::attribute(self, name)
+    }
+    fn attributes(self) -> Vec<(&'a str, &'a str)> {
+        self.attributes.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect()
+    }
+    fn children(self) -> Vec<XmlNode<'a, &'a Element>> {
+        self.children.iter().map(|node| match node {
+            Node::CharData(data) => XmlNode::CharData(Cow::Borrowed(data)),
+            Node::Element(element) => XmlNode::Element(element),
+            Node::Comment(text) => XmlNode::Comment(text),
+            Node::ProcessingInstruction { target, data } => XmlNode::ProcessingInstruction { target, data },
+            Node::Foreign(foreign) => XmlNode::Foreign(Cow::Borrowed(foreign)),
+        }).collect()
+    }
+}
+
+impl<'a> XmlElement<'a> for ElementRef<'a> {
+    fn name(self) -> &'a str {
+        ElementRef::name(&self)
+    }
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        ElementRef::attribute(&self, name)
+    }
+    fn attributes(self) -> Vec<(&'a str, &'a str)> {
+        ElementRef::attributes(&self).collect()
+    }
+    fn children(self) -> Vec<XmlNode<'a, ElementRef<'a>>> {
+        ElementRef::children(&self).map(|node| match node {
+            NodeRef::CharData(data) => XmlNode::CharData(Cow::Borrowed(data)),
+            NodeRef::Element(element) => XmlNode::Element(element),
+            NodeRef::Comment(text) => XmlNode::Comment(text),
+            NodeRef::ProcessingInstruction { target, data } => XmlNode::ProcessingInstruction { target, data },
+            NodeRef::Foreign(foreign) => XmlNode::Foreign(Cow::Borrowed(foreign)),
+        }).collect()
+    }
+}
+
+impl<'a, 'b: 'a> XmlElement<'a> for &'a BorrowedElement<'b> {
+    fn name(self) -> &'a str {
+        &self.name
+    }
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        BorrowedElement::attribute(self, name).map(|value| &**value)
+    }
+    fn attributes(self) -> Vec<(&'a str, &'a str)> {
+        self.attributes.iter().map(|(key, value)| (&**key, &**value)).collect()
+    }
+    fn children(self) -> Vec<XmlNode<'a, &'a BorrowedElement<'b>>> {
+        self.children.iter().map(|node| match node {
+            BorrowedNode::CharData(data) => XmlNode::CharData(Cow::Borrowed(data)),
+            BorrowedNode::Element(element) => XmlNode::Element(element),
+            BorrowedNode::Comment(text) => XmlNode::Comment(text),
+            BorrowedNode::ProcessingInstruction { target, data } => XmlNode::ProcessingInstruction { target, data },
+            BorrowedNode::Foreign(foreign) => XmlNode::Foreign(Cow::Owned(foreign.to_foreign())),
+        }).collect()
+    }
+}
+
+impl<'a, T: Text> XmlElement<'a> for ElementHandle<'a, T> {
+    fn name(self) -> &'a str {
+        match &self.node().data {
+            NodeData::Element { name, .. } => name,
+            _ => unreachable!("handles only point at elements"),
+        }
+    }
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        match &self.node().data {
+            NodeData::Element { attributes, .. } => attributes.get(name).map(String::as_str),
+            _ => None,
+        }
+    }
+    fn attributes(self) -> Vec<(&'a str, &'a str)> {
+        match &self.node().data {
+            NodeData::Element { attributes, .. } => attributes.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect(),
+            _ => vec![],
+        }
+    }
+    fn children(self) -> Vec<XmlNode<'a, ElementHandle<'a, T>>> {
+        let tree = self.tree();
+        self.node().children().iter().filter_map(|&id| Some(match &tree.get(id)?.data {
+            NodeData::Element { .. } => XmlNode::Element(tree.handle(id)?),
+            NodeData::CharData(data) => XmlNode::CharData(Cow::Owned(data.to_text())),
+            NodeData::Comment(text) => XmlNode::Comment(text),
+            NodeData::ProcessingInstruction { target, data } => XmlNode::ProcessingInstruction { target, data },
+            NodeData::Foreign(foreign) => XmlNode::Foreign(Cow::Borrowed(foreign)),
+        })).collect()
+    }
+}
+
+/// The concatenated character data below `element`, like `Element::text`.
+pub fn text<'a, E: XmlElement<'a>>(element: E) -> String {
+    let mut out = String::new();
+    for child in element.children() {
+        match child {
+            XmlNode::CharData(data) => out.push_str(&data),
+            XmlNode::Element(child) => out.push_str(&text(child)),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Every element at or below `element` whose name matches, in document order.
+pub fn find_all<'a, 'n, E: XmlElement<'a>>(element: E, name: impl Into<NameMatch<'n>>) -> Vec<E> {
+    let name = name.into();
+    let mut found = vec![];
+    let mut stack = vec![element];
+    while let Some(element) = stack.pop() {
+        if name.matches(element.name()) {
+            found.push(element);
+        }
+        let children = element.children().into_iter().filter_map(|child| match child {
+            XmlNode::Element(child) => Some(child),
+            _ => None,
+        });
+        let start = stack.len();
+        stack.extend(children);
+        stack[start..].reverse();
+    }
+    found
+}
+
+/// Copies any element into an owned `Element`.
+pub fn to_element<'a, E: XmlElement<'a>>(element: E) -> Element {
+    Element {
+        name: element.name().to_string(),
+        attributes: element.attributes().into_iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        children: element.children().into_iter().map(|child| match child {
+            XmlNode::CharData(data) => Node::CharData(data.into_owned()),
+            XmlNode::Element(child) => Node::Element(to_element(child)),
+            XmlNode::Comment(text) => Node::Comment(text.to_string()),
+            XmlNode::ProcessingInstruction { target, data } => Node::ProcessingInstruction { target: target.to_string(), data: data.to_string() },
+            XmlNode::Foreign(foreign) => Node::Foreign(foreign.into_owned()),
+        }).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::arena::Tree;
+    use crate::borrowed::parse_borrowed;
+    use crate::text::Rope;
+    use crate::tree::*;
+
+    fn summary<'a, E: XmlElement<'a>>(root: E) -> (Element, Vec<String>, String) {
+        let items = find_all(root, "item").into_iter().map(|item| item.attribute("id").unwrap_or_default().to_string()).collect();
+        (to_element(root), items, text(root))
+    }
+
+    #[test]
+    fn generic_trees() {
+        let parser = Parser::default();
+        let input = r#"<list n="2"><item id="a">x &amp; y</item><!--c--><group><item id="b"><item id="c">z</item></item></group></list>"#;
+        let owned = parser.complete_element(input).unwrap();
+        let expected = (owned.clone(), vec!["a".to_string(), "b".to_string(), "c".to_string()], "x & yz".to_string());
+        assert_eq!(summary(&owned), expected);
+        assert_eq!(summary(owned.view()), expected);
+        assert_eq!(summary(&parse_borrowed(&parser, input).unwrap()), expected);
+        let tree = Tree::from_element(&owned);
+        assert_eq!(summary(tree.handle(tree.root()).unwrap()), expected);
+        let rope: Tree<Rope> = Tree::from_element_with_storage(&owned);
+        assert_eq!(summary(rope.handle(rope.root()).unwrap()), expected);
+        #[cfg(feature = "write")]
+        {
+            let mut writer = writer::Writer::new(vec![]);
+            writer.write_tree(rope.handle(rope.root()).unwrap()).unwrap();
+            let mut expected = writer::Writer::new(vec![]);
+            expected.write_element(&owned).unwrap();
+            assert_eq!(writer.into_inner(), expected.into_inner());
+        }
+    }
+}
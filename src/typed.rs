@@ -0,0 +1,979 @@
+use std::fmt;
+use std::str::FromStr;
+
+use std::marker::PhantomData;
+
+use crate::reader::{ReadError, Reader, Records};
+use crate::{ns, Element, NameMatch, Node, Parser};
+
+/// Why an element could not be mapped to a type, and where: `path` runs from the element
+/// being mapped down to the offending one, like `order/item[2]/@qty`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeError {
+    pub path: String,
+    pub message: String,
+    /// What was expected and found, for errors raised from an `ExpectError`; see `expect_error`.
+    mismatch: Option<Box<(Expected, Option<String>)>>,
+    /// Further problems found in sibling fields and list items, in document order.
+    pub others: Vec<DeError>,
+}
+
+impl DeError {
+    pub fn new(message: impl Into<String>) -> DeError {
+        DeError {
+            path: String::new(),
+            message: message.into(),
+            mismatch: None,
+            others: vec![],
+        }
+    }
+
+    /// The mismatch this error reports, with its full path, if it is one.
+    pub fn expect_error(&self) -> Option<ExpectError> {
+        let (expected, found) = self.mismatch.as_deref()?.clone();
+        Some(ExpectError { path: self.path.clone(), expected, found })
+    }
+
+    /// Prefixes the path with one more step, as the error travels up to the caller.
+    pub fn within(mut self, step: &str) -> DeError {
+        self.path = if self.path.is_empty() { step.to_string() } else { format!("{}/{}", step, self.path) };
+        self.others = self.others.into_iter().map(|e| e.within(step)).collect();
+        self
+    }
+
+    /// One error holding all of `errors`, or `None` when there are none.
+    pub fn combine(errors: impl IntoIterator<Item=DeError>) -> Option<DeError> {
+        let mut all = errors.into_iter().flat_map(DeError::into_errors);
+        let mut first = all.next()?;
+        first.others = all.collect();
+        Some(first)
+    }
+
+    /// This problem and every other one, each on its own.
+    pub fn into_errors(mut self) -> Vec<DeError> {
+        let others = std::mem::take(&mut self.others);
+        std::iter::once(self).chain(others).collect()
+    }
+}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)?;
+        } else {
+            write!(f, "{}: {}", self.path, self.message)?;
+        }
+        match self.others.len() {
+            0 => Ok(()),
+            1 => f.write_str(" (and 1 more error)"),
+            n => write!(f, " (and {} more errors)", n),
+        }
+    }
+}
+
+impl std::error::Error for DeError {}
+
+/// Something an element was expected to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    Element(String),
+    Attribute(String),
+    /// Text parsing as the named type.
+    Type(String),
+    /// Content rather than `xsi:nil`.
+    Value,
+    /// Exactly one child element of this name.
+    OneElement(String),
+    /// Any of these, as `Fallback` tries them.
+    OneOf(Vec<Expected>),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Element(name) => write!(f, "element {}", name),
+            Expected::Attribute(name) => write!(f, "attribute {}", name),
+            Expected::Type(name) => f.write_str(name),
+            Expected::Value => f.write_str("value"),
+            Expected::OneElement(name) => write!(f, "one element {}", name),
+            Expected::OneOf(all) => {
+                for (index, expected) in all.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(" or ")?;
+                    }
+                    write!(f, "{}", expected)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Characters of a found value kept in messages.
+const SNIPPET_LEN: usize = 40;
+
+/// The tree did not have the shape a mapping expected, phrased for whoever wrote the document:
+/// `order/item[2]/@qty: expected u32, found "two"` or `server: missing element host`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectError {
+    pub path: String,
+    pub expected: Expected,
+    /// A short description of what was there instead, `None` when nothing was.
+    pub found: Option<String>,
+}
+
+impl ExpectError {
+    pub fn missing(expected: Expected) -> ExpectError {
+        ExpectError { path: String::new(), expected, found: None }
+    }
+
+    /// Quotes `value`, cut short if it is long.
+    pub fn found(expected: Expected, value: &str) -> ExpectError {
+        let mut snippet: String = value.chars().take(SNIPPET_LEN).collect();
+        if snippet.len() < value.len() {
+            snippet.push('…');
+        }
+        ExpectError::found_other(expected, &format!("{:?}", snippet))
+    }
+
+    /// Describes what was found in words, such as `nil element`.
+    pub fn found_other(expected: Expected, found: &str) -> ExpectError {
+        ExpectError { path: String::new(), expected, found: Some(found.to_string()) }
+    }
+
+    fn message(&self) -> String {
+        match &self.found {
+            Some(found) => format!("expected {}, found {}", self.expected, found),
+            None => format!("missing {}", self.expected),
+        }
+    }
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for ExpectError {}
+
+impl From<ExpectError> for DeError {
+    fn from(e: ExpectError) -> DeError {
+        DeError {
+            message: e.message(),
+            path: e.path,
+            mismatch: Some(Box::new((e.expected, e.found))),
+            others: vec![],
+        }
+    }
+}
+
+impl From<ReadError> for DeError {
+    fn from(e: ReadError) -> DeError {
+        DeError::new(e.to_string())
+    }
+}
+
+/// Types that can be built from an element, by hand, with the helpers in this module or with
+/// `xml_struct!`.
+pub trait FromElement: Sized {
+    fn from_element(element: &Element) -> Result<Self, DeError>;
+
+    /// The value for a child element that is not there at all, if the type has one.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+/// Types that can be read from an attribute value or from text; `None` is an absent attribute.
+pub trait FromValue: Sized {
+    fn from_value(value: Option<&str>) -> Result<Self, DeError>;
+}
+
+impl FromValue for String {
+    fn from_value(value: Option<&str>) -> Result<String, DeError> {
+        value.map(str::to_string).ok_or_else(|| ExpectError::missing(Expected::Value).into())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Option<&str>) -> Result<Option<T>, DeError> {
+        value.map(|value| T::from_value(Some(value))).transpose()
+    }
+}
+
+fn not_nil(element: &Element) -> Result<(), DeError> {
+    if element.is_nil() {
+        Err(ExpectError::found_other(Expected::Value, "nil element").into())
+    } else {
+        Ok(())
+    }
+}
+
+impl FromElement for String {
+    fn from_element(element: &Element) -> Result<String, DeError> {
+        not_nil(element)?;
+        Ok(element.text())
+    }
+}
+
+/// `None` for an `xsi:nil` element rather than an empty value.
+impl<T: FromElement> FromElement for Option<T> {
+    fn from_element(element: &Element) -> Result<Option<T>, DeError> {
+        if element.is_nil() {
+            Ok(None)
+        } else {
+            T::from_element(element).map(Some)
+        }
+    }
+
+    fn missing() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+macro_rules! from_element_via_from_str {
+    ($($t:ty),*) => {
+        $(impl FromElement for $t {
+            fn from_element(element: &Element) -> Result<$t, DeError> {
+                not_nil(element)?;
+                parse_value(element.text().trim())
+            }
+        }
+
+        impl FromValue for $t {
+            fn from_value(value: Option<&str>) -> Result<$t, DeError> {
+                parse_value(value.ok_or_else(|| DeError::from(ExpectError::missing(Expected::Value)))?.trim())
+            }
+        })*
+    };
+}
+
+from_element_via_from_str!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, DeError> {
+    value.parse().map_err(|_| ExpectError::found(Expected::Type(std::any::type_name::<T>().to_string()), value).into())
+}
+
+/// Reports the first problem only; see `from_str_all`.
+pub fn from_str<T: FromElement>(parser: &Parser, input: &str) -> Result<T, DeError> {
+    from_str_all(parser, input).map_err(|errors| errors.into_iter().next().expect("at least one error"))
+}
+
+/// Like `from_str`, listing every problem found rather than the first, so a config file can be
+/// fixed in one pass. Types written with `xml_struct!`, `children` and `ErrorCollector` report
+/// all their fields; a hand-written `?` still stops at its first error.
+pub fn from_str_all<T: FromElement>(parser: &Parser, input: &str) -> Result<T, Vec<DeError>> {
+    let element = parser.complete_element(input).map_err(|e| vec![DeError::new(e.to_string())])?;
+    T::from_element(&element).map_err(|e| e.within(&element.name).into_errors())
+}
+
+/// Gathers the errors of a hand-written `FromElement`'s fields so all are reported: `take`
+/// each field's result, then `finish()?` before unwrapping the values.
+#[derive(Debug, Default)]
+pub struct ErrorCollector {
+    errors: Vec<DeError>,
+}
+
+impl ErrorCollector {
+    /// The value, or `None` after recording the error.
+    pub fn take<T>(&mut self, result: Result<T, DeError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+    pub fn finish(self) -> Result<(), DeError> {
+        DeError::combine(self.errors).map_or(Ok(()), Err)
+    }
+}
+
+pub fn attr<T: FromStr>(element: &Element, name: &str) -> Result<T, DeError> {
+    optional_attr(element, name)?.ok_or_else(|| ExpectError::missing(Expected::Attribute(name.to_string())).into())
+}
+
+pub fn optional_attr<T: FromStr>(element: &Element, name: &str) -> Result<Option<T>, DeError> {
+    element.attribute(name).map(|value| parse_value(value).map_err(|e| e.within(&format!("@{}", name)))).transpose()
+}
+
+/// The only child element called `name`.
+pub fn child<T: FromElement>(element: &Element, name: &str) -> Result<T, DeError> {
+    match child_element(element, name, None)? {
+        Some(only) => T::from_element(only).map_err(|e| e.within(name)),
+        None => T::missing().ok_or_else(|| ExpectError::missing(Expected::Element(name.to_string())).into()),
+    }
+}
+
+/// `None` when there is no such child or it is `xsi:nil`.
+pub fn optional_child<T: FromElement>(element: &Element, name: &str) -> Result<Option<T>, DeError> {
+    match child_element(element, name, None)? {
+        Some(only) => Option::<T>::from_element(only).map_err(|e| e.within(name)),
+        None => Ok(None),
+    }
+}
+
+/// Every child element called `name`, in order.
+pub fn children<T: FromElement>(element: &Element, name: &str) -> Result<Vec<T>, DeError> {
+    list(child_elements(element, name, None), name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    Attribute(String),
+    ChildText(String),
+}
+
+/// Where a setting may be written, most specific first, for configs where an attribute
+/// overrides a child element which overrides a default:
+/// `Fallback::attr("timeout").or_child_text("timeout").or_default(30).get(element)`.
+///
+/// A blank or `xsi:nil` child counts as absent; a present but invalid value is an error
+/// rather than a reason to fall through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fallback {
+    sources: Vec<Source>,
+}
+
+impl Fallback {
+    pub fn attr(name: &str) -> Fallback {
+        Fallback { sources: vec![Source::Attribute(name.to_string())] }
+    }
+    pub fn child_text(name: &str) -> Fallback {
+        Fallback { sources: vec![Source::ChildText(name.to_string())] }
+    }
+    pub fn or_attr(mut self, name: &str) -> Fallback {
+        self.sources.push(Source::Attribute(name.to_string()));
+        self
+    }
+    pub fn or_child_text(mut self, name: &str) -> Fallback {
+        self.sources.push(Source::ChildText(name.to_string()));
+        self
+    }
+    pub fn or_default<T: FromStr + Clone>(self, value: T) -> WithDefault<T> {
+        WithDefault { fallback: self, value }
+    }
+
+    /// The first value present, or `None` when no source has one.
+    pub fn optional<T: FromStr>(&self, element: &Element) -> Result<Option<T>, DeError> {
+        for source in &self.sources {
+            match source {
+                Source::Attribute(name) => if let Some(value) = element.attribute(name) {
+                    return parse_value(value).map(Some).map_err(|e| e.within(&format!("@{}", name)));
+                },
+                Source::ChildText(name) => if let Some(child) = child_element(element, name, None)? {
+                    let text = child.text();
+                    if !child.is_nil() && !text.trim().is_empty() {
+                        return parse_value(text.trim()).map(Some).map_err(|e| e.within(name));
+                    }
+                },
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn get<T: FromStr>(&self, element: &Element) -> Result<T, DeError> {
+        self.optional(element)?.ok_or_else(|| {
+            let tried = self.sources.iter().map(|source| match source {
+                Source::Attribute(name) => Expected::Attribute(name.clone()),
+                Source::ChildText(name) => Expected::Element(name.clone()),
+            }).collect();
+            ExpectError::missing(Expected::OneOf(tried)).into()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithDefault<T> {
+    fallback: Fallback,
+    value: T,
+}
+
+impl<T: FromStr + Clone> WithDefault<T> {
+    pub fn get(&self, element: &Element) -> Result<T, DeError> {
+        Ok(self.fallback.optional(element)?.unwrap_or_else(|| self.value.clone()))
+    }
+}
+
+/// Maps every `name` element read from `reader` to a `T`, holding only one record in memory at
+/// a time. Mapping errors carry the record's position, as in `record[3]/@id`, and iteration
+/// continues past them; a read error ends it.
+pub fn from_reader_iter<'p, 'a, T: FromElement>(reader: Reader<'p, 'a>, name: impl Into<NameMatch<'p>>) -> TypedRecords<'p, 'a, T> {
+    TypedRecords {
+        records: reader.split_on(name),
+        count: 0,
+        marker: PhantomData,
+    }
+}
+
+pub struct TypedRecords<'p, 'a, T> {
+    records: Records<'p, 'a>,
+    count: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'p, 'a, T> TypedRecords<'p, 'a, T> {
+    /// See `Records::checkpoint`.
+    pub fn checkpoint(&self) -> crate::reader::Checkpoint {
+        self.records.checkpoint()
+    }
+}
+
+impl<'p, 'a, T: FromElement> Iterator for TypedRecords<'p, 'a, T> {
+    type Item = Result<T, DeError>;
+
+    fn next(&mut self) -> Option<Result<T, DeError>> {
+        let element = match self.records.next()? {
+            Ok(element) => element,
+            Err(e) => return Some(Err(e.into())),
+        };
+        self.count += 1;
+        Some(T::from_element(&element).map_err(|e| e.within(&format!("{}[{}]", element.name, self.count))))
+    }
+}
+
+#[doc(hidden)]
+pub fn attr_value<'e>(element: &'e Element, name: &str, ns: Option<&str>) -> Option<&'e str> {
+    match ns {
+        Some(uri) => element.attr_ns(uri, name),
+        None => element.attribute(name),
+    }
+}
+
+/// Child elements named `name` or, with `ns`, named `prefix:name` for a prefix bound to `ns`
+/// on the parent or the child itself.
+#[doc(hidden)]
+pub fn child_elements<'e>(element: &'e Element, name: &str, ns: Option<&str>) -> Vec<&'e Element> {
+    element.children.iter().filter_map(Node::as_element).filter(|x| match ns {
+        Some(uri) => crate::ns::prefixes_for(&[x, element], uri).iter().any(|prefix| x.name == format!("{}:{}", prefix, name)),
+        None => x.name == name,
+    }).collect()
+}
+
+#[doc(hidden)]
+pub fn child_element<'e>(element: &'e Element, name: &str, ns: Option<&str>) -> Result<Option<&'e Element>, DeError> {
+    match child_elements(element, name, ns).as_slice() {
+        [] => Ok(None),
+        [only] => Ok(Some(only)),
+        many => Err(ExpectError::found_other(Expected::OneElement(name.to_string()), &many.len().to_string()).into()),
+    }
+}
+
+#[doc(hidden)]
+pub fn list<T: FromElement>(elements: Vec<&Element>, name: &str) -> Result<Vec<T>, DeError> {
+    let mut errors = ErrorCollector::default();
+    let items: Vec<Option<T>> = elements.into_iter()
+        .enumerate()
+        .map(|(index, x)| errors.take(T::from_element(x).map_err(|e| e.within(&format!("{}[{}]", name, index + 1)))))
+        .collect();
+    errors.finish()?;
+    Ok(items.into_iter().flatten().collect())
+}
+
+#[doc(hidden)]
+pub fn field<T>(map: impl FnOnce() -> Result<T, DeError>) -> Result<T, DeError> {
+    map()
+}
+
+/// What one `xml_struct!` field reads, so an `other` field can collect everything else.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub enum Consumed<'a> {
+    Attribute(&'a str, Option<&'a str>),
+    Element(&'a str, Option<&'a str>),
+    Nothing,
+}
+
+fn qualified(scope: &[&Element], name: &str, local: &str, ns: Option<&str>) -> bool {
+    match ns {
+        Some(uri) => crate::ns::prefixes_for(scope, uri).iter().any(|prefix| name == format!("{}:{}", prefix, local)),
+        None => name == local,
+    }
+}
+
+/// A copy of `element` with only the attributes and child elements no field reads.
+#[doc(hidden)]
+pub fn unmapped(element: &Element, consumed: &[Consumed<'_>]) -> Element {
+    let attributes = element.attributes.iter()
+        .filter(|(key, _)| !consumed.iter().any(|c| matches!(*c, Consumed::Attribute(local, ns) if qualified(&[element], key, local, ns))))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let children = element.children.iter()
+        .filter(|node| match node {
+            Node::Element(x) => !consumed.iter().any(|c| matches!(*c, Consumed::Element(local, ns) if qualified(&[x, element], &x.name, local, ns))),
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    Element { name: element.name.clone(), attributes, children }
+}
+
+/// Declares a struct and implements `FromElement` for it from per-field annotations:
+///
+/// - `#[xml(attr)]` reads an attribute and `#[xml(text)]` the element's text; fields
+///   without either read the only child element of that name;
+/// - `rename = "..."` uses another attribute or element name than the field's;
+/// - `ns = "..."` matches the attribute or child in that namespace URI instead;
+/// - `default` uses `Default::default()` when the value is absent or the text is blank;
+/// - `flatten` reads a `Vec` from every child element of that name, and `wrapped = "item"`
+///   from the `item` children of the one child of that name, empty when it is absent;
+/// - `other` takes an `Element` holding every attribute and child element no other field
+///   reads, so vendor extensions survive a trip through the struct.
+///
+/// Option fields are `None` when absent. Annotations combine, as in `#[xml(attr, rename =
+/// "max-size", default)]`.
+#[macro_export]
+macro_rules! xml_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[xml($($opt:tt)*)])?
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $crate::typed::FromElement for $name {
+            fn from_element(element: &$crate::Element) -> Result<$name, $crate::typed::DeError> {
+                let consumed = [
+                    $($crate::__xml_field!(consumed element consumed, $ty; [child] [stringify!($field)] [] []; $($($opt)*)?)),*
+                ];
+                let _ = &consumed;
+                // Every field is tried before any is bound, so all their errors are reported.
+                let ($($field,)*) = ($(
+                    $crate::typed::field::<$ty>(|| {
+                        Ok($crate::__xml_field!(value element consumed, $ty; [child] [stringify!($field)] [] []; $($($opt)*)?))
+                    }),
+                )*);
+                let mut __errors = $crate::typed::ErrorCollector::default();
+                $(let $field = __errors.take($field);)*
+                __errors.finish()?;
+                Ok($name {
+                    $($field: $field.expect("every field mapped")),*
+                })
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __xml_field {
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; attr $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [attr] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; text $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [text] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; child $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [child] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; flatten $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [flatten] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; wrapped = $item:literal $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [wrapped $item] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; other $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [other] [$name] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; rename = $new:literal $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [$($kind)+] [$new] [$($ns)?] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; ns = $uri:expr $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [$($kind)+] [$name] [$uri] [$($default)?]; $($($rest)*)?)
+    };
+    ($mode:ident $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?]; default $(, $($rest:tt)*)?) => {
+        $crate::__xml_field!($mode $e $c, $ty; [$($kind)+] [$name] [$($ns)?] [default]; $($($rest)*)?)
+    };
+
+    (value $e:ident $c:ident, $ty:ty; [attr] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {{
+        let value = $crate::typed::attr_value($e, $name, None $(.or(Some($ns)))?);
+        $crate::__xml_field!(@value value, $ty, [$($default)?])
+            .map_err(|e| e.within(&format!("@{}", $name)))?
+    }};
+    (value $e:ident $c:ident, $ty:ty; [text] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {{
+        let text = $e.text();
+        let value = $crate::__xml_field!(@text text, [$($default)?]);
+        $crate::__xml_field!(@value value, $ty, [$($default)?])?
+    }};
+    (value $e:ident $c:ident, $ty:ty; [child] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {{
+        match $crate::typed::child_element($e, $name, None $(.or(Some($ns)))?)? {
+            Some(child) => <$ty as $crate::typed::FromElement>::from_element(child).map_err(|e| e.within($name))?,
+            None => $crate::__xml_field!(@missing $ty, $name, [$($default)?])?,
+        }
+    }};
+    (value $e:ident $c:ident, $ty:ty; [flatten] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::list($crate::typed::child_elements($e, $name, None $(.or(Some($ns)))?), $name)?
+    };
+    (value $e:ident $c:ident, $ty:ty; [wrapped $item:literal] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        match $crate::typed::child_element($e, $name, None $(.or(Some($ns)))?)? {
+            Some(wrapper) => $crate::typed::list($crate::typed::child_elements(wrapper, $item, None $(.or(Some($ns)))?), $item)
+                .map_err(|e| e.within($name))?,
+            None => Vec::new(),
+        }
+    };
+    (value $e:ident $c:ident, $ty:ty; [other] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::unmapped($e, &$c)
+    };
+
+    (consumed $e:ident $c:ident, $ty:ty; [attr] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::Consumed::Attribute($name, None $(.or(Some($ns)))?)
+    };
+    (consumed $e:ident $c:ident, $ty:ty; [text] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::Consumed::Nothing
+    };
+    (consumed $e:ident $c:ident, $ty:ty; [other] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::Consumed::Nothing
+    };
+    (consumed $e:ident $c:ident, $ty:ty; [$($kind:tt)+] [$name:expr] [$($ns:expr)?] [$($default:ident)?];) => {
+        $crate::typed::Consumed::Element($name, None $(.or(Some($ns)))?)
+    };
+
+    (@text $text:ident, [default]) => {
+        Some($text.as_str()).filter(|text| !text.trim().is_empty())
+    };
+    (@text $text:ident, []) => {
+        Some($text.as_str())
+    };
+    (@value $value:ident, $ty:ty, [default]) => {
+        match $value {
+            Some(_) => <$ty as $crate::typed::FromValue>::from_value($value),
+            None => Ok(Default::default()),
+        }
+    };
+    (@value $value:ident, $ty:ty, []) => {
+        <$ty as $crate::typed::FromValue>::from_value($value)
+    };
+    (@missing $ty:ty, $name:expr, [default]) => {
+        Ok::<$ty, $crate::typed::DeError>(Default::default())
+    };
+    (@missing $ty:ty, $name:expr, []) => {
+        <$ty as $crate::typed::FromElement>::missing()
+            .ok_or_else(|| $crate::typed::DeError::from($crate::typed::ExpectError::missing(
+                $crate::typed::Expected::Element($name.to_string()),
+            )))
+    };
+}
+
+type Variant<T> = (String, fn(&Element) -> Result<T, DeError>);
+
+/// Picks how to map an element by the value of a type attribute, `xsi:type` by default, as
+/// SOAP and XSD-derived payloads do for polymorphic content. Type names are matched in full or
+/// by their local part, so `tns:Circle` selects the `Circle` variant.
+///
+/// `xsi:type` is found through an `xmlns` declaration on the element itself and otherwise by
+/// its conventional `xsi` prefix, since an element does not see its ancestors' declarations.
+#[derive(Debug, Clone)]
+pub struct Polymorphic<T> {
+    attribute: Option<String>,
+    variants: Vec<Variant<T>>,
+}
+
+impl<T> Default for Polymorphic<T> {
+    fn default() -> Polymorphic<T> {
+        Polymorphic {
+            attribute: None,
+            variants: vec![],
+        }
+    }
+}
+
+impl<T> Polymorphic<T> {
+    pub fn new() -> Polymorphic<T> {
+        Polymorphic::default()
+    }
+    /// Dispatches on a plain attribute, like `kind`, instead of `xsi:type`.
+    pub fn attribute(mut self, name: &str) -> Polymorphic<T> {
+        self.attribute = Some(name.to_string());
+        self
+    }
+    pub fn variant(mut self, type_name: &str, map: fn(&Element) -> Result<T, DeError>) -> Polymorphic<T> {
+        self.variants.push((type_name.to_string(), map));
+        self
+    }
+
+    fn type_name<'e>(&self, element: &'e Element) -> Option<&'e str> {
+        match &self.attribute {
+            Some(name) => element.attribute(name),
+            None => element.attr_ns(ns::XSI, "type").or_else(|| element.attribute("xsi:type")),
+        }
+    }
+
+    pub fn from_element(&self, element: &Element) -> Result<T, DeError> {
+        let attribute = self.attribute.as_deref().unwrap_or("xsi:type");
+        let type_name = self.type_name(element)
+            .ok_or_else(|| DeError::from(ExpectError::missing(Expected::Attribute(attribute.to_string()))))?;
+        let local = type_name.rsplit(':').next().unwrap_or(type_name);
+        let (_, map) = self.variants.iter()
+            .find(|(name, _)| name == type_name)
+            .or_else(|| self.variants.iter().find(|(name, _)| name == local))
+            .ok_or_else(|| {
+                let known = self.variants.iter().map(|(name, _)| Expected::Type(name.clone())).collect();
+                DeError::from(ExpectError::found(Expected::OneOf(known), type_name)).within(&format!("@{}", attribute))
+            })?;
+        map(element)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::reader::Reader;
+    use crate::typed::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Circle { r: f64 },
+        Square { side: u32 },
+    }
+
+    impl FromElement for Shape {
+        fn from_element(element: &Element) -> Result<Shape, DeError> {
+            Polymorphic::new()
+                .variant("Circle", |e| Ok(Shape::Circle { r: attr(e, "r")? }))
+                .variant("Square", |e| Ok(Shape::Square { side: child(e, "side")? }))
+                .from_element(element)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Drawing {
+        title: String,
+        shapes: Vec<Shape>,
+    }
+
+    impl FromElement for Drawing {
+        fn from_element(element: &Element) -> Result<Drawing, DeError> {
+            Ok(Drawing { title: attr(element, "title")?, shapes: children(element, "shape")? })
+        }
+    }
+
+    #[test]
+    fn nil_elements() {
+        let parser = Parser::default();
+        let element = parser.complete_element(r#"<row xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+            <a xsi:nil="true"/><b>2</b><c xsi:nil="true"/><c>x</c><d xsi:nil="1"></d>
+        </row>"#).unwrap();
+        assert_eq!(optional_child::<u32>(&element, "a"), Ok(None));
+        assert_eq!(optional_child::<u32>(&element, "b"), Ok(Some(2)));
+        assert_eq!(children::<Option<String>>(&element, "c"), Ok(vec![None, Some("x".to_string())]));
+        assert_eq!(child::<String>(&element, "d").unwrap_err().to_string(), "d: expected value, found nil element");
+        assert_eq!(child::<Option<String>>(&element, "d"), Ok(None));
+    }
+
+    xml_struct! {
+        #[derive(Debug, PartialEq)]
+        struct Server {
+            #[xml(attr)]
+            name: String,
+            #[xml(attr, rename = "listen-port", default)]
+            port: u16,
+            #[xml(attr, ns = "urn:ops")]
+            owner: Option<String>,
+            host: String,
+            #[xml(rename = "max-conn", default)]
+            max_connections: u32,
+            #[xml(ns = "urn:ops")]
+            tier: Option<u8>,
+        }
+    }
+
+    xml_struct! {
+        #[derive(Debug, PartialEq)]
+        struct Note {
+            #[xml(text, default)]
+            body: String,
+            #[xml(text)]
+            raw: String,
+        }
+    }
+
+    xml_struct! {
+        #[derive(Debug, PartialEq)]
+        struct Order {
+            #[xml(flatten, rename = "line")]
+            lines: Vec<u32>,
+            #[xml(wrapped = "tag")]
+            tags: Vec<String>,
+        }
+    }
+
+    xml_struct! {
+        #[derive(Debug, PartialEq)]
+        struct Product {
+            #[xml(attr)]
+            sku: String,
+            name: String,
+            #[xml(flatten, rename = "tag")]
+            tags: Vec<String>,
+            #[xml(other)]
+            extra: Element,
+        }
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let parser = Parser::default();
+        let input = r#"<product sku="1" vendor:color="red"><name>n</name><tag>a</tag><vendor:stock>4</vendor:stock></product>"#;
+        let product: Product = from_str(&parser, input).unwrap();
+        assert_eq!(product.tags, vec!["a"]);
+        let expected = parser.complete_element(r#"<product vendor:color="red"><vendor:stock>4</vendor:stock></product>"#).unwrap();
+        assert_eq!(product.extra, expected);
+    }
+
+    #[test]
+    fn list_shapes() {
+        let parser = Parser::default();
+        let order: Order = from_str(&parser, "<order><line>1</line><tags><tag>a</tag><tag>b</tag></tags><line>2</line></order>").unwrap();
+        assert_eq!(order, Order { lines: vec![1, 2], tags: vec!["a".to_string(), "b".to_string()] });
+        assert_eq!(from_str::<Order>(&parser, "<order/>").unwrap(), Order { lines: vec![], tags: vec![] });
+        let err = from_str::<Order>(&parser, "<order><tags><tag/><tags/></tags><line>x</line></order>").unwrap_err();
+        assert_eq!(err.to_string(), "order/line[1]: expected u32, found \"x\"");
+    }
+
+    #[test]
+    fn annotated_structs() {
+        let parser = Parser::default();
+        let server: Server = from_str(&parser, r#"<server name="a" xmlns:o="urn:ops" o:owner="ops-team">
+            <host>example.org</host><o:tier>2</o:tier>
+        </server>"#).unwrap();
+        assert_eq!(server, Server {
+            name: "a".to_string(),
+            port: 0,
+            owner: Some("ops-team".to_string()),
+            host: "example.org".to_string(),
+            max_connections: 0,
+            tier: Some(2),
+        });
+        let server: Server = from_str(&parser, r#"<server name="b" listen-port="80"><host>h</host><max-conn>5</max-conn></server>"#).unwrap();
+        assert_eq!((server.port, server.owner, server.max_connections, server.tier), (80, None, 5, None));
+
+        let err = from_str::<Server>(&parser, r#"<server listen-port="x"><host>h</host></server>"#).unwrap_err();
+        assert_eq!(err.to_string(), "server/@name: missing value");
+        let err = from_str::<Server>(&parser, r#"<server name="c" listen-port="x"><host>h</host></server>"#).unwrap_err();
+        assert_eq!(err.to_string(), "server/@listen-port: expected u16, found \"x\"");
+        let err = from_str::<Server>(&parser, r#"<server name="c"/>"#).unwrap_err();
+        assert_eq!(err.to_string(), "server: missing element host");
+        assert_eq!(from_str::<Note>(&parser, "<note>hi</note>").unwrap().body, "hi");
+        let preserve = Parser { whitespace: WhitespacePolicy::Preserve, ..Default::default() };
+        assert_eq!(from_str::<Note>(&preserve, "<note> </note>").unwrap(), Note { body: String::new(), raw: " ".to_string() });
+    }
+
+    #[test]
+    fn typed_mapping() {
+        let parser = Parser::default();
+        let drawing: Drawing = from_str(&parser, r#"<drawing title="d">
+            <shape xsi:type="g:Circle" r="1.5"/>
+            <shape xmlns:s="http://www.w3.org/2001/XMLSchema-instance" s:type="Square"><side>3</side></shape>
+        </drawing>"#).unwrap();
+        assert_eq!(drawing, Drawing {
+            title: "d".to_string(),
+            shapes: vec![Shape::Circle { r: 1.5 }, Shape::Square { side: 3 }],
+        });
+
+        let err = from_str::<Drawing>(&parser, r#"<drawing title="d"><shape xsi:type="Square"/><shape xsi:type="Hexagon"/></drawing>"#)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "drawing/shape[1]: missing element side");
+        let err = from_str::<Drawing>(&parser, r#"<drawing title="d"><shape xsi:type="Hexagon"/></drawing>"#).unwrap_err();
+        assert_eq!(err.to_string(), "drawing/shape[1]/@xsi:type: expected Circle or Square, found \"Hexagon\"");
+        let err = from_str::<Shape>(&parser, r#"<shape xsi:type="Circle" r="big"/>"#).unwrap_err();
+        assert_eq!(err.to_string(), "shape/@r: expected f64, found \"big\"");
+
+        let by_kind = Polymorphic::new().attribute("kind").variant("n", u8::from_element);
+        let element = parser.complete_element("<v kind='n'> 7 </v>").unwrap();
+        assert_eq!(by_kind.from_element(&element), Ok(7));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        v: String,
+    }
+
+    impl FromElement for Record {
+        fn from_element(element: &Element) -> Result<Record, DeError> {
+            Ok(Record { id: attr(element, "id")?, v: child(element, "v")? })
+        }
+    }
+
+    #[test]
+    fn streamed_records() {
+        let parser = Parser::default();
+        let input = r#"<export><meta/><record id="1"><v>a</v></record><record id="x"><v/></record><record id="3"><v>c</v></record></export>"#;
+        let records: Vec<Result<Record, DeError>> = from_reader_iter(Reader::new(&parser, input), "record").collect();
+        assert_eq!(records[0], Ok(Record { id: 1, v: "a".to_string() }));
+        assert_eq!(records[1].as_ref().unwrap_err().path, "record[2]/@id");
+        assert_eq!(records[2], Ok(Record { id: 3, v: "c".to_string() }));
+
+        let ids: Vec<Result<u32, DeError>> = from_reader_iter(Reader::new(&parser, input), "v").collect();
+        assert_eq!(ids[1].as_ref().unwrap_err().to_string(), "v[2]: expected u32, found \"\"");
+        let broken: Vec<Result<u32, DeError>> = from_reader_iter(Reader::new(&parser, "<a><v>1</v><v>2</w></a>"), "v").collect();
+        assert_eq!(broken.len(), 2);
+        assert!(broken[1].as_ref().unwrap_err().message.starts_with("mismatched"));
+    }
+
+    xml_struct! {
+        #[derive(Debug)]
+        struct Limits {
+            #[xml(attr)]
+            max: u32,
+            #[xml(attr)]
+            min: u32,
+            #[xml(flatten, rename = "port")]
+            ports: Vec<u16>,
+        }
+    }
+
+    #[test]
+    fn all_errors() {
+        let parser = Parser::default();
+        let input = r#"<limits max="x"><port>80</port><port>http</port><port>70000</port></limits>"#;
+        let errors = from_str_all::<Limits>(&parser, input).unwrap_err();
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["limits/@max", "limits/@min", "limits/port[2]", "limits/port[3]"]);
+        assert_eq!(from_str::<Limits>(&parser, input).unwrap_err().to_string(), "limits/@max: expected u32, found \"x\"");
+        let nested = DeError::combine(errors).unwrap().within("config");
+        assert_eq!(nested.to_string(), "config/limits/@max: expected u32, found \"x\" (and 3 more errors)");
+        let limits = from_str_all::<Limits>(&parser, r#"<limits max="1" min="0"><port>80</port></limits>"#).unwrap();
+        assert_eq!((limits.max, limits.min, limits.ports), (1, 0, vec![80]));
+    }
+
+    #[test]
+    fn fallback_chains() {
+        let parser = Parser::default();
+        let timeout = Fallback::attr("timeout").or_child_text("timeout").or_default(30u32);
+        let parse = |input| parser.complete_element(input).unwrap();
+        assert_eq!(timeout.get(&parse(r#"<server timeout="5"><timeout>10</timeout></server>"#)), Ok(5));
+        assert_eq!(timeout.get(&parse("<server><timeout> 10 </timeout></server>")), Ok(10));
+        assert_eq!(timeout.get(&parse("<server><timeout/></server>")), Ok(30));
+        assert_eq!(timeout.get(&parse(r#"<server timeout="soon"/>"#)).unwrap_err().path, "@timeout");
+        let host = Fallback::attr("host").or_child_text("host").or_attr("address");
+        assert_eq!(host.get::<String>(&parse(r#"<server address="h"/>"#)), Ok("h".to_string()));
+        assert_eq!(host.get::<String>(&parse("<server/>")).unwrap_err().message, "missing attribute host or element host or attribute address");
+    }
+
+    #[test]
+    fn expect_errors() {
+        let parser = Parser::default();
+        let order = parser.complete_element(&format!("<order qty='{}'><note/><note/></order>", "9".repeat(50))).unwrap();
+        let e = attr::<u32>(&order, "qty").unwrap_err().within("order").expect_error().unwrap();
+        assert_eq!(e.path, "order/@qty");
+        assert_eq!(e.expected, Expected::Type("u32".to_string()));
+        assert_eq!(e.to_string(), format!("order/@qty: expected u32, found \"{}…\"", "9".repeat(40)));
+        let e = child::<String>(&order, "note").unwrap_err();
+        assert_eq!(e.to_string(), "expected one element note, found 2");
+        assert_eq!(e.expect_error().unwrap().expected, Expected::OneElement("note".to_string()));
+        assert_eq!(DeError::new("by hand").expect_error(), None);
+    }
+}
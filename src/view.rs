@@ -0,0 +1,195 @@
+use crate::foreign::Foreign;
+use crate::links::Fragment;
+use crate::pattern::{Captures, Pattern};
+use crate::{Element, NameMatch, Node, Position, QuerySupport, Select};
+
+/// Read-only handle to an element, for handing query results to code that should not see
+/// (or depend on) the owned representation.
+#[derive(Debug, Clone, Copy)]
+pub struct ElementRef<'a>(&'a Element);
+
+#[derive(Debug, Clone, Copy)]
+pub enum NodeRef<'a> {
+    CharData(&'a str),
+    Element(ElementRef<'a>),
+    Comment(&'a str),
+    ProcessingInstruction { target: &'a str, data: &'a str },
+    Foreign(&'a Foreign),
+}
+
+impl<'a> From<&'a Element> for ElementRef<'a> {
+    fn from(element: &'a Element) -> Self {
+        ElementRef(element)
+    }
+}
+
+impl<'a> From<&'a Node> for NodeRef<'a> {
+    fn from(node: &'a Node) -> Self {
+        match node {
+            Node::CharData(data) => NodeRef::CharData(data),
+            Node::Element(element) => NodeRef::Element(ElementRef(element)),
+            Node::Comment(comment) => NodeRef::Comment(comment),
+            Node::ProcessingInstruction { target, data } => NodeRef::ProcessingInstruction { target, data },
+            Node::Foreign(foreign) => NodeRef::Foreign(foreign),
+        }
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn as_cdata(&self) -> Option<&'a str> {
+        match *self {
+            NodeRef::CharData(data) => Some(data),
+            _ => None,
+        }
+    }
+    pub fn as_element(&self) -> Option<ElementRef<'a>> {
+        match *self {
+            NodeRef::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+}
+
+/// Two views are equal when they refer to the same element, not when the elements look alike.
+impl<'a> PartialEq for ElementRef<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl<'a> Eq for ElementRef<'a> {}
+
+impl<'a> ElementRef<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.0.name
+    }
+    pub fn is_named<'n>(&self, name: impl Into<NameMatch<'n>>) -> bool {
+        self.0.is_named(name)
+    }
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.0.attributes.get(name).map(String::as_str)
+    }
+    pub fn attributes(&self) -> impl Iterator<Item=(&'a str, &'a str)> {
+        self.0.attributes.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+    pub fn attributes_sorted(&self) -> Vec<(&'a str, &'a str)> {
+        self.0.attributes_sorted()
+    }
+    pub fn text(&self) -> String {
+        self.0.text()
+    }
+    pub fn children(&self) -> impl Iterator<Item=NodeRef<'a>> {
+        self.0.children.iter().map(NodeRef::from)
+    }
+    pub fn child_elements(&self) -> impl Iterator<Item=ElementRef<'a>> {
+        self.0.children.iter().filter_map(Node::as_element).map(ElementRef)
+    }
+    /// Same results as `elem_name` on this element's children, as views.
+    pub fn select<'n>(&self, name: impl Into<NameMatch<'n>>) -> Vec<ElementRef<'a>> {
+        self.select_iter(name).collect()
+    }
+    pub fn select_iter<'n>(&self, name: impl Into<NameMatch<'n>>) -> std::iter::Map<Select<'a, 'n>, fn(&'a Element) -> ElementRef<'a>> {
+        self.0.select_iter(name).map(ElementRef)
+    }
+    /// Same results as `elem_where` on this element's children, as views.
+    pub fn elem_where<F: Fn(ElementRef<'_>) -> bool>(&self, predicate: F) -> Vec<ElementRef<'a>> {
+        views(self.0.children().elem_where(|element| predicate(ElementRef(element))))
+    }
+    /// The child elements named `name` at `position` among them.
+    pub fn child_at<'n>(&self, name: impl Into<NameMatch<'n>>, position: Position) -> Vec<ElementRef<'a>> {
+        views(vec![self.0].child_at(name, position))
+    }
+    /// Views from this element down to `target`, both inclusive, as `Element::path_to`.
+    pub fn path_to(&self, target: ElementRef<'_>) -> Option<Vec<ElementRef<'a>>> {
+        self.0.path_to(target.0).map(views)
+    }
+    pub fn parent_of(&self, target: ElementRef<'_>) -> Option<ElementRef<'a>> {
+        self.0.parent_of(target.0).map(ElementRef)
+    }
+    /// Ancestors of `target`, nearest first.
+    pub fn ancestors_of(&self, target: ElementRef<'_>) -> Vec<ElementRef<'a>> {
+        views(self.0.ancestors_of(target.0))
+    }
+    /// Element siblings after `target`, in document order.
+    pub fn following_siblings_of(&self, target: ElementRef<'_>) -> Vec<ElementRef<'a>> {
+        views(self.0.following_siblings_of(target.0))
+    }
+    /// Element siblings before `target`, nearest first.
+    pub fn preceding_siblings_of(&self, target: ElementRef<'_>) -> Vec<ElementRef<'a>> {
+        views(self.0.preceding_siblings_of(target.0))
+    }
+    /// Every match of `pattern` at or below this element, as `Pattern::find_all`; read the
+    /// captured elements with `Captures::element`.
+    pub fn find_all(&self, pattern: &Pattern) -> Vec<Captures<'a>> {
+        pattern.find_all(self.0)
+    }
+    /// The element an intra-document reference points to, with this element as the root.
+    pub fn resolve_fragment(&self, reference: &str) -> Option<ElementRef<'a>> {
+        Fragment::parse(reference)?.resolve(self.0).map(ElementRef)
+    }
+    /// Copies the viewed subtree into a new owned element.
+    pub fn to_element(&self) -> Element {
+        self.0.clone()
+    }
+}
+
+impl Element {
+    pub fn view(&self) -> ElementRef<'_> {
+        ElementRef(self)
+    }
+}
+
+impl<'a> Captures<'a> {
+    /// The element captured as `name`, as a view.
+    pub fn element(&self, name: &str) -> Option<ElementRef<'a>> {
+        self.elements.get(name).map(|&element| ElementRef(element))
+    }
+}
+
+fn views(elements: Vec<&Element>) -> Vec<ElementRef<'_>> {
+    elements.into_iter().map(ElementRef).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::view::*;
+
+    #[test]
+    fn read_only_views() {
+        let res = Parser::default()
+            .complete_element(r#"<feed lang="en"><item id="1">a</item>text<item id="2"><sub/></item></feed>"#)
+            .unwrap();
+        let view = res.view();
+        assert_eq!(view.name(), "feed");
+        assert_eq!(view.attribute("lang"), Some("en"));
+        let items = view.select("item");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].attribute("id"), Some("2"));
+        assert_eq!(view.children().filter_map(|x| x.as_cdata()).collect::<Vec<_>>(), vec!["text"]);
+        assert_eq!(view.child_elements().count(), 2);
+        assert_eq!(items[0], view.child_elements().next().unwrap());
+        assert_eq!(items[1].to_element().children.len(), 1);
+    }
+
+    #[test]
+    fn view_queries() {
+        let res = Parser::default()
+            .complete_element(r#"<feed><item id="1">a</item><item id="2"><sub/></item><item id="3"/><note/></feed>"#)
+            .unwrap();
+        let view = res.view();
+        let ids = |items: Vec<ElementRef>| items.iter().map(|x| x.attribute("id").unwrap_or_default().to_string()).collect::<Vec<_>>();
+        assert_eq!(ids(view.elem_where(|x| x.attribute("id").is_some_and(|id| id != "1"))), ["2", "3"]);
+        assert_eq!(ids(view.child_at("item", Position::Last)), ["3"]);
+        let sub = view.select("sub")[0];
+        let second = view.parent_of(sub).unwrap();
+        assert_eq!(ids(view.path_to(sub).unwrap()), ["", "2", ""]);
+        assert_eq!(view.ancestors_of(sub), [second, view]);
+        assert_eq!(ids(view.following_siblings_of(second)), ["3", ""]);
+        assert_eq!(ids(view.preceding_siblings_of(second)), ["1"]);
+        assert_eq!(view.resolve_fragment("#3"), Some(view.select("item")[2]));
+        let found = view.find_all(&pattern::Pattern::element("item").capture("it").child(pattern::Pattern::element("sub")));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].element("it"), Some(second));
+    }
+}
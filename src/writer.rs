@@ -0,0 +1,477 @@
+use std::borrow::Cow;
+use std::io;
+
+use crate::{doctype_body, Document, Element, NameToken, Node};
+use crate::reader::Event;
+use crate::tree::{XmlElement, XmlNode};
+
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            ch => escaped.push(ch),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Escapes a value for use inside double quotes, keeping literal tabs and newlines intact
+/// through attribute value normalization by encoding them as character references.
+pub fn escape_attribute(value: &str) -> Cow<'_, str> {
+    if !value.contains(['&', '<', '"', '\t', '\n', '\r']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            ch => escaped.push(ch),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// What the writer does with characters XML 1.0 does not allow at all, even escaped: C0
+/// controls other than tab, newline and carriage return, and U+FFFE/U+FFFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlChars {
+    /// Fail the write with `InvalidInput`.
+    #[default]
+    Reject,
+    /// Write U+FFFD in their place.
+    Replace,
+    /// Drop them, checking what is left as if it had been written that way.
+    Strip,
+}
+
+pub(crate) fn is_forbidden(ch: char) -> bool {
+    (ch < ' ' && !matches!(ch, '\t' | '\n' | '\r')) || ch == '\u{fffe}' || ch == '\u{ffff}'
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Streaming XML writer. A start tag is left open until the next write so that an element
+/// without content can be emitted as `<name/>`.
+///
+/// Anything that cannot be written as well-formed XML (invalid names, duplicate attributes,
+/// `--` in a comment, `?>` in a processing instruction, forbidden control characters) fails
+/// with `InvalidInput` before any of it is written.
+pub struct Writer<W: io::Write> {
+    inner: W,
+    open: Vec<String>,
+    start_open: bool,
+    control_chars: ControlChars,
+}
+
+impl<W: io::Write> Writer<W> {
+    pub fn new(inner: W) -> Writer<W> {
+        Writer {
+            inner,
+            open: vec![],
+            start_open: false,
+            control_chars: ControlChars::default(),
+        }
+    }
+
+    pub fn control_chars(mut self, policy: ControlChars) -> Writer<W> {
+        self.control_chars = policy;
+        self
+    }
+
+    fn clean<'t>(&self, text: &'t str) -> io::Result<Cow<'t, str>> {
+        if !text.contains(is_forbidden) {
+            return Ok(Cow::Borrowed(text));
+        }
+        match self.control_chars {
+            ControlChars::Reject => Err(invalid(format!("forbidden character in {:?}", text))),
+            ControlChars::Replace => Ok(Cow::Owned(text.replace(is_forbidden, "\u{fffd}"))),
+            ControlChars::Strip => Ok(Cow::Owned(text.replace(is_forbidden, ""))),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    fn finish_start(&mut self) -> io::Result<()> {
+        if self.start_open {
+            self.start_open = false;
+            self.inner.write_all(b">")?;
+        }
+        Ok(())
+    }
+
+    pub fn declaration(&mut self, version: i32, encoding: Option<&str>) -> io::Result<()> {
+        self.finish_start()?;
+        write!(self.inner, "<?xml version=\"1.{}\"", version)?;
+        if let Some(encoding) = encoding {
+            write!(self.inner, " encoding=\"{}\"", escape_attribute(encoding))?;
+        }
+        self.inner.write_all(b"?>")
+    }
+
+    /// Writes `<!DOCTYPE` and `body` as given, which must read back the same: a `>` only
+    /// inside quotes or the internal subset, and every quote and bracket closed.
+    pub fn doctype(&mut self, body: &str) -> io::Result<()> {
+        let body = self.clean(body)?;
+        let closed = format!("{}>", body);
+        if !matches!(doctype_body(&closed), Ok(("", read)) if read == body) {
+            return Err(invalid(format!("malformed doctype {:?}", body)));
+        }
+        self.finish_start()?;
+        write!(self.inner, "<!DOCTYPE{}>", body)
+    }
+
+    pub fn start<I, K, V>(&mut self, name: &str, attributes: I) -> io::Result<()>
+        where I: IntoIterator<Item=(K, V)>, K: AsRef<str>, V: AsRef<str> {
+        NameToken::new(name).map_err(|e| invalid(e.to_string()))?;
+        let mut checked: Vec<(K, String)> = vec![];
+        for (key, value) in attributes {
+            NameToken::new(key.as_ref()).map_err(|e| invalid(e.to_string()))?;
+            if checked.iter().any(|(x, _)| x.as_ref() == key.as_ref()) {
+                return Err(invalid(format!("duplicate attribute {:?}", key.as_ref())));
+            }
+            let value = escape_attribute(&self.clean(value.as_ref())?).into_owned();
+            checked.push((key, value));
+        }
+        self.finish_start()?;
+        write!(self.inner, "<{}", name)?;
+        for (key, value) in checked {
+            write!(self.inner, " {}=\"{}\"", key.as_ref(), value)?;
+        }
+        self.open.push(name.to_string());
+        self.start_open = true;
+        Ok(())
+    }
+
+    pub fn end(&mut self) -> io::Result<()> {
+        let name = self.open.pop()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no open element to end"))?;
+        if self.start_open {
+            self.start_open = false;
+            self.inner.write_all(b"/>")
+        } else {
+            write!(self.inner, "</{}>", name)
+        }
+    }
+
+    pub fn text(&mut self, text: &str) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let text = self.clean(text)?;
+        self.finish_start()?;
+        self.inner.write_all(escape_text(&text).as_bytes())
+    }
+
+    pub fn cdata(&mut self, text: &str) -> io::Result<()> {
+        let text = self.clean(text)?;
+        self.finish_start()?;
+        write!(self.inner, "<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+    }
+
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        let text = self.clean(text)?;
+        if text.contains("--") || text.ends_with('-') {
+            return Err(invalid(format!("comment {:?} contains \"--\" or ends with \"-\"", text)));
+        }
+        self.finish_start()?;
+        write!(self.inner, "<!--{}-->", text)
+    }
+
+    pub fn processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        NameToken::new(target).map_err(|e| invalid(e.to_string()))?;
+        let data = self.clean(data)?;
+        if target.eq_ignore_ascii_case("xml") || data.contains("?>") {
+            return Err(invalid(format!("malformed processing instruction {:?} {:?}", target, data)));
+        }
+        self.finish_start()?;
+        if data.is_empty() {
+            write!(self.inner, "<?{}?>", target)
+        } else {
+            write!(self.inner, "<?{} {}?>", target, data)
+        }
+    }
+
+    /// Writes markup exactly as given, such as the source of a `Node::Foreign`; nothing is
+    /// checked or escaped.
+    pub fn raw(&mut self, markup: &str) -> io::Result<()> {
+        self.finish_start()?;
+        self.inner.write_all(markup.as_bytes())
+    }
+
+    /// Writes an event. `End` always closes the innermost open element regardless of its name.
+    pub fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::Declaration { version, encoding } => self.declaration(*version, encoding.as_deref()),
+            Event::Doctype(body) => self.doctype(body),
+            Event::Start { name, attributes } => self.start(name, attributes.iter().map(|(k, v)| (k, v))),
+            Event::End { .. } => self.end(),
+            Event::Text(text) => self.text(text),
+            Event::CData(text) => self.cdata(text),
+            Event::Comment(text) => self.comment(text),
+            Event::ProcessingInstruction { target, data } => self.processing_instruction(target, data),
+            Event::Foreign(foreign) => self.raw(foreign.source()),
+        }
+    }
+
+    /// Writes a whole subtree, with attributes in sorted order.
+    pub fn write_element(&mut self, element: &Element) -> io::Result<()> {
+        self.start(&element.name, element.attributes_sorted())?;
+        for child in &element.children {
+            match child {
+                Node::CharData(data) => self.text(data)?,
+                Node::Element(child) => self.write_element(child)?,
+                Node::Comment(text) => self.comment(text)?,
+                Node::ProcessingInstruction { target, data } => self.processing_instruction(target, data)?,
+                Node::Foreign(foreign) => self.raw(foreign.source())?,
+            }
+        }
+        self.end()
+    }
+
+    /// `write_element` for any tree representation.
+    pub fn write_tree<'a, E: XmlElement<'a>>(&mut self, element: E) -> io::Result<()> {
+        self.start(element.name(), element.attributes_sorted())?;
+        for child in element.children() {
+            match child {
+                XmlNode::CharData(data) => self.text(&data)?,
+                XmlNode::Element(child) => self.write_tree(child)?,
+                XmlNode::Comment(text) => self.comment(text)?,
+                XmlNode::ProcessingInstruction { target, data } => self.processing_instruction(target, data)?,
+                XmlNode::Foreign(foreign) => self.raw(foreign.source())?,
+            }
+        }
+        self.end()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.finish_start()?;
+        self.inner.flush()
+    }
+}
+
+/// Streams `elements` (or documents, via their root) out one after another inside a wrapper root.
+pub fn join<W, I>(writer: &mut Writer<W>, root: &str, attributes: &[(&str, &str)], elements: I) -> io::Result<()>
+    where W: io::Write, I: IntoIterator, I::Item: AsRef<Element> {
+    writer.start(root, attributes.iter().copied())?;
+    for element in elements {
+        writer.write_element(element.as_ref())?;
+    }
+    writer.end()?;
+    writer.flush()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Newline {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl Newline {
+    fn as_str(self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Writes trees indented for people to read and edit, two spaces per level by default. As with
+/// `TreeBuilder::indent`, only elements whose content is elements, comments and processing
+/// instructions are indented, and the whitespace already between their children is replaced;
+/// an element holding text is written as it is, since added whitespace would change it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyPrinter {
+    unit: String,
+    newline: Newline,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> PrettyPrinter {
+        PrettyPrinter { unit: "  ".to_string(), newline: Newline::default() }
+    }
+}
+
+fn is_structural(element: &Element) -> bool {
+    element.children.iter().all(|node| !matches!(node, Node::CharData(data) if !data.trim().is_empty()))
+}
+
+impl PrettyPrinter {
+    pub fn new() -> PrettyPrinter {
+        PrettyPrinter::default()
+    }
+    pub fn spaces(mut self, width: usize) -> PrettyPrinter {
+        self.unit = " ".repeat(width);
+        self
+    }
+    pub fn tabs(mut self) -> PrettyPrinter {
+        self.unit = "\t".to_string();
+        self
+    }
+    pub fn newline(mut self, newline: Newline) -> PrettyPrinter {
+        self.newline = newline;
+        self
+    }
+
+    pub fn write_element<W: io::Write>(&self, w: W, element: &Element) -> io::Result<()> {
+        let mut writer = Writer::new(w);
+        self.element(&mut writer, element, 0)?;
+        writer.flush()
+    }
+
+    /// The declaration and the root on lines of their own, ending with a newline.
+    pub fn write_document<W: io::Write>(&self, w: W, document: &Document) -> io::Result<()> {
+        let mut writer = Writer::new(w);
+        writer.declaration(document.version(), document.encoding())?;
+        writer.raw(self.newline.as_str())?;
+        self.element(&mut writer, document.root(), 0)?;
+        writer.raw(self.newline.as_str())?;
+        writer.flush()
+    }
+
+    pub fn element_to_string(&self, element: &Element) -> io::Result<String> {
+        let mut out = vec![];
+        self.write_element(&mut out, element)?;
+        Ok(String::from_utf8(out).expect("the writer writes UTF-8"))
+    }
+    pub fn document_to_string(&self, document: &Document) -> io::Result<String> {
+        let mut out = vec![];
+        self.write_document(&mut out, document)?;
+        Ok(String::from_utf8(out).expect("the writer writes UTF-8"))
+    }
+
+    fn element<W: io::Write>(&self, writer: &mut Writer<W>, element: &Element, depth: usize) -> io::Result<()> {
+        if !is_structural(element) {
+            return writer.write_element(element);
+        }
+        writer.start(&element.name, element.attributes_sorted())?;
+        let mut any = false;
+        for child in &element.children {
+            if let Node::CharData(_) = child {
+                continue;
+            }
+            writer.raw(&format!("{}{}", self.newline.as_str(), self.unit.repeat(depth + 1)))?;
+            any = true;
+            match child {
+                Node::Element(child) => self.element(writer, child, depth + 1)?,
+                Node::Comment(text) => writer.comment(text)?,
+                Node::ProcessingInstruction { target, data } => writer.processing_instruction(target, data)?,
+                Node::Foreign(foreign) => writer.raw(foreign.source())?,
+                Node::CharData(_) => {}
+            }
+        }
+        if any {
+            writer.raw(&format!("{}{}", self.newline.as_str(), self.unit.repeat(depth)))?;
+        }
+        writer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::writer::*;
+
+    #[test]
+    fn streaming_writer() {
+        let mut writer = Writer::new(vec![]);
+        writer.declaration(0, Some("UTF-8")).unwrap();
+        writer.start("root", vec![("a", "x \"y\" & <z>")]).unwrap();
+        writer.start("empty", Vec::<(&str, &str)>::new()).unwrap();
+        writer.end().unwrap();
+        writer.text("1 < 2 & 3 > 2").unwrap();
+        writer.cdata("a]]>b").unwrap();
+        writer.comment(" c ").unwrap();
+        writer.processing_instruction("pi", "").unwrap();
+        writer.end().unwrap();
+        assert!(writer.end().is_err());
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?><root a="x &quot;y&quot; &amp; &lt;z>"><empty/>1 &lt; 2 &amp; 3 &gt; 2<![CDATA[a]]]]><![CDATA[>b]]><!-- c --><?pi?></root>"#
+        );
+    }
+
+    #[test]
+    fn writer_validation() {
+        let mut writer = Writer::new(vec![]);
+        assert!(writer.start("two words", Vec::<(&str, &str)>::new()).is_err());
+        assert!(writer.start("a", vec![("x", "1"), ("x", "2")]).is_err());
+        assert!(writer.start("a", vec![("bad name", "1")]).is_err());
+        assert!(writer.start("a", vec![("x", "bell\u{7}")]).is_err());
+        assert!(writer.comment("a -- b").is_err());
+        assert!(writer.processing_instruction("xml", "").is_err());
+        assert!(writer.processing_instruction("pi", "?>").is_err());
+        assert!(writer.text("\u{0}").is_err());
+        assert!(writer.doctype(" x]><evil/>").is_err());
+        assert!(writer.doctype(" x SYSTEM \"a>b").is_err());
+        assert!(writer.doctype(" x [<!ENTITY e 'v'>").is_err());
+        assert!(writer.doctype(" x\u{1}").is_err());
+        assert!(writer.into_inner().is_empty());
+        let mut writer = Writer::new(vec![]);
+        writer.doctype(" x SYSTEM \"a>b\" [<!ENTITY e '>'><!-- ]> -->]").unwrap();
+        assert_eq!(writer.into_inner(), b"<!DOCTYPE x SYSTEM \"a>b\" [<!ENTITY e '>'><!-- ]> -->]>");
+        let mut writer = Writer::new(vec![]).control_chars(ControlChars::Replace);
+        writer.start("a", vec![("x", "bell\u{7}")]).unwrap();
+        writer.text("nul\u{0}").unwrap();
+        writer.end().unwrap();
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), "<a x=\"bell\u{fffd}\">nul\u{fffd}</a>");
+        let mut writer = Writer::new(vec![]).control_chars(ControlChars::Strip);
+        assert!(writer.comment("a-\u{0}-b").is_err());
+        assert!(writer.processing_instruction("pi", "x?\u{1}>y").is_err());
+        writer.comment("a\u{0}b").unwrap();
+        assert_eq!(writer.into_inner(), b"<!--ab-->");
+    }
+
+    #[test]
+    fn join_elements() {
+        let parser = Parser::default();
+        let a = parser.complete_document(r#"<?xml version="1.0"?><item id="1" b="2">one</item>"#).unwrap();
+        let b = parser.complete_element("<item><x/></item>").unwrap();
+        let mut writer = Writer::new(vec![]);
+        join(&mut writer, "batch", &[("count", "2")], vec![a.root, b]).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            r#"<batch count="2"><item b="2" id="1">one</item><item><x/></item></batch>"#
+        );
+    }
+
+    #[test]
+    fn pretty_printing() {
+        let parser = Parser::default();
+        let document = parser.complete_document(concat!(
+            r#"<?xml version="1.0"?><config>  <server port="80"><host>a</host><!--tls later--></server>"#,
+            "<note>mixed <b>x</b></note><empty/></config>",
+        )).unwrap();
+        assert_eq!(PrettyPrinter::new().document_to_string(&document).unwrap(), concat!(
+            "<?xml version=\"1.0\"?>\n<config>\n  <server port=\"80\">\n    <host>a</host>\n    <!--tls later-->\n  </server>\n",
+            "  <note>mixed <b>x</b></note>\n  <empty/>\n</config>\n",
+        ));
+        let server = document.root().children().elem_name("server")[0];
+        assert_eq!(
+            PrettyPrinter::new().tabs().newline(Newline::CrLf).element_to_string(server).unwrap(),
+            "<server port=\"80\">\r\n\t<host>a</host>\r\n\t<!--tls later-->\r\n</server>",
+        );
+        assert_eq!(server.to_pretty_string(4).unwrap(), "<server port=\"80\">\n    <host>a</host>\n    <!--tls later-->\n</server>");
+        let pretty = PrettyPrinter::new().element_to_string(document.root()).unwrap();
+        assert_eq!(parser.complete_element(&pretty).unwrap(), *document.root());
+    }
+}
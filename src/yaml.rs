@@ -0,0 +1,96 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{self, ConfigValue, ConvertError, Shape};
+use crate::Element;
+
+impl ConfigValue for Value {
+    fn null() -> Option<Value> {
+        Some(Value::Null)
+    }
+    fn string(text: String) -> Value {
+        Value::String(text)
+    }
+    fn list(values: Vec<Value>) -> Value {
+        Value::Sequence(values)
+    }
+    fn map(entries: Vec<(String, Value)>) -> Value {
+        Value::Mapping(entries.into_iter().map(|(key, value)| (Value::String(key), value)).collect::<Mapping>())
+    }
+    fn shape(&self) -> Result<Shape<'_, Value>, ConvertError> {
+        Ok(match self {
+            Value::Null => Shape::Null,
+            Value::Bool(value) => Shape::Text(value.to_string()),
+            Value::Number(value) => Shape::Text(value.to_string()),
+            Value::String(text) => Shape::Text(text.clone()),
+            Value::Sequence(values) => Shape::List(values.iter().collect()),
+            Value::Mapping(entries) => Shape::Map(entries.iter()
+                .map(|(key, value)| match key.shape()? {
+                    Shape::Text(key) => Ok((key, value)),
+                    _ => Err(ConvertError::new("a YAML key must be a scalar")),
+                })
+                .collect::<Result<_, _>>()?),
+            Value::Tagged(tagged) => tagged.value.shape()?,
+        })
+    }
+}
+
+/// `element` as a YAML value: see `json::to_json` for the mapping. An `xsi:nil` element is
+/// null. The root's own name is not kept; `from_yaml_value` takes it back as an argument.
+pub fn to_yaml_value(element: &Element) -> Value {
+    config::from_element(element, false)
+}
+
+/// The element named `root` that `value` maps back to. Numbers and booleans become their text,
+/// null an `xsi:nil` element, and tags are ignored.
+pub fn from_yaml_value(value: &Value, root: &str) -> Result<Element, ConvertError> {
+    config::to_element(value, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::yaml::*;
+
+    #[test]
+    fn yaml_round_trip() {
+        let res = Parser::default()
+            .complete_element(r#"<config version="2"><name>web: main</name><server port="80"><host>a</host></server><server port="81"/><note>line
+two</note><debug>yes</debug><proxy xsi:nil="true"/></config>"#)
+            .unwrap();
+        let value = to_yaml_value(&res);
+        assert_eq!(serde_yaml::to_string(&value).unwrap(), concat!(
+            "'@version': '2'\n",
+            "name: 'web: main'\n",
+            "server:\n",
+            "- '@port': '80'\n",
+            "  host: a\n",
+            "- '@port': '81'\n",
+            "note: |-\n",
+            "  line\n",
+            "  two\n",
+            "debug: yes\n",
+            "proxy: null\n",
+        ));
+        assert_eq!(from_yaml_value(&value, "config").unwrap(), res);
+    }
+
+    #[test]
+    fn yaml_input() {
+        let input = "name: it's\nports: [80, \"443\"]\nlimits: {cpu: 2.5, mem: }\nusers:\n- id: 1\n  admin: true\n- !user {id: 2}\n";
+        let value: serde_yaml::Value = serde_yaml::from_str(input).unwrap();
+        let res = from_yaml_value(&value, "service").unwrap();
+        let text = |path: &str| res.children().elem_name(path).iter().map(|x| x.text()).collect::<Vec<_>>();
+        assert_eq!(text("name"), ["it's"]);
+        assert_eq!(text("ports"), ["80", "443"]);
+        let limits = res.children().elem_name("limits")[0];
+        assert_eq!(limits.children().elem_name("cpu")[0].text(), "2.5");
+        assert!(limits.children().elem_name("mem")[0].is_nil());
+        assert_eq!(text("users"), ["1true", "2"]);
+
+        let invalid = |input: &str| from_yaml_value(&serde_yaml::from_str(input).unwrap(), "x").unwrap_err().to_string();
+        assert_eq!(invalid("[1, 2]"), "a list cannot be the single element x");
+        assert_eq!(invalid("a: {b: 1}\n\"@c\": [1]\n"), "@c of x is not a string");
+        assert_eq!(invalid("[1]: 2\n"), "a YAML key must be a scalar");
+        assert!(from_yaml_value(&serde_yaml::from_str("two words: 1").unwrap(), "x").is_err());
+    }
+}